@@ -0,0 +1,73 @@
+//! Ed25519 program instruction parsing shared by the on-chain programs.
+//!
+//! Programs can't verify signatures themselves. The Ed25519 native program
+//! checks them in its own instruction of the same transaction, which fails
+//! the whole transaction if a signature is invalid. A program reads that
+//! instruction back through the instructions sysvar and uses
+//! [`parse_ed25519_instruction`] to see which signer and message it checked,
+//! mapping a `None` onto its own error type.
+
+/// Pulls the single (public key, message) pair out of Ed25519 program
+/// instruction data, provided both live in that same instruction.
+pub fn parse_ed25519_instruction(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    // [num_signatures, padding, 7 x u16 offsets]
+    if data.len() < 16 || data[0] != 1 {
+        return None;
+    }
+    let offset = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+    let [_, signature_ix, public_key_offset, public_key_ix, message_offset, message_size, message_ix] =
+        [0, 1, 2, 3, 4, 5, 6].map(offset);
+    if [signature_ix, public_key_ix, message_ix].iter().any(|ix| *ix != u16::MAX) {
+        return None;
+    }
+
+    let public_key = data.get(public_key_offset as usize..public_key_offset as usize + 32)?;
+    let message = data.get(message_offset as usize..message_offset as usize + message_size as usize)?;
+    Some((public_key, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ed25519 program data laid out like web3.js `Ed25519Program.createInstructionWithPublicKey`.
+    fn ed25519_ix_data(public_key: &[u8; 32], message: &[u8], message_ix: u16) -> Vec<u8> {
+        let (public_key_offset, signature_offset, message_offset) = (16u16, 48u16, 112u16);
+        let mut data = vec![1, 0];
+        for value in [
+            signature_offset, u16::MAX,
+            public_key_offset, u16::MAX,
+            message_offset, message.len() as u16, message_ix,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(public_key);
+        data.extend_from_slice(&[0u8; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn test_parse_ed25519_instruction() {
+        let signer = [7u8; 32];
+        let message = b"domain:some signed terms".to_vec();
+
+        let data = ed25519_ix_data(&signer, &message, u16::MAX);
+        let (public_key, signed) = parse_ed25519_instruction(&data).unwrap();
+        assert_eq!(public_key, &signer[..]);
+        assert_eq!(signed, &message[..]);
+
+        // A message living in some other instruction can't be trusted
+        assert!(parse_ed25519_instruction(&ed25519_ix_data(&signer, &message, 0)).is_none());
+        // Truncated data
+        assert!(parse_ed25519_instruction(&data[..100]).is_none());
+        assert!(parse_ed25519_instruction(&data[..15]).is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_multiple_signatures() {
+        let mut data = ed25519_ix_data(&[7u8; 32], b"message", u16::MAX);
+        data[0] = 2;
+        assert!(parse_ed25519_instruction(&data).is_none());
+    }
+}
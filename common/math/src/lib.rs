@@ -0,0 +1,97 @@
+//! Checked integer math shared by the on-chain programs.
+//!
+//! Every helper returns `None` instead of overflowing or dividing by zero, so
+//! each program can map failures onto its own error type. Products are taken
+//! in `u128`, and the floor/ceil variants let callers pick the rounding
+//! direction on purpose: fees a protocol keeps round up, amounts it pays out
+//! round down.
+
+/// `a * b / denominator`, rounded down.
+pub fn mul_div_floor(a: u64, b: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    to_u64(a as u128 * b as u128 / denominator as u128)
+}
+
+/// `a * b / denominator`, rounded up.
+pub fn mul_div_ceil(a: u64, b: u64, denominator: u64) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    to_u64((a as u128 * b as u128).div_ceil(denominator as u128))
+}
+
+/// Largest integer whose square is at most `n`.
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from an estimate at or above the root converges downward
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let next = (x + n / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+/// Narrows `value` to `u64`, or `None` if it doesn't fit.
+pub fn to_u64(value: u128) -> Option<u64> {
+    u64::try_from(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div_rounding_direction() {
+        // Exact divisions agree
+        assert_eq!(mul_div_floor(1_000, 250, 10_000), Some(25));
+        assert_eq!(mul_div_ceil(1_000, 250, 10_000), Some(25));
+        assert_eq!(mul_div_floor(0, 250, 10_000), Some(0));
+        assert_eq!(mul_div_ceil(0, 250, 10_000), Some(0));
+
+        // Inexact divisions differ by one
+        assert_eq!(mul_div_floor(999, 250, 10_000), Some(24));
+        assert_eq!(mul_div_ceil(999, 250, 10_000), Some(25));
+        assert_eq!(mul_div_floor(1, 1, 10_000), Some(0));
+        assert_eq!(mul_div_ceil(1, 1, 10_000), Some(1));
+    }
+
+    #[test]
+    fn test_mul_div_overflow_and_zero_denominator() {
+        // The product may exceed u64 as long as the quotient fits
+        assert_eq!(mul_div_floor(u64::MAX, u64::MAX, u64::MAX), Some(u64::MAX));
+        assert_eq!(mul_div_ceil(u64::MAX, 3, 4), Some(u64::MAX / 4 * 3 + 3));
+        assert_eq!(mul_div_floor(u64::MAX, 2, 1), None);
+        assert_eq!(mul_div_ceil(u64::MAX, 2, 1), None);
+
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+        assert_eq!(mul_div_ceil(1, 1, 0), None);
+    }
+
+    #[test]
+    fn test_isqrt_floors() {
+        for (n, root) in [(0, 0), (1, 1), (2, 1), (3, 1), (4, 2), (15, 3), (16, 4), (17, 4), (1_000_000, 1_000)] {
+            assert_eq!(isqrt(n), root);
+        }
+        // sqrt(1e9 * 4e9) is exactly 2e9
+        assert_eq!(isqrt(4_000_000_000_000_000_000), 2_000_000_000);
+
+        // Large inputs, where f64 loses precision
+        let big = u64::MAX as u128;
+        assert_eq!(isqrt(big * big), big);
+        assert_eq!(isqrt(big * big - 1), big - 1);
+        assert_eq!(isqrt(u128::MAX), big);
+    }
+
+    #[test]
+    fn test_checked_narrowing() {
+        assert_eq!(to_u64(u64::MAX as u128), Some(u64::MAX));
+        assert_eq!(to_u64(u64::MAX as u128 + 1), None);
+    }
+}
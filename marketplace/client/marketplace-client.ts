@@ -8,9 +8,9 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program, AnchorProvider, Wallet } from "@coral-xyz/anchor";
 import { Connection, PublicKey, Keypair, Transaction } from "@solana/web3.js";
 import { TOKEN_PROGRAM_ID, getAssociatedTokenAddress } from "@solana/spl-token";
-import { findMetadataPda, findMasterEditionPda } from "@metaplex-foundation/mpl-token-metadata";
+import { fetchMetadata, findMetadataPda, findMasterEditionPda } from "@metaplex-foundation/mpl-token-metadata";
 import { createUmi } from "@metaplex-foundation/umi-bundle-defaults";
-import { publicKey } from "@metaplex-foundation/umi";
+import { publicKey, unwrapOption } from "@metaplex-foundation/umi";
 import { Marketplace } from "../target/types/marketplace";
 
 export interface MarketplaceConfig {
@@ -23,6 +23,7 @@ export interface ListingData {
   maker: PublicKey;
   makerMint: PublicKey;
   price: anchor.BN;
+  allowedBuyer: PublicKey | null;
   bump: number;
 }
 
@@ -33,6 +34,11 @@ export interface MarketplaceData {
   treasuryBump: number;
   rewardsBump: number;
   name: string;
+  totalVolume: anchor.BN;
+  totalSales: anchor.BN;
+  requireVerifiedCreator: boolean;
+  listingFee: anchor.BN;
+  enforceRoyalties: boolean;
 }
 
 export class MarketplaceClient {
@@ -100,14 +106,17 @@ export class MarketplaceClient {
   async initializeMarketplace(
     admin: Keypair,
     name: string,
-    fee: number
+    fee: number,
+    requireVerifiedCreator = false,
+    listingFee = new anchor.BN(0),
+    enforceRoyalties = true
   ): Promise<string> {
     const [marketplace] = this.getMarketplacePDA(name);
     const [treasury] = this.getTreasuryPDA(marketplace);
     const [rewardMint] = this.getRewardsMintPDA(marketplace);
 
     return await this.program.methods
-      .initialize(name, fee)
+      .initialize(name, fee, requireVerifiedCreator, listingFee, enforceRoyalties)
       .accountsPartial({
         admin: admin.publicKey,
         marketplace,
@@ -128,10 +137,12 @@ export class MarketplaceClient {
     marketplaceName: string,
     nftMint: PublicKey,
     collectionMint: PublicKey,
-    price: anchor.BN
+    price: anchor.BN,
+    allowedBuyer: PublicKey | null = null
   ): Promise<string> {
     const [marketplace] = this.getMarketplacePDA(marketplaceName);
     const [listing] = this.getListingPDA(marketplace, nftMint);
+    const [treasury] = this.getTreasuryPDA(marketplace);
     
     const makerAta = await getAssociatedTokenAddress(nftMint, maker.publicKey);
     const vault = await getAssociatedTokenAddress(nftMint, listing, true);
@@ -142,10 +153,11 @@ export class MarketplaceClient {
     const masterEdition = findMasterEditionPda(umi, { mint: publicKey(nftMint) });
 
     return await this.program.methods
-      .listing(price)
+      .listing(price, allowedBuyer)
       .accountsPartial({
         maker: maker.publicKey,
         marketplace,
+        treasury,
         makerMint: nftMint,
         makerAta,
         vault,
@@ -186,6 +198,12 @@ export class MarketplaceClient {
     const nftMetadata = findMetadataPda(umi, { mint: publicKey(nftMint) });
     const masterEdition = findMasterEditionPda(umi, { mint: publicKey(nftMint) });
 
+    // Creators are paid in metadata order when the marketplace enforces royalties
+    const { enforceRoyalties } = await this.program.account.marketplace.fetch(marketplace);
+    const creators = enforceRoyalties
+      ? unwrapOption((await fetchMetadata(umi, nftMetadata)).creators) ?? []
+      : [];
+
     return await this.program.methods
       .purchase()
       .accountsPartial({
@@ -207,6 +225,11 @@ export class MarketplaceClient {
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
+      .remainingAccounts(creators.map(creator => ({
+        pubkey: new PublicKey(creator.address),
+        isWritable: true,
+        isSigner: false,
+      })))
       .signers([taker])
       .rpc();
   }
@@ -225,6 +248,11 @@ export class MarketplaceClient {
     const makerAta = await getAssociatedTokenAddress(nftMint, maker.publicKey);
     const vault = await getAssociatedTokenAddress(nftMint, listing, true);
 
+    // Get metadata and master edition PDAs
+    const umi = this.umi;
+    const nftMetadata = findMetadataPda(umi, { mint: publicKey(nftMint) });
+    const masterEdition = findMasterEditionPda(umi, { mint: publicKey(nftMint) });
+
     return await this.program.methods
       .delist()
       .accountsPartial({
@@ -234,6 +262,9 @@ export class MarketplaceClient {
         makerAta,
         vault,
         listing,
+        metadata: new PublicKey(nftMetadata[0]),
+        masterEdition: new PublicKey(masterEdition[0]),
+        metadataProgram: new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"),
         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -248,12 +279,15 @@ export class MarketplaceClient {
   async updateMarketplace(
     admin: Keypair,
     marketplaceName: string,
-    newFee?: number
+    newFee?: number,
+    requireVerifiedCreator?: boolean,
+    listingFee?: anchor.BN,
+    enforceRoyalties?: boolean
   ): Promise<string> {
     const [marketplace] = this.getMarketplacePDA(marketplaceName);
 
     return await this.program.methods
-      .updateMarketplace(newFee || null)
+      .updateMarketplace(newFee || null, requireVerifiedCreator ?? null, listingFee ?? null, enforceRoyalties ?? null)
       .accountsPartial({
         admin: admin.publicKey,
         marketplace,
@@ -0,0 +1,34 @@
+/**
+ * Cancel Offer Context
+ * 
+ * Withdraws an outstanding offer. Closing the offer PDA returns the escrowed
+ * bid and the rent to the bidder.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{Marketplace, Offer};
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    /// The bidder who made the offer
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// The marketplace the offer was made on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The offer PDA, closed back to the bidder
+    #[account(
+        mut,
+        seeds = [b"offer", marketplace.key().as_ref(), offer.maker_mint.as_ref(), bidder.key().as_ref()],
+        bump = offer.bump,
+        has_one = bidder,
+        close = bidder,
+    )]
+    pub offer: Account<'info, Offer>,
+}
@@ -0,0 +1,49 @@
+/**
+ * Confirm Receipt Context
+ * 
+ * Lets the buyer of a protected sale release the escrowed proceeds to the
+ * maker before the dispute window ends. The escrow's rent goes back to the
+ * buyer.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{Marketplace, SaleEscrow};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct ConfirmReceipt<'info> {
+    /// The buyer confirming the sale
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// The seller receiving the proceeds
+    /// CHECK: Verified against the sale escrow
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    /// The marketplace the sale happened on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The sale escrow PDA, closed back to the buyer
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), sale_escrow.maker_mint.as_ref(), buyer.key().as_ref()],
+        bump = sale_escrow.bump,
+        has_one = maker @ MarketplaceError::InvalidMaker,
+        close = buyer,
+    )]
+    pub sale_escrow: Account<'info, SaleEscrow>,
+}
+
+impl<'info> ConfirmReceipt<'info> {
+    /// Pay the maker now; a disputed sale can only be settled by the admin
+    pub fn confirm(&mut self) -> Result<()> {
+        self.sale_escrow.ensure_undisputed()?;
+        SaleEscrow::pay_out(&self.sale_escrow, &self.maker)
+    }
+}
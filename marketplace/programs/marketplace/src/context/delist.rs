@@ -2,18 +2,22 @@
  * Delist NFT Context
  * 
  * Removes an NFT listing and returns the NFT to the original owner.
- * Closes the vault and listing accounts.
+ * Closes the vault and listing accounts and updates the collection's
+ * floor price. Programmable NFTs are moved through Token Metadata
+ * (see `pnft.rs`).
  */
 
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
+    metadata::{MasterEditionAccount, Metadata, MetadataAccount},
     token::{close_account, transfer_checked, CloseAccount, TransferChecked},
     token_interface::{Mint, TokenAccount, TokenInterface}
 };
 
-use crate::state::{Listing, Marketplace};
+use crate::state::{CollectionStats, Listing, Marketplace};
 use crate::error::MarketplaceError;
+use crate::pnft::{is_programmable, optional_info, PnftTransfer};
 
 #[derive(Accounts)]
 pub struct Delist<'info> {
@@ -51,15 +55,79 @@ pub struct Delist<'info> {
     )]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The PDA for the listing, which will be closed on delisting
+    /// The PDA for the listing, which will be closed on delisting. An English
+    /// auction can't be pulled from under its highest bidder.
     #[account(
         mut,
         seeds = [marketplace.key().as_ref(), maker_mint.key().as_ref()],
         bump = listing.bump,
+        constraint = !listing.has_bids() @ MarketplaceError::AuctionHasBids,
         close = maker,
     )]
     pub listing: Account<'info, Listing>,
 
+    /// Floor price tracking for the listing's collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Next-cheapest listing of the collection, taking over the floor if this listing held it
+    pub floor_candidate: Option<Account<'info, Listing>>,
+
+    /// NFT metadata, used to detect pNFTs (written by pNFT transfers)
+    #[account(
+        mut,
+        seeds = [
+            b"metadata",
+            metadata_program.key().as_ref(),
+            maker_mint.key().as_ref(),
+        ],
+        seeds::program = metadata_program.key(),
+        bump,
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    /// Master edition of the NFT
+    #[account(
+        seeds = [
+            b"metadata", 
+            metadata_program.key().as_ref(),
+            maker_mint.key().as_ref(),
+            b"edition"
+        ],
+        seeds::program = metadata_program.key(),
+        bump,
+    )]
+    pub master_edition: Account<'info, MasterEditionAccount>,
+
+    /// Token record for the NFT's current token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Token record for the destination token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Rule set attached to the pNFT, if any
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// Token Auth Rules program, if the pNFT has a rule set
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// Instructions sysvar (pNFTs only)
+    /// CHECK: Address is checked against the sysvar ID
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// Metaplex program
+    pub metadata_program: Program<'info, Metadata>,
     /// For creating ATAs
     pub associated_token_program: Program<'info, AssociatedToken>,
     /// For creating accounts
@@ -69,6 +137,16 @@ pub struct Delist<'info> {
 }
 
 impl<'info> Delist<'info> {
+    /// Hand the collection floor to the candidate listing if this one held it
+    pub fn update_floor(&mut self) -> Result<()> {
+        let candidate = self.floor_candidate
+            .as_ref()
+            .map(|candidate| self.collection_stats.validate_candidate(candidate, self.listing.key()))
+            .transpose()?;
+        self.collection_stats.on_removed(self.listing.maker_mint, candidate);
+        Ok(())
+    }
+
     /// Transfer the NFT from vault back to maker
     pub fn delist(&mut self) -> Result<()> {
         let marketplace_key = self.marketplace.key();
@@ -80,6 +158,32 @@ impl<'info> Delist<'info> {
         ];
         let signer_seeds = &[&seeds[..]];
 
+        if is_programmable(&self.metadata) {
+            PnftTransfer {
+                metadata_program: self.metadata_program.as_ref(),
+                token: self.vault.as_ref(),
+                token_owner: self.listing.as_ref(),
+                destination_token: self.maker_ata.as_ref(),
+                destination_owner: self.maker.as_ref(),
+                mint: self.maker_mint.as_ref(),
+                metadata: self.metadata.as_ref(),
+                edition: self.master_edition.as_ref(),
+                authority: self.listing.as_ref(),
+                payer: self.maker.as_ref(),
+                system_program: self.system_program.as_ref(),
+                token_program: self.token_program.as_ref(),
+                associated_token_program: self.associated_token_program.as_ref(),
+                token_record: optional_info(&self.owner_token_record),
+                destination_token_record: optional_info(&self.destination_token_record),
+                sysvar_instructions: optional_info(&self.sysvar_instructions),
+                authorization_rules: optional_info(&self.authorization_rules),
+                authorization_rules_program: optional_info(&self.authorization_rules_program),
+            }.invoke_signed(signer_seeds)?;
+
+            msg!("pNFT returned to maker");
+            return Ok(());
+        }
+
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
@@ -100,6 +204,11 @@ impl<'info> Delist<'info> {
 
     /// Close the vault account
     pub fn close_mint_vault(&mut self) -> Result<()> {
+        // Token Metadata may already have closed an emptied pNFT vault
+        if self.vault.to_account_info().lamports() == 0 {
+            return Ok(());
+        }
+
         let marketplace_key = self.marketplace.key();
         let maker_mint_key = self.maker_mint.key();
         let seeds = &[
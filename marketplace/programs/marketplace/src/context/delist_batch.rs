@@ -0,0 +1,156 @@
+/**
+ * Batch Delist Context
+ *
+ * Removes several of a seller's listings in one transaction. Each listing is
+ * passed through `remaining_accounts` as a group of four accounts:
+ * `[listing, vault, maker_mint, maker_ata]`. All listings must belong to the
+ * collection of `collection_stats`. Every NFT is returned to the maker and
+ * its vault and listing accounts are closed; any failure aborts the whole
+ * batch. The collection floor is then updated, with `floor_candidate` taking
+ * over as in `delist`.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token::{close_account, transfer_checked, CloseAccount, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::state::{CollectionStats, Listing, Marketplace};
+use crate::error::MarketplaceError;
+
+/// Number of remaining accounts supplied per listing
+pub const DELIST_BATCH_ACCOUNTS_PER_LISTING: usize = 4;
+
+/// Safe upper bound on listings per batch. Each listing costs two token CPIs
+/// plus two ATA derivations (~25k CU), and its four accounts take ~128 bytes
+/// of the 1232-byte transaction, so five keeps well inside both limits.
+pub const MAX_DELIST_BATCH: usize = 5;
+
+#[derive(Accounts)]
+pub struct DelistBatch<'info> {
+    /// The signer who originally listed every NFT in the batch
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// The marketplace state account
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// Floor price tracking for the listings' collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_stats.collection_mint.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Cheapest listing of the collection left after the batch, taking over
+    /// the floor if a delisted listing held it
+    pub floor_candidate: Option<Account<'info, Listing>>,
+
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> DelistBatch<'info> {
+    /// Return each NFT to the maker and close its vault and listing
+    pub fn delist_all(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts.len().is_multiple_of(DELIST_BATCH_ACCOUNTS_PER_LISTING),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require!(
+            remaining_accounts.len() / DELIST_BATCH_ACCOUNTS_PER_LISTING <= MAX_DELIST_BATCH,
+            MarketplaceError::BatchTooLarge
+        );
+
+        let mut removed = Vec::with_capacity(remaining_accounts.len() / DELIST_BATCH_ACCOUNTS_PER_LISTING);
+        for group in remaining_accounts.chunks(DELIST_BATCH_ACCOUNTS_PER_LISTING) {
+            removed.push(self.delist_one(&group[0], &group[1], &group[2], &group[3])?);
+        }
+        self.collection_stats.on_removed_many(&removed, self.floor_candidate.as_ref())?;
+
+        msg!("Delisted {} NFTs", remaining_accounts.len() / DELIST_BATCH_ACCOUNTS_PER_LISTING);
+        Ok(())
+    }
+
+    /// Delist one NFT, returning its (listing, mint)
+    fn delist_one(
+        &self,
+        listing_info: &'info AccountInfo<'info>,
+        vault_info: &'info AccountInfo<'info>,
+        mint_info: &'info AccountInfo<'info>,
+        maker_ata_info: &'info AccountInfo<'info>,
+    ) -> Result<(Pubkey, Pubkey)> {
+        let listing: Account<'info, Listing> = Account::try_from(listing_info)?;
+        let maker_mint: InterfaceAccount<'info, Mint> = InterfaceAccount::try_from(mint_info)?;
+        let vault: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(vault_info)?;
+
+        require_keys_eq!(listing.maker, self.maker.key(), MarketplaceError::Unauthorized);
+        require_keys_eq!(listing.maker_mint, maker_mint.key(), MarketplaceError::InvalidBatchAccounts);
+        require!(!listing.has_bids(), MarketplaceError::AuctionHasBids);
+        require_keys_eq!(listing.collection, self.collection_stats.collection_mint, MarketplaceError::InvalidCollection);
+
+        // The listing must be the PDA for this marketplace and mint
+        let marketplace_key = self.marketplace.key();
+        let maker_mint_key = maker_mint.key();
+        let seeds = &[
+            marketplace_key.as_ref(),
+            maker_mint_key.as_ref(),
+            &[listing.bump]
+        ];
+        let expected_listing = Pubkey::create_program_address(seeds, &crate::ID)
+            .map_err(|_| MarketplaceError::InvalidBatchAccounts)?;
+        require_keys_eq!(expected_listing, listing.key(), MarketplaceError::InvalidBatchAccounts);
+
+        // Vault and destination must be the canonical ATAs
+        let token_program_key = self.token_program.key();
+        require_keys_eq!(
+            vault.key(),
+            get_associated_token_address_with_program_id(&listing.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require_keys_eq!(
+            maker_ata_info.key(),
+            get_associated_token_address_with_program_id(&self.maker.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require!(vault.amount == 1, MarketplaceError::EmptyVault);
+
+        let signer_seeds = &[&seeds[..]];
+
+        // Transfer 1 NFT back to maker
+        let cpi_accounts = TransferChecked {
+            from: vault_info.clone(),
+            mint: mint_info.clone(),
+            to: maker_ata_info.clone(),
+            authority: listing_info.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        transfer_checked(cpi_ctx, 1, maker_mint.decimals)?;
+
+        // Close the vault, refunding rent to the maker
+        let cpi_accounts = CloseAccount {
+            account: vault_info.clone(),
+            destination: self.maker.to_account_info(),
+            authority: listing_info.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        close_account(cpi_ctx)?;
+
+        // Close the listing, refunding rent to the maker
+        let listing_key = listing.key();
+        listing.close(self.maker.to_account_info())?;
+
+        msg!("NFT {} returned to maker", maker_mint_key);
+        Ok((listing_key, maker_mint_key))
+    }
+}
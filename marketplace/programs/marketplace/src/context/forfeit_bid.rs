@@ -0,0 +1,81 @@
+/**
+ * Forfeit Bid Context
+ * 
+ * Permissionless cleanup of an English auction whose highest bidder didn't
+ * settle within the settlement window. The bid's deposit is paid to the
+ * maker and its rent returned to the bidder. The auction is left without
+ * bids, so the maker can delist the NFT.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::state::{Bid, Listing, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct ForfeitBid<'info> {
+    /// The seller, paid the forfeited deposit
+    /// CHECK: Verified against the listing
+    #[account(
+        mut,
+        address = listing.maker @ MarketplaceError::InvalidMaker
+    )]
+    pub maker: UncheckedAccount<'info>,
+
+    /// The winning bidder who didn't settle, refunded the bid's rent
+    /// CHECK: Verified against the bid
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    /// The marketplace the auction ran on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The auctioned NFT
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The English auction listing, cleared of its highest bid
+    #[account(
+        mut,
+        seeds = [marketplace.key().as_ref(), maker_mint.key().as_ref()],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, Listing>,
+
+    /// The lapsed winning bid, closed back to the bidder
+    #[account(
+        mut,
+        seeds = [b"bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump = bid.bump,
+        has_one = bidder @ MarketplaceError::InvalidPreviousBid,
+        close = bidder,
+    )]
+    pub bid: Account<'info, Bid>,
+}
+
+impl<'info> ForfeitBid<'info> {
+    /// Pay the deposit to the maker once the settlement window has passed
+    pub fn forfeit(&mut self) -> Result<()> {
+        let mut auction = self.listing.english_auction.ok_or(MarketplaceError::NotEnglishAuction)?;
+        require!(auction.highest_bidder == Some(self.bidder.key()), MarketplaceError::NotHighestBidder);
+        require!(
+            Clock::get()?.unix_timestamp > auction.settle_deadline()?,
+            MarketplaceError::SettlementWindowOpen
+        );
+
+        let deposit = self.bid.deposit;
+        self.bid.sub_lamports(deposit)?;
+        self.maker.add_lamports(deposit)?;
+
+        auction.highest_bid = 0;
+        auction.highest_bidder = None;
+        self.listing.english_auction = Some(auction);
+
+        msg!("Deposit of {} lamports forfeited to the maker", deposit);
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+/**
+ * Get Floor Price Context
+ * 
+ * Read-only view of a collection's floor price, returned as instruction
+ * return data.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{CollectionStats, Marketplace};
+
+#[derive(Accounts)]
+pub struct GetFloorPrice<'info> {
+    /// The marketplace the collection trades on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// Floor price tracking for the collection
+    #[account(
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_stats.collection_mint.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+}
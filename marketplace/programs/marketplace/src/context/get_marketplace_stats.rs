@@ -0,0 +1,64 @@
+/**
+ * Get Marketplace Stats Context
+ * 
+ * Read-only view of the marketplace's health in one call: its fee, what the
+ * treasury holds, lifetime sales and the reward tokens minted so far,
+ * returned as instruction return data.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::state::Marketplace;
+
+/// Snapshot returned by `get_marketplace_stats`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MarketplaceStats {
+    /// Marketplace fee in basis points
+    pub fee: u16,
+    /// Lamports held by the treasury, including its rent-exempt minimum
+    pub treasury_balance: u64,
+    /// Sum of all sale prices in lamports
+    pub total_volume: u64,
+    /// Number of completed purchases
+    pub total_sales: u64,
+    /// Reward tokens minted to buyers so far
+    pub reward_supply: u64,
+}
+
+#[derive(Accounts)]
+pub struct GetMarketplaceStats<'info> {
+    /// The marketplace to report on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The treasury PDA that collects fees
+    #[account(
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
+    /// The rewards mint PDA
+    #[account(
+        seeds = [b"rewards", marketplace.key().as_ref()],
+        bump = marketplace.rewards_bump,
+    )]
+    pub rewards_mint: InterfaceAccount<'info, Mint>,
+}
+
+impl<'info> GetMarketplaceStats<'info> {
+    /// Gather the marketplace's config and counters with the live balances
+    pub fn stats(&self) -> MarketplaceStats {
+        MarketplaceStats {
+            fee: self.marketplace.fee,
+            treasury_balance: self.treasury.lamports(),
+            total_volume: self.marketplace.total_volume,
+            total_sales: self.marketplace.total_sales,
+            reward_supply: self.rewards_mint.supply,
+        }
+    }
+}
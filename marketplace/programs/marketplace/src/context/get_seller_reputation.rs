@@ -0,0 +1,27 @@
+/**
+ * Get Seller Reputation Context
+ * 
+ * Read-only view of a seller's reputation score, returned as instruction
+ * return data. See `SellerStats::reputation` for how it is derived.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{Marketplace, SellerStats};
+
+#[derive(Accounts)]
+pub struct GetSellerReputation<'info> {
+    /// The marketplace the seller trades on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The seller's stats PDA
+    #[account(
+        seeds = [b"seller_stats", marketplace.key().as_ref(), seller_stats.seller.as_ref()],
+        bump = seller_stats.bump,
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+}
@@ -53,7 +53,15 @@ pub struct Initialize<'info> {
 
 impl<'info> Initialize<'info> {
     /// Initialize the marketplace with provided configuration
-    pub fn init(&mut self, name: String, fee: u16, bumps: &InitializeBumps) -> Result<()> {
+    pub fn init(
+        &mut self,
+        name: String,
+        fee: u16,
+        require_verified_creator: bool,
+        listing_fee: u64,
+        enforce_royalties: bool,
+        bumps: &InitializeBumps,
+    ) -> Result<()> {
         // Set marketplace account data
         self.marketplace.set_inner(Marketplace {
             admin: self.admin.key(),
@@ -62,6 +70,13 @@ impl<'info> Initialize<'info> {
             treasury_bump: bumps.treasury,
             rewards_bump: bumps.reward_mint,
             name,
+            total_volume: 0,
+            total_sales: 0,
+            require_verified_creator,
+            listing_fee,
+            enforce_royalties,
+            dispute_window: 0,
+            reward_supply_cap: 0,
         });
 
         msg!("Initialized marketplace with fee: {} basis points", fee);
@@ -3,9 +3,15 @@
  * 
  * Creates a listing for an NFT, transferring it to an escrow vault.
  * Validates that the NFT belongs to a verified collection.
+ * Charges the marketplace's listing fee, if any, to the treasury.
+ * Lowers the collection's floor price when the listing undercuts it.
+ * Dutch auction listings start their price curve here and, since their
+ * price keeps falling, are left out of the floor. English auction listings
+ * open their bidding here and are left out of the floor too.
+ * Programmable NFTs are moved through Token Metadata (see `pnft.rs`).
  */
 
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
 use anchor_spl::{
     associated_token::AssociatedToken,
     metadata::{MasterEditionAccount, Metadata, MetadataAccount},
@@ -13,8 +19,9 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface}
 };
 
-use crate::state::{Listing, Marketplace};
+use crate::state::{CollectionStats, DutchAuction, EnglishAuction, Listing, Marketplace};
 use crate::error::MarketplaceError;
+use crate::pnft::{is_programmable, optional_info, PnftTransfer};
 
 #[derive(Accounts)]
 pub struct List<'info> {
@@ -29,6 +36,14 @@ pub struct List<'info> {
     )]
     pub marketplace: Account<'info, Marketplace>,
 
+    /// The treasury PDA that receives the listing fee
+    #[account(
+        mut,
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
     /// The NFT mint being listed
     pub maker_mint: InterfaceAccount<'info, Mint>,
     
@@ -63,8 +78,9 @@ pub struct List<'info> {
     /// Collection the NFT belongs to
     pub collection_mint: InterfaceAccount<'info, Mint>,
     
-    /// NFT metadata to verify collection
+    /// NFT metadata to verify collection (written by pNFT transfers)
     #[account(
+        mut,
         seeds = [
             b"metadata",
             metadata_program.key().as_ref(),
@@ -74,6 +90,9 @@ pub struct List<'info> {
         bump,
         constraint = metadata.collection.as_ref().unwrap().key.as_ref() == collection_mint.key().as_ref() @ MarketplaceError::InvalidCollection,
         constraint = metadata.collection.as_ref().unwrap().verified == true @ MarketplaceError::UnverifiedCollection,
+        constraint = !marketplace.require_verified_creator
+            || metadata.creators.as_ref().is_some_and(|creators| creators.iter().any(|creator| creator.verified))
+            @ MarketplaceError::UnverifiedCreator,
     )]
     pub metadata: Account<'info, MetadataAccount>,
     
@@ -90,6 +109,39 @@ pub struct List<'info> {
     )]
     pub master_edition: Account<'info, MasterEditionAccount>,
 
+    /// Floor price tracking for the NFT's collection
+    #[account(
+        init_if_needed,
+        payer = maker,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        space = CollectionStats::INIT_SPACE,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Token record for the NFT's current token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Token record for the destination token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Rule set attached to the pNFT, if any
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// Token Auth Rules program, if the pNFT has a rule set
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// Instructions sysvar (pNFTs only)
+    /// CHECK: Address is checked against the sysvar ID
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
     /// Metaplex program
     pub metadata_program: Program<'info, Metadata>,
     /// For creating ATAs
@@ -101,12 +153,37 @@ pub struct List<'info> {
 }
 
 impl<'info> List<'info> {
-    /// Create the listing account with specified price
-    pub fn create_listing(&mut self, price: u64, bumps: &ListBumps) -> Result<()> {
+    /// Transfer the marketplace's listing fee from maker to treasury
+    pub fn charge_listing_fee(&mut self) -> Result<()> {
+        let listing_fee = self.marketplace.listing_fee;
+        if listing_fee == 0 {
+            return Ok(());
+        }
+
+        require!(self.maker.lamports() >= listing_fee, MarketplaceError::InsufficientListingFee);
+
+        let cpi_program = self.system_program.to_account_info();
+        let cpi_accounts = Transfer {
+            from: self.maker.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        transfer(cpi_ctx, listing_fee)?;
+
+        msg!("Listing fee {} lamports transferred to treasury", listing_fee);
+        Ok(())
+    }
+
+    /// Create the listing account with specified price and optional private buyer
+    pub fn create_listing(&mut self, price: u64, allowed_buyer: Option<Pubkey>, bumps: &ListBumps) -> Result<()> {
         self.listing.set_inner(Listing {
             maker: self.maker.key(),
             maker_mint: self.maker_mint.key(),
             price,
+            allowed_buyer,
+            collection: self.collection_mint.key(),
+            dutch_auction: None,
+            english_auction: None,
             bump: bumps.listing,
         });
 
@@ -114,8 +191,85 @@ impl<'info> List<'info> {
         Ok(())
     }
 
+    /// Turn the new listing into a Dutch auction starting now
+    pub fn start_dutch_auction(&mut self, start_price: u64, end_price: u64, duration: i64) -> Result<()> {
+        require!(
+            end_price > 0 && start_price > end_price && duration > 0,
+            MarketplaceError::InvalidDutchAuction
+        );
+
+        self.listing.dutch_auction = Some(DutchAuction {
+            start_price,
+            end_price,
+            start_time: Clock::get()?.unix_timestamp,
+            duration,
+        });
+
+        msg!("Dutch auction from {} to {} lamports over {} seconds", start_price, end_price, duration);
+        Ok(())
+    }
+
+    /// Turn the new listing into an English auction taking bids for
+    /// `duration` seconds, with `settle_window` seconds for the winner to pay
+    pub fn start_english_auction(&mut self, duration: i64, settle_window: i64) -> Result<()> {
+        require!(duration > 0 && settle_window > 0, MarketplaceError::InvalidEnglishAuction);
+
+        let end_time = Clock::get()?
+            .unix_timestamp
+            .checked_add(duration)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        self.listing.english_auction = Some(EnglishAuction {
+            end_time,
+            settle_window,
+            highest_bid: 0,
+            highest_bidder: None,
+        });
+
+        msg!("English auction from {} lamports for {} seconds", self.listing.price, duration);
+        Ok(())
+    }
+
+    /// Record the listing in the collection's stats, making it the floor if it is the cheapest
+    pub fn track_floor(&mut self, price: u64, bumps: &ListBumps) -> Result<()> {
+        let stats = &mut self.collection_stats;
+        if stats.marketplace == Pubkey::default() {
+            stats.marketplace = self.marketplace.key();
+            stats.collection_mint = self.collection_mint.key();
+            stats.bump = bumps.collection_stats;
+        }
+
+        stats.on_listed(self.maker_mint.key(), price);
+        Ok(())
+    }
+
     /// Transfer the NFT from maker to vault
     pub fn deposit_nft(&mut self) -> Result<()> {
+        if is_programmable(&self.metadata) {
+            PnftTransfer {
+                metadata_program: self.metadata_program.as_ref(),
+                token: self.maker_ata.as_ref(),
+                token_owner: self.maker.as_ref(),
+                destination_token: self.vault.as_ref(),
+                destination_owner: self.listing.as_ref(),
+                mint: self.maker_mint.as_ref(),
+                metadata: self.metadata.as_ref(),
+                edition: self.master_edition.as_ref(),
+                authority: self.maker.as_ref(),
+                payer: self.maker.as_ref(),
+                system_program: self.system_program.as_ref(),
+                token_program: self.token_program.as_ref(),
+                associated_token_program: self.associated_token_program.as_ref(),
+                token_record: optional_info(&self.owner_token_record),
+                destination_token_record: optional_info(&self.destination_token_record),
+                sysvar_instructions: optional_info(&self.sysvar_instructions),
+                authorization_rules: optional_info(&self.authorization_rules),
+                authorization_rules_program: optional_info(&self.authorization_rules_program),
+            }.invoke_signed(&[])?;
+
+            msg!("pNFT deposited to vault");
+            return Ok(());
+        }
+
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
@@ -0,0 +1,248 @@
+/**
+ * Collection Bulk List Context
+ *
+ * Lists several NFTs from one verified collection at the same price in one
+ * transaction, for drops. Each NFT is passed through `remaining_accounts` as
+ * a group of six accounts:
+ * `[maker_mint, maker_ata, vault, listing, metadata, master_edition]`.
+ * Every NFT gets the same checks as `listing`, is escrowed in its vault and
+ * gets an open listing; any failure aborts the whole batch. The listing fee,
+ * if any, is charged once per NFT. pNFTs need token records per transfer and
+ * are refused; list them with `listing`.
+ */
+
+use anchor_lang::{
+    prelude::*,
+    system_program::{create_account, transfer, CreateAccount, Transfer},
+};
+use anchor_spl::{
+    associated_token::{create, get_associated_token_address_with_program_id, AssociatedToken, Create},
+    metadata::{MasterEditionAccount, Metadata, MetadataAccount},
+    token::{transfer_checked, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::state::{CollectionStats, Listing, Marketplace};
+use crate::error::MarketplaceError;
+use crate::pnft::is_programmable;
+
+/// Number of remaining accounts supplied per NFT
+pub const LIST_COLLECTION_ACCOUNTS_PER_NFT: usize = 6;
+
+/// Safe upper bound on NFTs per batch. Each NFT's six accounts take ~192
+/// bytes of the 1232-byte transaction on top of the fixed accounts, and
+/// costs two account creations plus a token transfer.
+pub const MAX_LIST_COLLECTION: usize = 3;
+
+#[derive(Accounts)]
+pub struct ListCollection<'info> {
+    /// The owner of every NFT in the batch
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// The marketplace configuration account
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The treasury PDA that receives the listing fees
+    #[account(
+        mut,
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
+    /// Collection every NFT in the batch must belong to
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// Floor price tracking for the collection
+    #[account(
+        init_if_needed,
+        payer = maker,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        space = CollectionStats::INIT_SPACE,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Metaplex program
+    pub metadata_program: Program<'info, Metadata>,
+    /// For creating ATAs
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> ListCollection<'info> {
+    /// Escrow each NFT and open a listing for it at `price`
+    pub fn list_all(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        price: u64,
+        bumps: &ListCollectionBumps,
+    ) -> Result<()> {
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts.len().is_multiple_of(LIST_COLLECTION_ACCOUNTS_PER_NFT),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        let count = remaining_accounts.len() / LIST_COLLECTION_ACCOUNTS_PER_NFT;
+        require!(count <= MAX_LIST_COLLECTION, MarketplaceError::BatchTooLarge);
+
+        self.charge_listing_fees(count as u64)?;
+
+        let stats = &mut self.collection_stats;
+        if stats.marketplace == Pubkey::default() {
+            stats.marketplace = self.marketplace.key();
+            stats.collection_mint = self.collection_mint.key();
+            stats.bump = bumps.collection_stats;
+        }
+
+        for group in remaining_accounts.chunks(LIST_COLLECTION_ACCOUNTS_PER_NFT) {
+            self.list_one(group, price)?;
+        }
+
+        msg!("Listed {} NFTs at {} lamports each", count, price);
+        Ok(())
+    }
+
+    /// Transfer the marketplace's listing fee for `count` NFTs from maker to treasury
+    fn charge_listing_fees(&self, count: u64) -> Result<()> {
+        let total_fee = self.marketplace.listing_fee
+            .checked_mul(count)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        if total_fee == 0 {
+            return Ok(());
+        }
+
+        require!(self.maker.lamports() >= total_fee, MarketplaceError::InsufficientListingFee);
+
+        let cpi_accounts = Transfer {
+            from: self.maker.to_account_info(),
+            to: self.treasury.to_account_info(),
+        };
+        transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), total_fee)?;
+
+        msg!("Listing fees {} lamports transferred to treasury", total_fee);
+        Ok(())
+    }
+
+    fn list_one(&mut self, group: &'info [AccountInfo<'info>], price: u64) -> Result<()> {
+        let [mint_info, maker_ata_info, vault_info, listing_info, metadata_info, edition_info] = group else {
+            return err!(MarketplaceError::InvalidBatchAccounts);
+        };
+
+        let maker_mint: InterfaceAccount<'info, Mint> = InterfaceAccount::try_from(mint_info)?;
+        let maker_ata: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(maker_ata_info)?;
+        let maker_mint_key = maker_mint.key();
+        let token_program_key = self.token_program.key();
+
+        // The maker's ATA must hold the NFT
+        require_keys_eq!(
+            maker_ata.key(),
+            get_associated_token_address_with_program_id(&self.maker.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require!(maker_ata.amount == 1, MarketplaceError::InsufficientTokens);
+
+        // Same metadata checks as `listing`
+        let metadata_program_key = self.metadata_program.key();
+        let (expected_metadata, _) = Pubkey::find_program_address(
+            &[b"metadata", metadata_program_key.as_ref(), maker_mint_key.as_ref()],
+            &metadata_program_key,
+        );
+        let (expected_edition, _) = Pubkey::find_program_address(
+            &[b"metadata", metadata_program_key.as_ref(), maker_mint_key.as_ref(), b"edition"],
+            &metadata_program_key,
+        );
+        require_keys_eq!(metadata_info.key(), expected_metadata, MarketplaceError::InvalidBatchAccounts);
+        require_keys_eq!(edition_info.key(), expected_edition, MarketplaceError::InvalidBatchAccounts);
+
+        let metadata: Account<'info, MetadataAccount> = Account::try_from(metadata_info)?;
+        let _master_edition: Account<'info, MasterEditionAccount> = Account::try_from(edition_info)?;
+
+        let collection = metadata.collection.as_ref().ok_or(MarketplaceError::InvalidCollection)?;
+        require_keys_eq!(collection.key, self.collection_mint.key(), MarketplaceError::InvalidCollection);
+        require!(collection.verified, MarketplaceError::UnverifiedCollection);
+        require!(
+            !self.marketplace.require_verified_creator
+                || metadata.creators.as_ref().is_some_and(|creators| creators.iter().any(|creator| creator.verified)),
+            MarketplaceError::UnverifiedCreator
+        );
+        require!(!is_programmable(&metadata), MarketplaceError::BulkListPnftUnsupported);
+
+        // Create the listing PDA for this marketplace and mint
+        let marketplace_key = self.marketplace.key();
+        let (expected_listing, listing_bump) = Pubkey::find_program_address(
+            &[marketplace_key.as_ref(), maker_mint_key.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(listing_info.key(), expected_listing, MarketplaceError::InvalidBatchAccounts);
+
+        let seeds = &[
+            marketplace_key.as_ref(),
+            maker_mint_key.as_ref(),
+            &[listing_bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = CreateAccount {
+            from: self.maker.to_account_info(),
+            to: listing_info.clone(),
+        };
+        create_account(
+            CpiContext::new_with_signer(self.system_program.to_account_info(), cpi_accounts, signer_seeds),
+            Rent::get()?.minimum_balance(Listing::INIT_SPACE),
+            Listing::INIT_SPACE as u64,
+            &crate::ID,
+        )?;
+
+        Listing {
+            maker: self.maker.key(),
+            maker_mint: maker_mint_key,
+            price,
+            allowed_buyer: None,
+            collection: self.collection_mint.key(),
+            dutch_auction: None,
+            english_auction: None,
+            bump: listing_bump,
+        }.try_serialize(&mut &mut listing_info.try_borrow_mut_data()?[..])?;
+
+        // Create the escrow vault owned by the listing
+        require_keys_eq!(
+            vault_info.key(),
+            get_associated_token_address_with_program_id(&listing_info.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        create(CpiContext::new(
+            self.associated_token_program.to_account_info(),
+            Create {
+                payer: self.maker.to_account_info(),
+                associated_token: vault_info.clone(),
+                authority: listing_info.clone(),
+                mint: mint_info.clone(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+            },
+        ))?;
+
+        // Transfer 1 NFT from maker to vault
+        let cpi_accounts = TransferChecked {
+            from: maker_ata_info.clone(),
+            mint: mint_info.clone(),
+            to: vault_info.clone(),
+            authority: self.maker.to_account_info(),
+        };
+        transfer_checked(CpiContext::new(self.token_program.to_account_info(), cpi_accounts), 1, maker_mint.decimals)?;
+
+        self.collection_stats.on_listed(maker_mint_key, price);
+
+        msg!("Created listing for mint: {}", maker_mint_key);
+        Ok(())
+    }
+}
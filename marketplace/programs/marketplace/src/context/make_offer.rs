@@ -0,0 +1,60 @@
+/**
+ * Make Offer Context
+ * 
+ * Lets a bidder offer a price for an NFT by escrowing the lamports in an
+ * offer PDA. The seller can accept the best one with `sell_to_best_offer`.
+ */
+
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use anchor_spl::token_interface::Mint;
+
+use crate::state::{Marketplace, Offer};
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    /// The bidder (signer) funding the offer
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// The marketplace the offer is made on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT being bid on
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The offer PDA escrowing the bid
+    #[account(
+        init,
+        payer = bidder,
+        space = Offer::INIT_SPACE,
+        seeds = [b"offer", marketplace.key().as_ref(), maker_mint.key().as_ref(), bidder.key().as_ref()],
+        bump,
+    )]
+    pub offer: Account<'info, Offer>,
+
+    /// For creating the offer and escrowing the bid
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeOffer<'info> {
+    /// Record the offer and move `amount` lamports into it
+    pub fn place_offer(&mut self, amount: u64, bumps: &MakeOfferBumps) -> Result<()> {
+        self.offer.set_inner(Offer {
+            bidder: self.bidder.key(),
+            marketplace: self.marketplace.key(),
+            maker_mint: self.maker_mint.key(),
+            amount,
+            bump: bumps.offer,
+        });
+
+        let cpi_accounts = Transfer {
+            from: self.bidder.to_account_info(),
+            to: self.offer.to_account_info(),
+        };
+        transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), amount)
+    }
+}
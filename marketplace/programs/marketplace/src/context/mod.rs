@@ -7,11 +7,74 @@ pub use list::*;
 pub mod delist;
 pub use delist::*;
 
+pub mod relist;
+pub use relist::*;
+
+pub mod delist_batch;
+pub use delist_batch::*;
+
+pub mod list_collection;
+pub use list_collection::*;
+
 pub mod purchase;
 pub use purchase::*;
 
+pub mod sweep;
+pub use sweep::*;
+
+pub mod purchase_signed;
+pub use purchase_signed::*;
+
+pub mod purchase_wsol;
+pub use purchase_wsol::*;
+
+pub mod release_sale;
+pub use release_sale::*;
+
+pub mod confirm_receipt;
+pub use confirm_receipt::*;
+
+pub mod raise_dispute;
+pub use raise_dispute::*;
+
+pub mod resolve_dispute;
+pub use resolve_dispute::*;
+
+pub mod make_offer;
+pub use make_offer::*;
+
+pub mod cancel_offer;
+pub use cancel_offer::*;
+
+pub mod place_bid;
+pub use place_bid::*;
+
+pub mod forfeit_bid;
+pub use forfeit_bid::*;
+
+pub mod sell_to_best_offer;
+pub use sell_to_best_offer::*;
+
+pub mod refresh_floor;
+pub use refresh_floor::*;
+
+pub mod get_floor_price;
+pub use get_floor_price::*;
+
+pub mod get_seller_reputation;
+pub use get_seller_reputation::*;
+
+pub mod get_marketplace_stats;
+pub use get_marketplace_stats::*;
+
 pub mod update_marketplace;
 pub use update_marketplace::*;
 
+pub mod set_collection_fee;
+pub use set_collection_fee::*;
+
+pub mod set_collection_royalty;
+pub use set_collection_royalty::*;
+
 pub mod withdraw_fees;
 pub use withdraw_fees::*;
@@ -0,0 +1,121 @@
+/**
+ * Place Bid Context
+ * 
+ * Bids on an English auction listing. The bidder posts a refundable deposit
+ * of `BID_DEPOSIT_BPS` of the bid into a bid PDA (see `state/bid.rs`) and
+ * becomes the highest bidder. The bid they beat is closed in the same call,
+ * refunding its deposit and rent to the outbid bidder, so only the highest
+ * bid is ever open.
+ */
+
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use anchor_spl::token_interface::Mint;
+
+use crate::state::{Bid, Listing, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    /// The bidder (signer) posting the deposit
+    #[account(
+        mut,
+        constraint = bidder.key() != listing.maker @ MarketplaceError::Unauthorized
+    )]
+    pub bidder: Signer<'info>,
+
+    /// The marketplace the auction runs on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT being auctioned
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The English auction listing, updated with the new highest bid
+    #[account(
+        mut,
+        seeds = [marketplace.key().as_ref(), maker_mint.key().as_ref()],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, Listing>,
+
+    /// The bid PDA holding the deposit
+    #[account(
+        init,
+        payer = bidder,
+        space = Bid::INIT_SPACE,
+        seeds = [b"bid", listing.key().as_ref(), bidder.key().as_ref()],
+        bump,
+    )]
+    pub bid: Account<'info, Bid>,
+
+    /// The highest bid being beaten, closed back to its bidder (required once
+    /// the auction has a bid)
+    #[account(
+        mut,
+        seeds = [b"bid", listing.key().as_ref(), previous_bid.bidder.as_ref()],
+        bump = previous_bid.bump,
+    )]
+    pub previous_bid: Option<Account<'info, Bid>>,
+
+    /// The outbid bidder, refunded their deposit and rent
+    /// CHECK: Verified against the previous bid
+    #[account(mut)]
+    pub previous_bidder: Option<UncheckedAccount<'info>>,
+
+    /// For creating the bid and moving the deposit
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> PlaceBid<'info> {
+    /// Record a bid of `amount`, take its deposit and refund the bid it beats
+    pub fn place_bid(&mut self, amount: u64, bumps: &PlaceBidBumps) -> Result<()> {
+        let mut auction = self.listing.english_auction.ok_or(MarketplaceError::NotEnglishAuction)?;
+        require!(Clock::get()?.unix_timestamp < auction.end_time, MarketplaceError::AuctionEnded);
+        match auction.highest_bidder {
+            None => require!(amount >= self.listing.price, MarketplaceError::BidTooLow),
+            Some(_) => require!(amount > auction.highest_bid, MarketplaceError::BidTooLow),
+        }
+
+        let deposit = Bid::deposit_for(amount)?;
+        self.bid.set_inner(Bid {
+            bidder: self.bidder.key(),
+            listing: self.listing.key(),
+            amount,
+            deposit,
+            bump: bumps.bid,
+        });
+
+        let cpi_accounts = Transfer {
+            from: self.bidder.to_account_info(),
+            to: self.bid.to_account_info(),
+        };
+        transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), deposit)?;
+
+        if let Some(outbid) = auction.highest_bidder {
+            self.refund_previous_bid(outbid)?;
+        }
+
+        auction.highest_bid = amount;
+        auction.highest_bidder = Some(self.bidder.key());
+        self.listing.english_auction = Some(auction);
+
+        msg!("Bid of {} lamports placed with a {} lamport deposit", amount, deposit);
+        Ok(())
+    }
+
+    /// Close the outbid highest bid, returning its deposit and rent to `outbid`
+    fn refund_previous_bid(&self, outbid: Pubkey) -> Result<()> {
+        let (Some(previous_bid), Some(previous_bidder)) = (&self.previous_bid, &self.previous_bidder) else {
+            return err!(MarketplaceError::InvalidPreviousBid);
+        };
+        require_keys_eq!(previous_bid.bidder, outbid, MarketplaceError::InvalidPreviousBid);
+        require_keys_eq!(previous_bidder.key(), outbid, MarketplaceError::InvalidPreviousBid);
+
+        previous_bid.close(previous_bidder.to_account_info())?;
+        msg!("Outbid deposit of {} lamports refunded to {}", previous_bid.deposit, outbid);
+        Ok(())
+    }
+}
@@ -2,13 +2,38 @@
  * Purchase NFT Context
  * 
  * Handles the purchase of a listed NFT, including:
- * - SOL payment with fee distribution
+ * - SOL payment with fee distribution (see `CollectionConfig::fee_bps`)
+ * - Creator royalties, or a `RoyaltiesBypassed` event when not enforced
+ * - The collection royalty, if the collection config sets one
  * - NFT transfer to buyer
+ * - Dutch auction listings, priced at the current point of their curve
+ * - English auction listings, settled by their highest bidder at their bid,
+ *   which refunds the bid's deposit (see `state/bid.rs`)
  * - Reward token minting
+ * - Collection floor price update
+ * - The seller's stats, created on their first sale (see `state/seller_stats.rs`)
+ * - Buyer protection: with a dispute window, the maker's proceeds go to a
+ *   sale escrow instead (see `state/sale_escrow.rs`)
  * - Account cleanup
+ *
+ * Programmable NFTs are moved through Token Metadata (see `pnft.rs`).
+ *
+ * The maker must be a system-owned account: a wallet, or a PDA that holds no
+ * data. Proceeds paid to an account owned by another program could only be
+ * spent by that program, so purchases from such makers fail with
+ * `MakerNotSystemOwned`; the maker can still delist. Programs that sell on a
+ * user's behalf should list from a data-less PDA and sweep its lamports.
+ *
+ * Ordering invariant (checks-effects-interactions): the payment split is
+ * computed and validated and the sale and collection floor recorded
+ * before any CPI, the escrowed NFT is released and
+ * its vault closed next, and SOL payments and reward minting run last.
+ * New CPIs should be appended after the existing ones, never ahead of the
+ * checks. The listing account is closed by Anchor once the handler returns.
  */
 
 use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use common_math::mul_div_floor;
 use anchor_spl::{
     associated_token::AssociatedToken,
     metadata::{MasterEditionAccount, Metadata, MetadataAccount},
@@ -16,25 +41,69 @@ use anchor_spl::{
     token_interface::{Mint, TokenAccount, TokenInterface}
 };
 
-use crate::state::{Listing, Marketplace};
+use crate::state::{Bid, CollectionConfig, CollectionStats, Listing, Marketplace, SaleEscrow, SellerStats};
 use crate::error::MarketplaceError;
+use crate::pnft::{is_programmable, optional_info, PnftTransfer};
+use crate::events::RoyaltiesBypassed;
+
+/// Reward tokens minted to the buyer per purchase (10 tokens, 6 decimals)
+pub const PURCHASE_REWARD_AMOUNT: u64 = 10_000_000;
+
+/// Where a purchase's lamports are paid from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PaymentSource {
+    /// Straight from the taker's wallet
+    Taker,
+    /// From the treasury, where `purchase_wsol` unwrapped the full price
+    Treasury,
+}
+
+/// Split `price` into the marketplace fee (`fee_bps`) and the maker's proceeds.
+/// The fee rounds down, so rounding never costs the maker.
+pub fn split_payment(price: u64, fee_bps: u16) -> Result<(u64, u64)> {
+    let fee_amount = mul_div_floor(price, fee_bps as u64, 10000)
+        .ok_or(MarketplaceError::MathOverflow)?;
+    let maker_amount = price
+        .checked_sub(fee_amount)
+        .ok_or(MarketplaceError::MathOverflow)?;
+
+    Ok((fee_amount, maker_amount))
+}
+
+/// Royalty owed to each creator on a sale at `price`, in metadata order.
+/// `seller_fee_bps` is the metadata royalty and `shares` the creator percentages.
+pub fn creator_royalties(price: u64, seller_fee_bps: u16, shares: &[u8]) -> Result<Vec<u64>> {
+    shares
+        .iter()
+        .map(|share| {
+            let amount = mul_div_floor(price, seller_fee_bps as u64 * *share as u64, 10000 * 100)
+                .ok_or(MarketplaceError::MathOverflow)?;
+            Ok(amount)
+        })
+        .collect()
+}
 
 #[derive(Accounts)]
 pub struct Purchase<'info> {
     /// The buyer (signer) purchasing the NFT
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = listing.allowed_buyer.is_none() || listing.allowed_buyer == Some(taker.key()) @ MarketplaceError::Unauthorized
+    )]
     pub taker: Signer<'info>,
 
-    /// The seller who originally listed the NFT
-    /// CHECK: Verified through listing account
+    /// The seller who originally listed the NFT, paid in lamports
+    /// CHECK: Verified through listing account, and system-owned so the proceeds stay spendable
     #[account(
         mut,
-        constraint = maker.key() == listing.maker @ MarketplaceError::InvalidMaker
+        constraint = maker.key() == listing.maker @ MarketplaceError::InvalidMaker,
+        owner = System::id() @ MarketplaceError::MakerNotSystemOwned
     )]
     pub maker: UncheckedAccount<'info>,
 
-    /// The marketplace state account
+    /// The marketplace state account, updated with the sale totals
     #[account(
+        mut,
         seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
         bump = marketplace.bump,
     )]
@@ -70,6 +139,61 @@ pub struct Purchase<'info> {
     )]
     pub listing: Account<'info, Listing>,
 
+    /// Floor price tracking for the listing's collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// The maker's sales record, created on their first sale
+    #[account(
+        init_if_needed,
+        payer = taker,
+        seeds = [b"seller_stats", marketplace.key().as_ref(), listing.maker.as_ref()],
+        bump,
+        space = SellerStats::INIT_SPACE,
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+
+    /// Next-cheapest listing of the collection, taking over the floor if this listing held it
+    pub floor_candidate: Option<Account<'info, Listing>>,
+
+    /// The collection's config PDA (see `CollectionConfig::fee_bps`)
+    /// CHECK: Address is checked by seeds; read by `CollectionConfig::fee_bps`
+    #[account(
+        seeds = [b"collection_config", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump,
+    )]
+    pub collection_config: UncheckedAccount<'info>,
+
+    /// Receives the collection royalty (required when the collection config sets one)
+    /// CHECK: Checked against the collection config's `royalty_recipient`
+    #[account(mut)]
+    pub royalty_recipient: Option<UncheckedAccount<'info>>,
+
+    /// Holds the maker's proceeds for the dispute window (required exactly when
+    /// the marketplace has one)
+    #[account(
+        init,
+        payer = taker,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), maker_mint.key().as_ref(), taker.key().as_ref()],
+        bump,
+        space = SaleEscrow::INIT_SPACE,
+    )]
+    pub sale_escrow: Option<Account<'info, SaleEscrow>>,
+
+    /// The taker's winning bid on an English auction listing, closed back to
+    /// them with its deposit (`settle_bid` only)
+    #[account(
+        mut,
+        seeds = [b"bid", listing.key().as_ref(), taker.key().as_ref()],
+        bump = bid.bump,
+        close = taker,
+    )]
+    pub bid: Option<Account<'info, Bid>>,
+
     /// The vault holding the NFT
     #[account(
         mut,
@@ -98,8 +222,9 @@ pub struct Purchase<'info> {
     /// Collection the NFT belongs to
     pub collection_mint: InterfaceAccount<'info, Mint>,
     
-    /// NFT metadata for verification
+    /// NFT metadata for verification (written by pNFT transfers)
     #[account(
+        mut,
         seeds = [
             b"metadata",
             metadata_program.key().as_ref(),
@@ -123,6 +248,29 @@ pub struct Purchase<'info> {
     )]
     pub master_edition: Account<'info, MasterEditionAccount>,
 
+    /// Token record for the NFT's current token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Token record for the destination token account (pNFTs only)
+    /// CHECK: Validated by the Token Metadata program
+    #[account(mut)]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// Rule set attached to the pNFT, if any
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// Token Auth Rules program, if the pNFT has a rule set
+    /// CHECK: Validated by the Token Metadata program
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// Instructions sysvar (pNFTs only)
+    /// CHECK: Address is checked against the sysvar ID
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
     /// Metaplex program
     pub metadata_program: Program<'info, Metadata>,
     /// For creating ATAs
@@ -134,38 +282,204 @@ pub struct Purchase<'info> {
 }
 
 impl<'info> Purchase<'info> {
-    /// Transfer SOL from taker to maker and treasury
-    pub fn send_sol(&mut self) -> Result<()> {
+    /// Dutch auction listings are bought with `purchase_dutch`, English ones with `settle_bid`
+    pub fn check_fixed_price(&self) -> Result<()> {
+        require!(self.listing.dutch_auction.is_none(), MarketplaceError::DutchAuctionListing);
+        require!(self.listing.english_auction.is_none(), MarketplaceError::EnglishAuctionListing);
+        Ok(())
+    }
+
+    /// Set the listing price to the Dutch auction's current price, so the
+    /// rest of the purchase charges and records it
+    pub fn price_dutch_auction(&mut self) -> Result<()> {
+        let auction = self.listing.dutch_auction.ok_or(MarketplaceError::NotDutchAuction)?;
+        self.listing.price = auction.price_at(Clock::get()?.unix_timestamp)?;
+
+        msg!("Dutch auction price: {} lamports", self.listing.price);
+        Ok(())
+    }
+
+    /// Set the listing price to the taker's winning bid, once the English
+    /// auction has ended and while its settlement window is open
+    pub fn price_english_auction(&mut self) -> Result<()> {
+        let auction = self.listing.english_auction.ok_or(MarketplaceError::NotEnglishAuction)?;
+        let bid = self.bid.as_ref().ok_or(MarketplaceError::NotHighestBidder)?;
+        require!(auction.highest_bidder == Some(self.taker.key()), MarketplaceError::NotHighestBidder);
+
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= auction.end_time, MarketplaceError::AuctionNotEnded);
+        require!(now <= auction.settle_deadline()?, MarketplaceError::SettlementWindowClosed);
+        self.listing.price = bid.amount;
+
+        msg!("Winning bid: {} lamports", self.listing.price);
+        Ok(())
+    }
+
+    /// Royalties owed to each metadata creator (empty when not enforced)
+    pub fn royalty_payouts(&self) -> Result<Vec<u64>> {
+        if !self.marketplace.enforce_royalties {
+            return Ok(Vec::new());
+        }
+
+        let shares: Vec<u8> = self.metadata.creators
+            .as_ref()
+            .map(|creators| creators.iter().map(|creator| creator.share).collect())
+            .unwrap_or_default();
+        creator_royalties(self.listing.price, self.metadata.seller_fee_basis_points, &shares)
+    }
+
+    /// Fee charged on this sale: the collection's override, else the global fee
+    pub fn fee_bps(&self) -> Result<u16> {
+        CollectionConfig::fee_bps(&self.collection_config, self.marketplace.fee)
+    }
+
+    /// Collection royalty owed on this sale (0 when the collection has none),
+    /// after checking the recipient account matches the collection config
+    pub fn collection_royalty(&self) -> Result<u64> {
+        CollectionConfig::royalty(&self.collection_config, self.royalty_recipient.as_deref(), self.listing.price)
+    }
+
+    /// Split the listing price into the marketplace fee and the maker's proceeds,
+    /// net of any creator and collection royalties
+    pub fn payment_split(&self, royalties: &[u64], collection_royalty: u64) -> Result<(u64, u64)> {
+        let (fee_amount, maker_amount) = split_payment(self.listing.price, self.fee_bps()?)?;
+        let royalty_total = royalties
+            .iter()
+            .try_fold(collection_royalty, |total, amount| total.checked_add(*amount))
+            .ok_or(MarketplaceError::MathOverflow)?;
+        let maker_amount = maker_amount
+            .checked_sub(royalty_total)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        Ok((fee_amount, maker_amount))
+    }
+
+    /// Require a sale escrow exactly when the marketplace holds proceeds for a dispute window
+    pub fn check_buyer_protection(&self) -> Result<()> {
+        self.marketplace.check_buyer_protection(self.sale_escrow.is_some())
+    }
+
+    /// Open the sale escrow for `maker_amount`, if buyer protection is on
+    pub fn open_sale_escrow(&mut self, maker_amount: u64, bumps: &PurchaseBumps) -> Result<()> {
+        if let (Some(sale_escrow), Some(bump)) = (self.sale_escrow.as_mut(), bumps.sale_escrow) {
+            sale_escrow.set_inner(SaleEscrow::new(
+                &self.marketplace,
+                self.listing.maker,
+                self.taker.key(),
+                self.maker_mint.key(),
+                maker_amount,
+                bump,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Add this sale to the marketplace's cumulative volume and count, and to the maker's stats
+    pub fn record_sale(&mut self, bumps: &PurchaseBumps) -> Result<()> {
         let price = self.listing.price;
-        let fee_amount = (price as u128)
-            .checked_mul(self.marketplace.fee as u128)
-            .unwrap()
-            .checked_div(10000)
-            .unwrap() as u64;
-        let maker_amount = price.checked_sub(fee_amount).unwrap();
+        self.marketplace.record_sale(price)?;
+
+        // A fresh stats account is zeroed; fill in who it belongs to
+        self.seller_stats.marketplace = self.marketplace.key();
+        self.seller_stats.seller = self.listing.maker;
+        self.seller_stats.bump = bumps.seller_stats;
+        self.seller_stats.record_sale(price)
+    }
 
+    /// Hand the collection floor to the candidate listing if this one held it
+    pub fn update_floor(&mut self) -> Result<()> {
+        let candidate = self.floor_candidate
+            .as_ref()
+            .map(|candidate| self.collection_stats.validate_candidate(candidate, self.listing.key()))
+            .transpose()?;
+        self.collection_stats.on_removed(self.listing.maker_mint, candidate);
+        Ok(())
+    }
+
+    /// Pay `amount` lamports to `to` from `source`, signing for the treasury when it pays
+    fn pay(&self, to: AccountInfo<'info>, amount: u64, source: PaymentSource) -> Result<()> {
+        let marketplace_key = self.marketplace.key();
+        let seeds = &[
+            b"treasury",
+            marketplace_key.as_ref(),
+            &[self.marketplace.treasury_bump]
+        ];
+        let treasury_signer = &[&seeds[..]];
+
+        let (from, signer_seeds): (AccountInfo<'info>, &[&[&[u8]]]) = match source {
+            PaymentSource::Taker => (self.taker.to_account_info(), &[]),
+            PaymentSource::Treasury => (self.treasury.to_account_info(), treasury_signer),
+        };
+        let cpi_accounts = Transfer { from, to };
+        transfer(CpiContext::new_with_signer(self.system_program.to_account_info(), cpi_accounts, signer_seeds), amount)
+    }
+
+    /// Transfer SOL from `source` to maker and treasury. A treasury-held
+    /// payment already includes the fee, so only the maker is paid.
+    pub fn send_sol(&mut self, fee_amount: u64, maker_amount: u64, source: PaymentSource) -> Result<()> {
         // Transfer fee to treasury
-        if fee_amount > 0 {
-            let cpi_program = self.system_program.to_account_info();
-            let cpi_accounts = Transfer {
-                from: self.taker.to_account_info(),
-                to: self.treasury.to_account_info(),
-            };
-            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-            transfer(cpi_ctx, fee_amount)?;
+        if fee_amount > 0 && source == PaymentSource::Taker {
+            self.pay(self.treasury.to_account_info(), fee_amount, source)?;
             msg!("Fee {} lamports transferred to treasury", fee_amount);
         }
 
-        // Transfer payment to maker
-        let cpi_program = self.system_program.to_account_info();
-        let cpi_accounts = Transfer {
-            from: self.taker.to_account_info(),
-            to: self.maker.to_account_info(),
+        // Transfer payment to maker, or hold it in the sale escrow
+        let recipient = match &self.sale_escrow {
+            Some(sale_escrow) => sale_escrow.to_account_info(),
+            None => self.maker.to_account_info(),
         };
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        transfer(cpi_ctx, maker_amount)?;
+        self.pay(recipient, maker_amount, source)?;
+
+        if self.sale_escrow.is_some() {
+            msg!("Payment {} lamports held in escrow for the dispute window", maker_amount);
+        } else {
+            msg!("Payment {} lamports transferred to maker", maker_amount);
+        }
+        Ok(())
+    }
+
+    /// Pay each creator their royalty from `source`, or record that royalties were skipped
+    pub fn pay_royalties(
+        &mut self,
+        creator_accounts: &[AccountInfo<'info>],
+        royalties: &[u64],
+        source: PaymentSource,
+    ) -> Result<()> {
+        if !self.marketplace.enforce_royalties {
+            emit!(RoyaltiesBypassed {
+                marketplace: self.marketplace.key(),
+                mint: self.maker_mint.key(),
+                price: self.listing.price,
+            });
+            return Ok(());
+        }
+
+        let creators = self.metadata.creators.as_deref().unwrap_or_default();
+        require!(creator_accounts.len() == creators.len(), MarketplaceError::InvalidCreatorAccounts);
+
+        for ((creator, account), amount) in creators.iter().zip(creator_accounts).zip(royalties) {
+            require_keys_eq!(creator.address, account.key(), MarketplaceError::InvalidCreatorAccounts);
+            if *amount == 0 {
+                continue;
+            }
 
-        msg!("Payment {} lamports transferred to maker", maker_amount);
+            self.pay(account.clone(), *amount, source)?;
+            msg!("Royalty {} lamports paid to creator {}", amount, creator.address);
+        }
+
+        Ok(())
+    }
+
+    /// Pay the collection royalty to its recipient from `source`
+    pub fn pay_collection_royalty(&mut self, amount: u64, source: PaymentSource) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        // `collection_royalty` required the recipient for any nonzero royalty
+        let recipient = self.royalty_recipient.as_ref().ok_or(MarketplaceError::InvalidRoyaltyRecipient)?;
+        self.pay(recipient.to_account_info(), amount, source)?;
+        msg!("Collection royalty {} lamports paid to {}", amount, recipient.key());
         Ok(())
     }
 
@@ -180,6 +494,32 @@ impl<'info> Purchase<'info> {
         ];
         let signer_seeds = &[&seeds[..]];
 
+        if is_programmable(&self.metadata) {
+            PnftTransfer {
+                metadata_program: self.metadata_program.as_ref(),
+                token: self.vault.as_ref(),
+                token_owner: self.listing.as_ref(),
+                destination_token: self.taker_ata.as_ref(),
+                destination_owner: self.taker.as_ref(),
+                mint: self.maker_mint.as_ref(),
+                metadata: self.metadata.as_ref(),
+                edition: self.master_edition.as_ref(),
+                authority: self.listing.as_ref(),
+                payer: self.taker.as_ref(),
+                system_program: self.system_program.as_ref(),
+                token_program: self.token_program.as_ref(),
+                associated_token_program: self.associated_token_program.as_ref(),
+                token_record: optional_info(&self.owner_token_record),
+                destination_token_record: optional_info(&self.destination_token_record),
+                sysvar_instructions: optional_info(&self.sysvar_instructions),
+                authorization_rules: optional_info(&self.authorization_rules),
+                authorization_rules_program: optional_info(&self.authorization_rules_program),
+            }.invoke_signed(signer_seeds)?;
+
+            msg!("pNFT transferred to taker");
+            return Ok(());
+        }
+
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
@@ -200,6 +540,12 @@ impl<'info> Purchase<'info> {
 
     /// Mint reward tokens to the taker
     pub fn receive_rewards(&mut self) -> Result<()> {
+        let reward_amount = self.marketplace.mintable_reward(PURCHASE_REWARD_AMOUNT, self.rewards_mint.supply);
+        if reward_amount == 0 {
+            msg!("Reward supply cap reached, no reward tokens minted");
+            return Ok(());
+        }
+
         let marketplace_name = self.marketplace.name.clone();
         let seeds = &[
             b"marketplace",
@@ -218,8 +564,6 @@ impl<'info> Purchase<'info> {
 
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer_seeds);
 
-        // Mint 10 reward tokens (with 6 decimals = 10_000_000)
-        let reward_amount = 10_000_000u64;
         mint_to(cpi_ctx, reward_amount)?;
 
         msg!("Reward tokens minted to taker");
@@ -228,6 +572,11 @@ impl<'info> Purchase<'info> {
 
     /// Close the vault account
     pub fn close_mint_vault(&mut self) -> Result<()> {
+        // Token Metadata may already have closed an emptied pNFT vault
+        if self.vault.to_account_info().lamports() == 0 {
+            return Ok(());
+        }
+
         let marketplace_key = self.marketplace.key();
         let maker_mint_key = self.maker_mint.key();
         let seeds = &[
@@ -0,0 +1,369 @@
+/**
+ * Purchase Signed Listing Context
+ * 
+ * Lazy listings: instead of escrowing the NFT in a listing account, the maker
+ * signs a `SignedListing` off-chain and approves the marketplace PDA as
+ * delegate of their token account. A buyer submits the signature in an
+ * Ed25519 program instruction placed right before `purchase_signed`, and the
+ * NFT is pulled straight from the maker's token account. Collection and
+ * creator checks that `list` would have made happen at purchase time, and the
+ * collection's fee (see `CollectionConfig::fee_bps`) and collection royalty
+ * apply as they do to `purchase`.
+ * 
+ * Each signed listing can be bought once: its nonce is consumed by creating a
+ * `ListingNonce` PDA. Makers cancel by revoking the delegate approval.
+ * 
+ * pNFT token accounts are frozen and signed sales don't pay creators, so
+ * signed listings are refused for pNFTs and on marketplaces that enforce
+ * royalties, and each sale emits `RoyaltiesBypassed`.
+ * 
+ * Buyer protection works as in `purchase`: with a dispute window, the maker's
+ * proceeds go to a sale escrow (see `state/sale_escrow.rs`).
+ */
+
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    metadata::{Metadata, MetadataAccount},
+    token::{mint_to, transfer_checked, MintTo, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::context::purchase::{split_payment, PURCHASE_REWARD_AMOUNT};
+use crate::state::{CollectionConfig, ListingNonce, Marketplace, SaleEscrow, SellerStats};
+use crate::error::MarketplaceError;
+use crate::ed25519::verify_ed25519_signature;
+use crate::events::RoyaltiesBypassed;
+use crate::pnft::is_programmable;
+
+/// Domain tag prefixed to every signed listing, so the signature can't be
+/// replayed as some other message
+pub const SIGNED_LISTING_DOMAIN: &[u8] = b"marketplace:signed_listing";
+
+/// Sale terms a maker signs off-chain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SignedListing {
+    /// The marketplace the listing is valid on
+    pub marketplace: Pubkey,
+    /// The wallet selling the NFT
+    pub maker: Pubkey,
+    /// The NFT being sold
+    pub maker_mint: Pubkey,
+    /// Sale price in lamports
+    pub price: u64,
+    /// Unix timestamp after which the listing can't be bought
+    pub expiry: i64,
+    /// Maker-chosen number that makes each listing single-use
+    pub nonce: u64,
+}
+
+impl SignedListing {
+    /// The exact bytes the maker signs
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message = SIGNED_LISTING_DOMAIN.to_vec();
+        self.serialize(&mut message)?;
+        Ok(message)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(listing: SignedListing)]
+pub struct PurchaseSigned<'info> {
+    /// The buyer (signer) purchasing the NFT
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    /// The seller who signed the listing
+    /// CHECK: Verified against the signed listing
+    #[account(
+        mut,
+        address = listing.maker @ MarketplaceError::InvalidMaker
+    )]
+    pub maker: UncheckedAccount<'info>,
+
+    /// The marketplace state account, updated with the sale totals
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+        constraint = marketplace.key() == listing.marketplace @ MarketplaceError::InvalidSignedListing,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT's mint address
+    #[account(address = listing.maker_mint @ MarketplaceError::InvalidSignedListing)]
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The maker's token account, delegated to the marketplace PDA
+    #[account(
+        mut,
+        associated_token::mint = maker_mint,
+        associated_token::authority = maker,
+        constraint = maker_ata.amount == 1 @ MarketplaceError::InsufficientTokens,
+        constraint = maker_ata.delegate.contains(&marketplace.key())
+            && maker_ata.delegated_amount >= 1 @ MarketplaceError::NftNotDelegated,
+    )]
+    pub maker_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The taker's ATA to receive the NFT
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = maker_mint,
+        associated_token::authority = taker,
+    )]
+    pub taker_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The taker's ATA to receive reward tokens
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = rewards_mint,
+        associated_token::authority = taker,
+    )]
+    pub taker_ata_reward: InterfaceAccount<'info, TokenAccount>,
+
+    /// Marks the signed listing as consumed; already existing means it was bought
+    #[account(
+        init,
+        payer = taker,
+        seeds = [b"listing_nonce", marketplace.key().as_ref(), maker.key().as_ref(), &listing.nonce.to_le_bytes()],
+        bump,
+        space = ListingNonce::INIT_SPACE,
+    )]
+    pub listing_nonce: Account<'info, ListingNonce>,
+
+    /// Holds the maker's proceeds for the dispute window (required exactly when
+    /// the marketplace has one)
+    #[account(
+        init,
+        payer = taker,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), maker_mint.key().as_ref(), taker.key().as_ref()],
+        bump,
+        space = SaleEscrow::INIT_SPACE,
+    )]
+    pub sale_escrow: Option<Account<'info, SaleEscrow>>,
+
+    /// The maker's sales record, created on their first sale
+    #[account(
+        init_if_needed,
+        payer = taker,
+        seeds = [b"seller_stats", marketplace.key().as_ref(), maker.key().as_ref()],
+        bump,
+        space = SellerStats::INIT_SPACE,
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+
+    /// The treasury PDA that receives the fee
+    #[account(
+        mut,
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
+    /// The rewards mint PDA used for minting reward tokens
+    #[account(
+        mut,
+        seeds = [b"rewards", marketplace.key().as_ref()],
+        bump = marketplace.rewards_bump,
+    )]
+    pub rewards_mint: InterfaceAccount<'info, Mint>,
+
+    /// Collection the NFT belongs to
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// The collection's config PDA (see `CollectionConfig::fee_bps`)
+    /// CHECK: Address is checked by seeds; read by `CollectionConfig::fee_bps`
+    #[account(
+        seeds = [b"collection_config", marketplace.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+    )]
+    pub collection_config: UncheckedAccount<'info>,
+
+    /// Receives the collection royalty (required when the collection config sets one)
+    /// CHECK: Checked against the collection config's `royalty_recipient`
+    #[account(mut)]
+    pub royalty_recipient: Option<UncheckedAccount<'info>>,
+
+    /// NFT metadata to verify collection and creators
+    #[account(
+        seeds = [
+            b"metadata",
+            metadata_program.key().as_ref(),
+            maker_mint.key().as_ref(),
+        ],
+        seeds::program = metadata_program.key(),
+        bump,
+        constraint = metadata.collection.as_ref().is_some_and(|collection| collection.key == collection_mint.key())
+            @ MarketplaceError::InvalidCollection,
+        constraint = metadata.collection.as_ref().is_some_and(|collection| collection.verified)
+            @ MarketplaceError::UnverifiedCollection,
+        constraint = !marketplace.require_verified_creator
+            || metadata.creators.as_ref().is_some_and(|creators| creators.iter().any(|creator| creator.verified))
+            @ MarketplaceError::UnverifiedCreator,
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    /// Instructions sysvar, to read back the maker's signature check
+    /// CHECK: Address is checked against the sysvar ID
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+
+    /// Metaplex program
+    pub metadata_program: Program<'info, Metadata>,
+    /// For creating ATAs
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> PurchaseSigned<'info> {
+    /// Check the listing terms and the maker's signature, and consume the nonce
+    pub fn verify_listing(&mut self, listing: &SignedListing, bumps: &PurchaseSignedBumps) -> Result<()> {
+        require!(
+            !self.marketplace.enforce_royalties && !is_programmable(&self.metadata),
+            MarketplaceError::SignedListingUnsupported
+        );
+        require!(listing.price > 0, MarketplaceError::InvalidPrice);
+        require!(Clock::get()?.unix_timestamp <= listing.expiry, MarketplaceError::SignedListingExpired);
+        verify_ed25519_signature(&self.sysvar_instructions, &listing.maker, &listing.message()?)?;
+        self.marketplace.check_buyer_protection(self.sale_escrow.is_some())?;
+
+        self.listing_nonce.bump = bumps.listing_nonce;
+        Ok(())
+    }
+
+    /// Record the sale on the marketplace and the maker's stats and split the price into
+    /// the fee, the collection royalty and the maker's proceeds, opening the sale escrow
+    /// for those proceeds if buyer protection is on
+    pub fn record_sale(&mut self, price: u64, bumps: &PurchaseSignedBumps) -> Result<(u64, u64, u64)> {
+        let fee_bps = CollectionConfig::fee_bps(&self.collection_config, self.marketplace.fee)?;
+        let (fee_amount, maker_amount) = split_payment(price, fee_bps)?;
+        let royalty = CollectionConfig::royalty(&self.collection_config, self.royalty_recipient.as_deref(), price)?;
+        let maker_amount = maker_amount.checked_sub(royalty).ok_or(MarketplaceError::MathOverflow)?;
+        self.marketplace.record_sale(price)?;
+
+        // A fresh stats account is zeroed; fill in who it belongs to
+        self.seller_stats.marketplace = self.marketplace.key();
+        self.seller_stats.seller = self.maker.key();
+        self.seller_stats.bump = bumps.seller_stats;
+        self.seller_stats.record_sale(price)?;
+
+        if let (Some(sale_escrow), Some(bump)) = (self.sale_escrow.as_mut(), bumps.sale_escrow) {
+            sale_escrow.set_inner(SaleEscrow::new(
+                &self.marketplace,
+                self.maker.key(),
+                self.taker.key(),
+                self.maker_mint.key(),
+                maker_amount,
+                bump,
+            )?);
+        }
+        Ok((fee_amount, royalty, maker_amount))
+    }
+
+    /// Transfer the NFT from maker to taker as the maker's approved delegate
+    pub fn receive_nft(&mut self) -> Result<()> {
+        let marketplace_name = self.marketplace.name.clone();
+        let seeds = &[
+            b"marketplace",
+            marketplace_name.as_str().as_bytes(),
+            &[self.marketplace.bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.maker_ata.to_account_info(),
+            mint: self.maker_mint.to_account_info(),
+            to: self.taker_ata.to_account_info(),
+            authority: self.marketplace.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+
+        // Transfer 1 NFT to taker
+        transfer_checked(cpi_ctx, 1, self.maker_mint.decimals)?;
+
+        msg!("NFT transferred to taker");
+        Ok(())
+    }
+
+    /// Transfer SOL from taker to treasury, collection royalty recipient and the maker or
+    /// sale escrow, and record that creator royalties were skipped
+    pub fn send_sol(&mut self, price: u64, fee_amount: u64, royalty: u64, maker_amount: u64) -> Result<()> {
+        if fee_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: self.taker.to_account_info(),
+                to: self.treasury.to_account_info(),
+            };
+            transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), fee_amount)?;
+            msg!("Fee {} lamports transferred to treasury", fee_amount);
+        }
+
+        if royalty > 0 {
+            // `CollectionConfig::royalty` required the recipient for any nonzero royalty
+            let recipient = self.royalty_recipient.as_ref().ok_or(MarketplaceError::InvalidRoyaltyRecipient)?;
+            let cpi_accounts = Transfer {
+                from: self.taker.to_account_info(),
+                to: recipient.to_account_info(),
+            };
+            transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), royalty)?;
+            msg!("Collection royalty {} lamports paid to {}", royalty, recipient.key());
+        }
+
+        let recipient = match &self.sale_escrow {
+            Some(sale_escrow) => sale_escrow.to_account_info(),
+            None => self.maker.to_account_info(),
+        };
+        let cpi_accounts = Transfer {
+            from: self.taker.to_account_info(),
+            to: recipient,
+        };
+        transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), maker_amount)?;
+        if self.sale_escrow.is_some() {
+            msg!("Payment {} lamports held in escrow for the dispute window", maker_amount);
+        } else {
+            msg!("Payment {} lamports transferred to maker", maker_amount);
+        }
+
+        emit!(RoyaltiesBypassed {
+            marketplace: self.marketplace.key(),
+            mint: self.maker_mint.key(),
+            price,
+        });
+        Ok(())
+    }
+
+    /// Mint reward tokens to the taker
+    pub fn receive_rewards(&mut self) -> Result<()> {
+        let reward_amount = self.marketplace.mintable_reward(PURCHASE_REWARD_AMOUNT, self.rewards_mint.supply);
+        if reward_amount == 0 {
+            msg!("Reward supply cap reached, no reward tokens minted");
+            return Ok(());
+        }
+
+        let marketplace_name = self.marketplace.name.clone();
+        let seeds = &[
+            b"marketplace",
+            marketplace_name.as_str().as_bytes(),
+            &[self.marketplace.bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: self.rewards_mint.to_account_info(),
+            to: self.taker_ata_reward.to_account_info(),
+            authority: self.marketplace.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+
+        mint_to(cpi_ctx, reward_amount)?;
+
+        msg!("Reward tokens minted to taker");
+        Ok(())
+    }
+}
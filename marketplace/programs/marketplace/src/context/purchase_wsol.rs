@@ -0,0 +1,115 @@
+/**
+ * Purchase With Wrapped SOL Context
+ *
+ * Lets a buyer holding wrapped SOL pay for a listing from their WSOL token
+ * account. Everything but the payment is the regular `Purchase` (fee
+ * override, royalties, buyer protection, pNFTs, rewards), and the maker is
+ * still paid in native SOL.
+ *
+ * The price is moved into a temporary WSOL account owned by the treasury,
+ * which is closed straight away: closing a native token account unwraps it,
+ * so the treasury receives the price as lamports. The treasury keeps the fee,
+ * pays the maker (or sale escrow) and creators, and refunds the temporary
+ * account's rent to the taker.
+ */
+
+use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
+use anchor_spl::{
+    token::spl_token::native_mint,
+    token_interface::{
+        close_account, sync_native, transfer_checked, CloseAccount, Mint, SyncNative, TokenAccount,
+        TokenInterface, TransferChecked,
+    },
+};
+
+use crate::context::purchase::*;
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct PurchaseWsol<'info> {
+    /// The regular purchase accounts
+    pub purchase: Purchase<'info>,
+
+    /// The taker's wrapped SOL account paying the price
+    #[account(
+        mut,
+        token::mint = native_mint,
+        token::authority = purchase.taker,
+    )]
+    pub taker_wsol: InterfaceAccount<'info, TokenAccount>,
+
+    /// Temporary account the payment is unwrapped through, closed within the instruction
+    #[account(
+        init,
+        payer = purchase.taker,
+        seeds = [b"wsol_unwrap", purchase.listing.key().as_ref()],
+        bump,
+        token::mint = native_mint,
+        token::authority = purchase.treasury,
+    )]
+    pub wsol_unwrap: InterfaceAccount<'info, TokenAccount>,
+
+    /// The wrapped SOL mint
+    #[account(address = native_mint::ID)]
+    pub native_mint: InterfaceAccount<'info, Mint>,
+
+    /// For creating the temporary account
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> PurchaseWsol<'info> {
+    /// Unwrap the listing price from the taker's WSOL account into the treasury
+    /// as lamports, refunding the temporary account's rent to the taker
+    pub fn unwrap_payment(&mut self) -> Result<()> {
+        let price = self.purchase.listing.price;
+
+        // Count lamports wrapped without a sync_native as part of the balance
+        sync_native(CpiContext::new(
+            self.token_program.to_account_info(),
+            SyncNative { account: self.taker_wsol.to_account_info() },
+        ))?;
+        self.taker_wsol.reload()?;
+        require!(self.taker_wsol.amount >= price, MarketplaceError::InsufficientFunds);
+
+        let rent = self.wsol_unwrap.to_account_info().lamports();
+
+        let cpi_accounts = TransferChecked {
+            from: self.taker_wsol.to_account_info(),
+            mint: self.native_mint.to_account_info(),
+            to: self.wsol_unwrap.to_account_info(),
+            authority: self.purchase.taker.to_account_info(),
+        };
+        transfer_checked(
+            CpiContext::new(self.token_program.to_account_info(), cpi_accounts),
+            price,
+            self.native_mint.decimals,
+        )?;
+
+        let marketplace_key = self.purchase.marketplace.key();
+        let seeds = &[
+            b"treasury",
+            marketplace_key.as_ref(),
+            &[self.purchase.marketplace.treasury_bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // Closing the native account hands its lamports, price and rent, to the treasury
+        let cpi_accounts = CloseAccount {
+            account: self.wsol_unwrap.to_account_info(),
+            destination: self.purchase.treasury.to_account_info(),
+            authority: self.purchase.treasury.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds))?;
+
+        let cpi_accounts = Transfer {
+            from: self.purchase.treasury.to_account_info(),
+            to: self.purchase.taker.to_account_info(),
+        };
+        transfer(CpiContext::new_with_signer(self.system_program.to_account_info(), cpi_accounts, signer_seeds), rent)?;
+
+        msg!("Unwrapped {} lamports of WSOL into the treasury", price);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+/**
+ * Raise Dispute Context
+ *
+ * Lets the buyer of a protected sale freeze the escrowed proceeds while the
+ * dispute window is open. The buyer hands the NFT back into a dispute vault
+ * owned by the sale escrow, so whichever way the admin settles the dispute
+ * through `resolve_dispute`, the NFT and the proceeds go to different
+ * parties. pNFT token accounts are frozen, so pNFT sales can't be disputed.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::{transfer_checked, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::state::{Marketplace, SaleEscrow};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    /// The buyer disputing the sale, paying the dispute vault's rent
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// The marketplace the sale happened on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT that was sold
+    #[account(address = sale_escrow.maker_mint @ MarketplaceError::SaleEscrowMismatch)]
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The buyer's token account holding the NFT
+    #[account(
+        mut,
+        associated_token::mint = maker_mint,
+        associated_token::authority = buyer,
+        constraint = !buyer_ata.is_frozen() @ MarketplaceError::DisputeNftFrozen,
+    )]
+    pub buyer_ata: InterfaceAccount<'info, TokenAccount>,
+
+    /// The sale escrow PDA to freeze
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), sale_escrow.maker_mint.as_ref(), buyer.key().as_ref()],
+        bump = sale_escrow.bump,
+    )]
+    pub sale_escrow: Account<'info, SaleEscrow>,
+
+    /// Holds the NFT until the dispute is resolved
+    #[account(
+        init,
+        payer = buyer,
+        associated_token::mint = maker_mint,
+        associated_token::authority = sale_escrow,
+    )]
+    pub dispute_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// For creating the dispute vault
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> RaiseDispute<'info> {
+    /// Freeze the proceeds for arbitration and escrow the NFT alongside them
+    pub fn dispute(&mut self) -> Result<()> {
+        self.sale_escrow.ensure_undisputed()?;
+        require!(
+            Clock::get()?.unix_timestamp < self.sale_escrow.release_at,
+            MarketplaceError::DisputeWindowClosed
+        );
+        require!(self.buyer_ata.amount == 1, MarketplaceError::InsufficientTokens);
+
+        self.sale_escrow.disputed = true;
+
+        let cpi_accounts = TransferChecked {
+            from: self.buyer_ata.to_account_info(),
+            mint: self.maker_mint.to_account_info(),
+            to: self.dispute_vault.to_account_info(),
+            authority: self.buyer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer_checked(cpi_ctx, 1, self.maker_mint.decimals)
+    }
+}
@@ -0,0 +1,70 @@
+/**
+ * Refresh Floor Context
+ * 
+ * Permissionless repair of a collection's floor price. If the recorded floor
+ * listing was closed, the floor is cleared. If it was relisted, its current
+ * price is picked up. An optional candidate listing then takes over when it
+ * is cheaper, which restores a floor cleared because its listing left the
+ * book without a candidate.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{CollectionStats, Listing, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct RefreshFloor<'info> {
+    /// The marketplace the collection trades on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// Floor price tracking for the collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_stats.collection_mint.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// The listing PDA of the recorded floor mint, which may have been closed
+    /// CHECK: Address is checked against the floor mint's listing PDA
+    pub floor_listing: UncheckedAccount<'info>,
+
+    /// A listing of the collection to take over as floor if it is cheaper
+    pub floor_candidate: Option<Account<'info, Listing>>,
+}
+
+impl<'info> RefreshFloor<'info> {
+    /// Drop or re-price a stale floor, then consider the candidate
+    pub fn refresh(&mut self) -> Result<()> {
+        let stats = &mut self.collection_stats;
+
+        if stats.has_floor() {
+            let (expected, _) = Pubkey::find_program_address(
+                &[stats.marketplace.as_ref(), stats.floor_mint.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(self.floor_listing.key(), expected, MarketplaceError::InvalidFloorCandidate);
+
+            if self.floor_listing.data_is_empty() {
+                let floor_mint = stats.floor_mint;
+                stats.on_removed(floor_mint, None);
+            } else {
+                let data = self.floor_listing.try_borrow_data()?;
+                let listing = Listing::try_deserialize(&mut &data[..])?;
+                stats.floor_price = listing.price;
+            }
+        }
+
+        if let Some(candidate) = &self.floor_candidate {
+            let (mint, price) = stats.validate_candidate(candidate, Pubkey::default())?;
+            stats.on_listed(mint, price);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+/**
+ * Release Sale Context
+ * 
+ * Pays a protected sale's escrowed proceeds to the maker once the dispute
+ * window has passed without a dispute. Anyone may crank it. The escrow's
+ * rent goes back to the buyer who paid it.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{Marketplace, SaleEscrow};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct ReleaseSale<'info> {
+    /// The seller receiving the proceeds
+    /// CHECK: Verified against the sale escrow
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    /// The buyer, refunded the escrow's rent
+    /// CHECK: Verified against the sale escrow
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// The marketplace the sale happened on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The sale escrow PDA, closed back to the buyer
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), sale_escrow.maker_mint.as_ref(), buyer.key().as_ref()],
+        bump = sale_escrow.bump,
+        has_one = maker @ MarketplaceError::InvalidMaker,
+        has_one = buyer @ MarketplaceError::Unauthorized,
+        close = buyer,
+    )]
+    pub sale_escrow: Account<'info, SaleEscrow>,
+}
+
+impl<'info> ReleaseSale<'info> {
+    /// Pay the maker once the dispute window has ended undisputed
+    pub fn release(&mut self) -> Result<()> {
+        self.sale_escrow.ensure_undisputed()?;
+        require!(
+            Clock::get()?.unix_timestamp >= self.sale_escrow.release_at,
+            MarketplaceError::DisputeWindowOpen
+        );
+
+        SaleEscrow::pay_out(&self.sale_escrow, &self.maker)
+    }
+}
@@ -0,0 +1,84 @@
+/**
+ * Relist NFT Context
+ * 
+ * Fast path for a maker who changes their mind about a live listing's price.
+ * The NFT stays in the listing's vault: only the listing account and the
+ * collection's floor price are updated, with no token transfer and no
+ * listing fee.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+
+use crate::state::{CollectionStats, Listing, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct Relist<'info> {
+    /// The signer who originally listed the NFT
+    #[account(
+        constraint = maker.key() == listing.maker @ MarketplaceError::Unauthorized
+    )]
+    pub maker: Signer<'info>,
+
+    /// The marketplace state account
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT's mint address
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The vault still holding the escrowed NFT
+    #[account(
+        associated_token::mint = maker_mint,
+        associated_token::authority = listing,
+        constraint = vault.amount == 1 @ MarketplaceError::EmptyVault,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The listing PDA, updated in place
+    #[account(
+        mut,
+        seeds = [marketplace.key().as_ref(), maker_mint.key().as_ref()],
+        bump = listing.bump,
+    )]
+    pub listing: Account<'info, Listing>,
+
+    /// Floor price tracking for the listing's collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Next-cheapest listing of the collection, taking over the floor if this
+    /// listing held it and its new price is higher
+    pub floor_candidate: Option<Account<'info, Listing>>,
+}
+
+impl<'info> Relist<'info> {
+    /// Set the listing's new price
+    pub fn reprice(&mut self, new_price: u64) -> Result<()> {
+        require!(new_price > 0, MarketplaceError::InvalidPrice);
+        require!(self.listing.dutch_auction.is_none(), MarketplaceError::DutchAuctionListing);
+        require!(self.listing.english_auction.is_none(), MarketplaceError::EnglishAuctionListing);
+        self.listing.price = new_price;
+        Ok(())
+    }
+
+    /// Re-rank the listing at its new price: it leaves the floor (handing it
+    /// to the candidate, if any) and re-enters at the new price
+    pub fn update_floor(&mut self) -> Result<()> {
+        let candidate = self.floor_candidate
+            .as_ref()
+            .map(|candidate| self.collection_stats.validate_candidate(candidate, self.listing.key()))
+            .transpose()?;
+        self.collection_stats.on_removed(self.listing.maker_mint, candidate);
+        self.collection_stats.on_listed(self.listing.maker_mint, self.listing.price);
+        Ok(())
+    }
+}
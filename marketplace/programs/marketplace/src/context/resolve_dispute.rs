@@ -0,0 +1,170 @@
+/**
+ * Resolve Dispute Context
+ * 
+ * Lets the marketplace admin settle a disputed sale, either refunding the
+ * escrowed proceeds to the buyer or releasing them to the maker. The NFT,
+ * held in the dispute vault since `raise_dispute`, goes the other way: back
+ * to the maker on a refund, or back to the buyer otherwise, so a refunded
+ * buyer can't keep the NFT. The admin pays for the recipient's token account
+ * if it was closed. The rent of the escrow and the dispute vault goes back
+ * to the buyer either way. An upheld dispute counts against the maker's
+ * reputation.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{create, get_associated_token_address_with_program_id, AssociatedToken, Create},
+    token::{close_account, transfer_checked, CloseAccount, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::state::{Marketplace, SaleEscrow, SellerStats};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    /// The marketplace admin arbitrating the dispute
+    #[account(
+        mut,
+        constraint = admin.key() == marketplace.admin @ MarketplaceError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    /// The seller, paid if the dispute is rejected
+    /// CHECK: Verified against the sale escrow
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    /// The buyer, refunded if the dispute is upheld
+    /// CHECK: Verified against the sale escrow
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// The marketplace the sale happened on
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The disputed sale escrow PDA, closed back to the buyer
+    #[account(
+        mut,
+        seeds = [b"sale_escrow", marketplace.key().as_ref(), sale_escrow.maker_mint.as_ref(), buyer.key().as_ref()],
+        bump = sale_escrow.bump,
+        has_one = maker @ MarketplaceError::InvalidMaker,
+        has_one = buyer @ MarketplaceError::Unauthorized,
+        constraint = sale_escrow.disputed @ MarketplaceError::SaleNotDisputed,
+        close = buyer,
+    )]
+    pub sale_escrow: Account<'info, SaleEscrow>,
+
+    /// The NFT that was sold
+    #[account(address = sale_escrow.maker_mint @ MarketplaceError::SaleEscrowMismatch)]
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// Holds the NFT handed back by the buyer, closed back to the buyer
+    #[account(
+        mut,
+        associated_token::mint = maker_mint,
+        associated_token::authority = sale_escrow,
+    )]
+    pub dispute_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token account of whoever gets the NFT: the maker's on a refund,
+    /// the buyer's otherwise
+    /// CHECK: Checked against the recipient's ATA, and created if missing
+    #[account(mut)]
+    pub recipient_ata: UncheckedAccount<'info>,
+
+    /// The maker's sales record, charged with upheld disputes
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [b"seller_stats", marketplace.key().as_ref(), maker.key().as_ref()],
+        bump,
+        space = SellerStats::INIT_SPACE,
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+
+    /// For creating the recipient's ATA
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating the seller stats
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> ResolveDispute<'info> {
+    /// Pay the proceeds to the buyer (`refund_buyer`) or the maker, and the
+    /// NFT to the other party
+    pub fn resolve(&mut self, refund_buyer: bool, bumps: &ResolveDisputeBumps) -> Result<()> {
+        // A fresh stats account is zeroed; fill in who it belongs to
+        self.seller_stats.marketplace = self.marketplace.key();
+        self.seller_stats.seller = self.maker.key();
+        self.seller_stats.bump = bumps.seller_stats;
+
+        if refund_buyer {
+            self.seller_stats.record_dispute()?;
+        }
+
+        let nft_recipient = if refund_buyer { &self.maker } else { &self.buyer };
+        self.return_nft(nft_recipient.to_account_info())?;
+
+        // Moved by hand, so after the token CPIs above
+        let recipient = if refund_buyer { &self.buyer } else { &self.maker };
+        SaleEscrow::pay_out(&self.sale_escrow, recipient)
+    }
+
+    /// Move the NFT from the dispute vault to `owner` and close the vault
+    fn return_nft(&self, owner: AccountInfo<'info>) -> Result<()> {
+        let token_program_key = self.token_program.key();
+        require_keys_eq!(
+            self.recipient_ata.key(),
+            get_associated_token_address_with_program_id(&owner.key(), &self.maker_mint.key(), &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        if self.recipient_ata.data_is_empty() {
+            create(CpiContext::new(
+                self.associated_token_program.to_account_info(),
+                Create {
+                    payer: self.admin.to_account_info(),
+                    associated_token: self.recipient_ata.to_account_info(),
+                    authority: owner,
+                    mint: self.maker_mint.to_account_info(),
+                    system_program: self.system_program.to_account_info(),
+                    token_program: self.token_program.to_account_info(),
+                },
+            ))?;
+        }
+
+        let marketplace_key = self.marketplace.key();
+        let maker_mint_key = self.maker_mint.key();
+        let buyer_key = self.buyer.key();
+        let seeds = &[
+            b"sale_escrow",
+            marketplace_key.as_ref(),
+            maker_mint_key.as_ref(),
+            buyer_key.as_ref(),
+            &[self.sale_escrow.bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.dispute_vault.to_account_info(),
+            mint: self.maker_mint.to_account_info(),
+            to: self.recipient_ata.to_account_info(),
+            authority: self.sale_escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        transfer_checked(cpi_ctx, 1, self.maker_mint.decimals)?;
+
+        let cpi_accounts = CloseAccount {
+            account: self.dispute_vault.to_account_info(),
+            destination: self.buyer.to_account_info(),
+            authority: self.sale_escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        close_account(cpi_ctx)
+    }
+}
@@ -0,0 +1,317 @@
+/**
+ * Sell To Best Offer Context
+ *
+ * Lets a seller accept the highest outstanding offer on their listed NFT in
+ * one call. Offers are passed through `remaining_accounts` as groups of three
+ * accounts: `[offer, bidder, bidder_ata]`. Offers that were cancelled, belong
+ * to another NFT or marketplace, or come from someone other than a private
+ * listing's allowed buyer are ignored; if none is left the sale reverts.
+ *
+ * The winning bid is paid out of its offer PDA: the marketplace fee (see
+ * `CollectionConfig::fee_bps`) to the treasury, the collection royalty, if
+ * the config sets one, to its recipient and the rest to the seller, or into
+ * a sale escrow for the bidder on marketplaces with a dispute window. The
+ * seller pays that escrow's rent as they do the bidder's ATA. The offer is
+ * then closed back to the bidder. Like sweeps, offer sales don't load
+ * metadata, so they are refused on marketplaces that enforce royalties and
+ * emit `RoyaltiesBypassed`. pNFT vaults are frozen; sell those through
+ * `purchase`. English auction listings are sold to their highest bidder
+ * instead. The collection floor is updated as in `purchase`.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{create, get_associated_token_address_with_program_id, AssociatedToken, Create},
+    token::{close_account, transfer_checked, CloseAccount, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::context::purchase::split_payment;
+use crate::state::{CollectionConfig, CollectionStats, Listing, Marketplace, Offer, SaleEscrow, SellerStats};
+use crate::error::MarketplaceError;
+use crate::events::RoyaltiesBypassed;
+
+/// Number of remaining accounts supplied per offer
+pub const OFFER_ACCOUNTS_PER_OFFER: usize = 3;
+
+/// Upper bound on offers compared in one call. Each offer's three accounts
+/// take ~96 bytes of the 1232-byte transaction.
+pub const MAX_OFFERS: usize = 8;
+
+#[derive(Accounts)]
+pub struct SellToBestOffer<'info> {
+    /// The seller who listed the NFT
+    #[account(
+        mut,
+        constraint = maker.key() == listing.maker @ MarketplaceError::InvalidMaker
+    )]
+    pub maker: Signer<'info>,
+
+    /// The marketplace state account, updated with the sale totals
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The NFT's mint address
+    pub maker_mint: InterfaceAccount<'info, Mint>,
+
+    /// The listing PDA, closed back to the maker after the sale
+    #[account(
+        mut,
+        seeds = [marketplace.key().as_ref(), maker_mint.key().as_ref()],
+        bump = listing.bump,
+        close = maker,
+    )]
+    pub listing: Account<'info, Listing>,
+
+    /// The vault holding the NFT
+    #[account(
+        mut,
+        associated_token::mint = maker_mint,
+        associated_token::authority = listing,
+        constraint = vault.amount == 1 @ MarketplaceError::EmptyVault,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The treasury PDA that receives the fee
+    #[account(
+        mut,
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
+    /// The maker's sales record, created on their first sale
+    #[account(
+        init_if_needed,
+        payer = maker,
+        seeds = [b"seller_stats", marketplace.key().as_ref(), maker.key().as_ref()],
+        bump,
+        space = SellerStats::INIT_SPACE,
+    )]
+    pub seller_stats: Account<'info, SellerStats>,
+
+    /// Floor price tracking for the listing's collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Next-cheapest listing of the collection, taking over the floor if this listing held it
+    pub floor_candidate: Option<Account<'info, Listing>>,
+
+    /// The listed collection's config PDA (see `CollectionConfig::fee_bps`)
+    /// CHECK: Address is checked by seeds; read by `CollectionConfig::fee_bps`
+    #[account(
+        seeds = [b"collection_config", marketplace.key().as_ref(), listing.collection.as_ref()],
+        bump,
+    )]
+    pub collection_config: UncheckedAccount<'info>,
+
+    /// Receives the collection royalty (required when the collection config sets one)
+    /// CHECK: Checked against the collection config's `royalty_recipient`
+    #[account(mut)]
+    pub royalty_recipient: Option<UncheckedAccount<'info>>,
+
+    /// Holds the proceeds for the dispute window, required exactly when the marketplace has one.
+    /// The bidder is only known once the best offer is picked, so it is created in the handler.
+    /// CHECK: Checked against the sale escrow PDA for the mint and winning bidder when created
+    #[account(mut)]
+    pub sale_escrow: Option<UncheckedAccount<'info>>,
+
+    /// For creating the bidder's ATA
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> SellToBestOffer<'info> {
+    /// Accept the highest valid offer among `remaining_accounts`
+    pub fn sell(&mut self, remaining_accounts: &'info [AccountInfo<'info>], bumps: &SellToBestOfferBumps) -> Result<()> {
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts.len().is_multiple_of(OFFER_ACCOUNTS_PER_OFFER),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require!(
+            remaining_accounts.len() / OFFER_ACCOUNTS_PER_OFFER <= MAX_OFFERS,
+            MarketplaceError::BatchTooLarge
+        );
+        require!(!self.marketplace.enforce_royalties, MarketplaceError::OfferRoyaltiesUnsupported);
+        self.marketplace.check_buyer_protection(self.sale_escrow.is_some())?;
+        require!(self.listing.english_auction.is_none(), MarketplaceError::EnglishAuctionListing);
+
+        // Ties go to the earliest offer passed in
+        let mut best: Option<(Account<'info, Offer>, &'info [AccountInfo<'info>])> = None;
+        for group in remaining_accounts.chunks(OFFER_ACCOUNTS_PER_OFFER) {
+            let Some(offer) = self.live_offer(&group[0], &group[1])? else {
+                msg!("Skipping offer {}: not valid for this listing", group[0].key());
+                continue;
+            };
+
+            if best.as_ref().is_none_or(|(current, _)| offer.amount > current.amount) {
+                best = Some((offer, group));
+            }
+        }
+
+        let (offer, group) = best.ok_or(MarketplaceError::NoValidOffers)?;
+        self.accept(offer, &group[1], &group[2], bumps.seller_stats)
+    }
+
+    /// Load the offer if it is a live bid on this listing, or `None` to ignore it
+    fn live_offer(
+        &self,
+        offer_info: &'info AccountInfo<'info>,
+        bidder_info: &'info AccountInfo<'info>,
+    ) -> Result<Option<Account<'info, Offer>>> {
+        // Cancelled or accepted since the caller fetched it
+        if offer_info.owner != &crate::ID || offer_info.data_is_empty() {
+            return Ok(None);
+        }
+
+        let Ok(offer) = Account::<'info, Offer>::try_from(offer_info) else {
+            return Ok(None);
+        };
+
+        let marketplace_key = self.marketplace.key();
+        let expected_offer = Pubkey::create_program_address(
+            &[b"offer", marketplace_key.as_ref(), offer.maker_mint.as_ref(), offer.bidder.as_ref(), &[offer.bump]],
+            &crate::ID,
+        );
+
+        if expected_offer != Ok(offer.key())
+            || offer.marketplace != marketplace_key
+            || offer.maker_mint != self.maker_mint.key()
+            || offer.bidder != bidder_info.key()
+            || self.listing.allowed_buyer.is_some_and(|buyer| buyer != offer.bidder)
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(offer))
+    }
+
+    /// Settle the sale of the listed NFT to `offer`'s bidder
+    /// Hand the collection floor to the candidate listing if this one held it
+    fn update_floor(&mut self) -> Result<()> {
+        let candidate = self.floor_candidate
+            .as_ref()
+            .map(|candidate| self.collection_stats.validate_candidate(candidate, self.listing.key()))
+            .transpose()?;
+        self.collection_stats.on_removed(self.listing.maker_mint, candidate);
+        Ok(())
+    }
+
+    fn accept(
+        &mut self,
+        offer: Account<'info, Offer>,
+        bidder_info: &'info AccountInfo<'info>,
+        bidder_ata_info: &'info AccountInfo<'info>,
+        seller_stats_bump: u8,
+    ) -> Result<()> {
+        let maker_mint_key = self.maker_mint.key();
+        require_keys_eq!(
+            bidder_ata_info.key(),
+            get_associated_token_address_with_program_id(&bidder_info.key(), &maker_mint_key, &self.token_program.key()),
+            MarketplaceError::InvalidBatchAccounts
+        );
+
+        let price = offer.amount;
+        let fee_bps = CollectionConfig::fee_bps(&self.collection_config, self.marketplace.fee)?;
+        let (fee_amount, maker_amount) = split_payment(price, fee_bps)?;
+        let royalty = CollectionConfig::royalty(&self.collection_config, self.royalty_recipient.as_deref(), price)?;
+        let maker_amount = maker_amount.checked_sub(royalty).ok_or(MarketplaceError::MathOverflow)?;
+        self.marketplace.record_sale(price)?;
+        self.update_floor()?;
+
+        // A fresh stats account is zeroed; fill in who it belongs to
+        self.seller_stats.marketplace = self.marketplace.key();
+        self.seller_stats.seller = self.maker.key();
+        self.seller_stats.bump = seller_stats_bump;
+        self.seller_stats.record_sale(price)?;
+
+        // The seller pays for the bidder's ATA when it doesn't exist yet
+        if bidder_ata_info.data_is_empty() {
+            create(CpiContext::new(
+                self.associated_token_program.to_account_info(),
+                Create {
+                    payer: self.maker.to_account_info(),
+                    associated_token: bidder_ata_info.clone(),
+                    authority: bidder_info.clone(),
+                    mint: self.maker_mint.to_account_info(),
+                    system_program: self.system_program.to_account_info(),
+                    token_program: self.token_program.to_account_info(),
+                },
+            ))?;
+        }
+
+        let marketplace_key = self.marketplace.key();
+        let seeds = &[
+            marketplace_key.as_ref(),
+            maker_mint_key.as_ref(),
+            &[self.listing.bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        // Transfer 1 NFT to the bidder
+        let cpi_accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.maker_mint.to_account_info(),
+            to: bidder_ata_info.clone(),
+            authority: self.listing.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        transfer_checked(cpi_ctx, 1, self.maker_mint.decimals)?;
+
+        // Close the vault, refunding rent to the maker who paid for the bidder's ATA
+        let cpi_accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: self.maker.to_account_info(),
+            authority: self.listing.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        close_account(cpi_ctx)?;
+
+        // Open the escrow before moving lamports by hand, which must balance across CPIs
+        if let Some(sale_escrow) = &self.sale_escrow {
+            let sale = SaleEscrow::new(&self.marketplace, self.maker.key(), offer.bidder, maker_mint_key, maker_amount, 0)?;
+            SaleEscrow::create(sale_escrow, &self.maker.to_account_info(), &self.system_program.to_account_info(), sale)?;
+        }
+
+        // The offer PDA holds the bid, so pay out of it directly
+        if fee_amount > 0 {
+            offer.sub_lamports(fee_amount)?;
+            self.treasury.add_lamports(fee_amount)?;
+        }
+        if royalty > 0 {
+            // `CollectionConfig::royalty` required the recipient for any nonzero royalty
+            let recipient = self.royalty_recipient.as_ref().ok_or(MarketplaceError::InvalidRoyaltyRecipient)?;
+            offer.sub_lamports(royalty)?;
+            recipient.add_lamports(royalty)?;
+        }
+        offer.sub_lamports(maker_amount)?;
+        match &self.sale_escrow {
+            Some(sale_escrow) => { sale_escrow.add_lamports(maker_amount)?; }
+            None => { self.maker.add_lamports(maker_amount)?; }
+        }
+
+        // Close the offer, refunding its rent to the bidder
+        offer.close(bidder_info.clone())?;
+
+        emit!(RoyaltiesBypassed {
+            marketplace: marketplace_key,
+            mint: maker_mint_key,
+            price,
+        });
+
+        msg!("NFT {} sold to {} for {} lamports", maker_mint_key, bidder_info.key(), price);
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+/**
+ * Set Collection Fee Context
+ * 
+ * Allows the marketplace admin to override the marketplace fee for one
+ * collection. The collection config PDA is created on first use and
+ * updated in place afterwards.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{CollectionConfig, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+#[instruction(collection_mint: Pubkey)]
+pub struct SetCollectionFee<'info> {
+    /// The marketplace admin, paying for the config account on first use
+    #[account(
+        mut,
+        constraint = admin.key() == marketplace.admin @ MarketplaceError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    /// The marketplace state account
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The collection's config PDA holding the override
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [b"collection_config", marketplace.key().as_ref(), collection_mint.as_ref()],
+        bump,
+        space = CollectionConfig::INIT_SPACE,
+    )]
+    pub collection_config: Account<'info, CollectionConfig>,
+
+    /// For creating the config account
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SetCollectionFee<'info> {
+    /// Store `fee_bps` as the collection's fee override
+    pub fn set_fee(&mut self, collection_mint: Pubkey, fee_bps: u16, bumps: &SetCollectionFeeBumps) -> Result<()> {
+        require!(fee_bps <= 10000, MarketplaceError::InvalidFee);
+
+        // Keep any collection royalty already configured
+        let config = &mut self.collection_config;
+        config.marketplace = self.marketplace.key();
+        config.collection_mint = collection_mint;
+        config.fee_bps = Some(fee_bps);
+        config.bump = bumps.collection_config;
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+/**
+ * Set Collection Royalty Context
+ * 
+ * Allows the marketplace admin to route a royalty on every sale of a
+ * collection to a recipient of their choosing, for collections whose
+ * metadata has no creators to pay. Shares the collection config PDA with
+ * `set_collection_fee`, created on first use by either.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::{CollectionConfig, Marketplace};
+use crate::error::MarketplaceError;
+
+#[derive(Accounts)]
+#[instruction(collection_mint: Pubkey)]
+pub struct SetCollectionRoyalty<'info> {
+    /// The marketplace admin, paying for the config account on first use
+    #[account(
+        mut,
+        constraint = admin.key() == marketplace.admin @ MarketplaceError::Unauthorized
+    )]
+    pub admin: Signer<'info>,
+
+    /// The marketplace state account
+    #[account(
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The collection's config PDA holding the royalty
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [b"collection_config", marketplace.key().as_ref(), collection_mint.as_ref()],
+        bump,
+        space = CollectionConfig::INIT_SPACE,
+    )]
+    pub collection_config: Account<'info, CollectionConfig>,
+
+    /// For creating the config account
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SetCollectionRoyalty<'info> {
+    /// Store `royalty_bps` of each sale as owed to `royalty_recipient`
+    pub fn set_royalty(
+        &mut self,
+        collection_mint: Pubkey,
+        royalty_bps: u16,
+        royalty_recipient: Pubkey,
+        bumps: &SetCollectionRoyaltyBumps,
+    ) -> Result<()> {
+        require!(royalty_bps <= 10000, MarketplaceError::InvalidRoyalty);
+
+        // Keep any fee override already configured
+        let config = &mut self.collection_config;
+        config.marketplace = self.marketplace.key();
+        config.collection_mint = collection_mint;
+        config.royalty_bps = royalty_bps;
+        config.royalty_recipient = royalty_recipient;
+        config.bump = bumps.collection_config;
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+/**
+ * Sweep Context
+ *
+ * Buys the cheapest listings of a collection in one transaction, charging
+ * the fee from `CollectionConfig::fee_bps` and paying its collection royalty
+ * if its config sets one. Each listing is passed through
+ * `remaining_accounts` as a group of seven accounts: `[listing, vault,
+ * maker_mint, maker, taker_ata, sale_escrow, seller_stats]`, sorted by
+ * ascending price. `sale_escrow` is the buyer protection PDA for the mint and
+ * taker; on marketplaces with a dispute window it is created, as `purchase`
+ * does, and holds the maker's proceeds (see `state/sale_escrow.rs`).
+ * `seller_stats` is the maker's stats PDA, created on their first sale and
+ * credited with each sale (see `state/seller_stats.rs`). Listings are
+ * bought in order until the next one would exceed the spend budget. Listings
+ * that were bought or delisted since the caller fetched them (or that are
+ * private to another buyer, Dutch or English auctions, or made by an account
+ * `purchase` can't pay, see `MakerNotSystemOwned`) are skipped rather than
+ * failing the sweep. pNFT vaults are frozen and are skipped too; buy them
+ * with `purchase`. The collection floor is updated for the bought listings,
+ * with `floor_candidate` taking over as in `purchase`.
+ *
+ * Sweeps don't load metadata, so they can't pay creators: they are refused on
+ * marketplaces that enforce royalties and emit `RoyaltiesBypassed` per sale.
+ */
+
+use anchor_lang::{prelude::*, system_program::{self, transfer, Transfer}};
+use anchor_spl::{
+    associated_token::{create, get_associated_token_address_with_program_id, AssociatedToken, Create},
+    token::{close_account, mint_to, transfer_checked, CloseAccount, MintTo, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface}
+};
+
+use crate::context::purchase::{split_payment, PURCHASE_REWARD_AMOUNT};
+use crate::state::{CollectionConfig, CollectionStats, Listing, Marketplace, SaleEscrow, SellerStats};
+use crate::error::MarketplaceError;
+use crate::events::RoyaltiesBypassed;
+
+/// Number of remaining accounts supplied per listing
+pub const SWEEP_ACCOUNTS_PER_LISTING: usize = 7;
+
+/// Safe upper bound on listings per sweep. Each listing can cost an ATA, a
+/// sale escrow and a seller stats creation plus four token and system CPIs,
+/// and its seven accounts take ~224 bytes of the 1232-byte transaction.
+pub const MAX_SWEEP: usize = 3;
+
+#[derive(Accounts)]
+pub struct Sweep<'info> {
+    /// The buyer paying for every NFT in the sweep
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    /// The marketplace state account, updated with the sale totals
+    #[account(
+        mut,
+        seeds = [b"marketplace", marketplace.name.as_str().as_bytes()],
+        bump = marketplace.bump,
+    )]
+    pub marketplace: Account<'info, Marketplace>,
+
+    /// The treasury PDA that receives the fees
+    #[account(
+        mut,
+        seeds = [b"treasury", marketplace.key().as_ref()],
+        bump = marketplace.treasury_bump,
+    )]
+    pub treasury: SystemAccount<'info>,
+
+    /// The collection being swept; every listing must belong to it
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// Floor price tracking for the collection
+    #[account(
+        mut,
+        seeds = [b"collection_stats", marketplace.key().as_ref(), collection_mint.key().as_ref()],
+        bump = collection_stats.bump,
+    )]
+    pub collection_stats: Account<'info, CollectionStats>,
+
+    /// Cheapest listing of the collection left after the sweep, taking over
+    /// the floor if a swept listing held it
+    pub floor_candidate: Option<Account<'info, Listing>>,
+
+    /// The collection's config PDA (see `CollectionConfig::fee_bps`)
+    /// CHECK: Address is checked by seeds; read by `CollectionConfig::fee_bps`
+    #[account(
+        seeds = [b"collection_config", marketplace.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+    )]
+    pub collection_config: UncheckedAccount<'info>,
+
+    /// Receives the collection royalty (required when the collection config sets one)
+    /// CHECK: Checked against the collection config's `royalty_recipient`
+    #[account(mut)]
+    pub royalty_recipient: Option<UncheckedAccount<'info>>,
+
+    /// The rewards mint PDA used for minting reward tokens
+    #[account(
+        mut,
+        seeds = [b"rewards", marketplace.key().as_ref()],
+        bump = marketplace.rewards_bump,
+    )]
+    pub rewards_mint: InterfaceAccount<'info, Mint>,
+
+    /// The taker's ATA to receive reward tokens
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = rewards_mint,
+        associated_token::authority = taker,
+    )]
+    pub taker_ata_reward: InterfaceAccount<'info, TokenAccount>,
+
+    /// For creating ATAs
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// For creating accounts
+    pub system_program: Program<'info, System>,
+    /// For token operations
+    pub token_program: Interface<'info, TokenInterface>
+}
+
+impl<'info> Sweep<'info> {
+    /// Buy listings in the given (ascending price) order within `max_total_spend`
+    pub fn sweep(&mut self, remaining_accounts: &'info [AccountInfo<'info>], max_total_spend: u64) -> Result<()> {
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts.len().is_multiple_of(SWEEP_ACCOUNTS_PER_LISTING),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require!(
+            remaining_accounts.len() / SWEEP_ACCOUNTS_PER_LISTING <= MAX_SWEEP,
+            MarketplaceError::BatchTooLarge
+        );
+        require!(!self.marketplace.enforce_royalties, MarketplaceError::SweepRoyaltiesUnsupported);
+
+        let mut spent: u64 = 0;
+        let mut last_price: u64 = 0;
+        let mut bought: u64 = 0;
+        let mut removed = Vec::new();
+
+        for group in remaining_accounts.chunks(SWEEP_ACCOUNTS_PER_LISTING) {
+            let Some(listing) = self.live_listing(&group[0], &group[1], &group[3])? else {
+                msg!("Skipping listing {}: no longer available", group[0].key());
+                continue;
+            };
+
+            require!(listing.price >= last_price, MarketplaceError::UnsortedSweep);
+            last_price = listing.price;
+
+            // Sorted by price, so nothing after this one fits either
+            let total = spent.checked_add(listing.price).ok_or(MarketplaceError::MathOverflow)?;
+            if total > max_total_spend {
+                msg!("Budget reached after {} purchases", bought);
+                break;
+            }
+
+            removed.push((listing.key(), listing.maker_mint));
+            self.buy_one(listing, &group[1], &group[2], &group[3], &group[4], &group[5], &group[6])?;
+            spent = total;
+            bought += 1;
+        }
+
+        self.collection_stats.on_removed_many(&removed, self.floor_candidate.as_ref())?;
+        self.mint_rewards(bought)?;
+
+        msg!("Swept {} NFTs for {} lamports", bought, spent);
+        Ok(())
+    }
+
+    /// Load the listing if it can still be bought by this taker, or `None` to skip it
+    fn live_listing(
+        &self,
+        listing_info: &'info AccountInfo<'info>,
+        vault_info: &'info AccountInfo<'info>,
+        maker_info: &'info AccountInfo<'info>,
+    ) -> Result<Option<Account<'info, Listing>>> {
+        // Bought or delisted since the caller fetched it
+        if listing_info.owner != &crate::ID || listing_info.data_is_empty() || vault_info.data_is_empty() {
+            return Ok(None);
+        }
+
+        let listing: Account<'info, Listing> = Account::try_from(listing_info)?;
+        let vault: InterfaceAccount<'info, TokenAccount> = InterfaceAccount::try_from(vault_info)?;
+
+        if listing.allowed_buyer.is_some_and(|buyer| buyer != self.taker.key())
+            || listing.dutch_auction.is_some()
+            || listing.english_auction.is_some()
+            || maker_info.owner != &system_program::ID
+            || vault.amount != 1
+            || vault.is_frozen()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(listing))
+    }
+
+    fn buy_one(
+        &mut self,
+        listing: Account<'info, Listing>,
+        vault_info: &'info AccountInfo<'info>,
+        mint_info: &'info AccountInfo<'info>,
+        maker_info: &'info AccountInfo<'info>,
+        taker_ata_info: &'info AccountInfo<'info>,
+        sale_escrow_info: &'info AccountInfo<'info>,
+        seller_stats_info: &'info AccountInfo<'info>,
+    ) -> Result<()> {
+        let maker_mint: InterfaceAccount<'info, Mint> = InterfaceAccount::try_from(mint_info)?;
+        require_keys_eq!(listing.maker_mint, maker_mint.key(), MarketplaceError::InvalidBatchAccounts);
+        require_keys_eq!(listing.maker, maker_info.key(), MarketplaceError::InvalidMaker);
+        require_keys_eq!(listing.collection, self.collection_mint.key(), MarketplaceError::InvalidCollection);
+
+        // The listing must be the PDA for this marketplace and mint
+        let marketplace_key = self.marketplace.key();
+        let maker_mint_key = maker_mint.key();
+        let seeds = &[
+            marketplace_key.as_ref(),
+            maker_mint_key.as_ref(),
+            &[listing.bump]
+        ];
+        let expected_listing = Pubkey::create_program_address(seeds, &crate::ID)
+            .map_err(|_| MarketplaceError::InvalidBatchAccounts)?;
+        require_keys_eq!(expected_listing, listing.key(), MarketplaceError::InvalidBatchAccounts);
+
+        // Vault and destination must be the canonical ATAs
+        let token_program_key = self.token_program.key();
+        require_keys_eq!(
+            vault_info.key(),
+            get_associated_token_address_with_program_id(&listing.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+        require_keys_eq!(
+            taker_ata_info.key(),
+            get_associated_token_address_with_program_id(&self.taker.key(), &maker_mint_key, &token_program_key),
+            MarketplaceError::InvalidBatchAccounts
+        );
+
+        let fee_bps = CollectionConfig::fee_bps(&self.collection_config, self.marketplace.fee)?;
+        let (fee_amount, maker_amount) = split_payment(listing.price, fee_bps)?;
+        let royalty = CollectionConfig::royalty(&self.collection_config, self.royalty_recipient.as_deref(), listing.price)?;
+        let maker_amount = maker_amount.checked_sub(royalty).ok_or(MarketplaceError::MathOverflow)?;
+        let price = listing.price;
+        self.marketplace.record_sale(price)?;
+        SellerStats::record_sale_for(
+            seller_stats_info,
+            &self.taker.to_account_info(),
+            &self.system_program.to_account_info(),
+            marketplace_key,
+            listing.maker,
+            price,
+        )?;
+
+        if taker_ata_info.data_is_empty() {
+            create(CpiContext::new(
+                self.associated_token_program.to_account_info(),
+                Create {
+                    payer: self.taker.to_account_info(),
+                    associated_token: taker_ata_info.clone(),
+                    authority: self.taker.to_account_info(),
+                    mint: mint_info.clone(),
+                    system_program: self.system_program.to_account_info(),
+                    token_program: self.token_program.to_account_info(),
+                },
+            ))?;
+        }
+
+        let signer_seeds = &[&seeds[..]];
+        let listing_info = listing.to_account_info();
+
+        // Transfer 1 NFT to taker
+        let cpi_accounts = TransferChecked {
+            from: vault_info.clone(),
+            mint: mint_info.clone(),
+            to: taker_ata_info.clone(),
+            authority: listing_info.clone(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        transfer_checked(cpi_ctx, 1, maker_mint.decimals)?;
+
+        // Close the vault, refunding rent to the taker as `purchase` does
+        let cpi_accounts = CloseAccount {
+            account: vault_info.clone(),
+            destination: self.taker.to_account_info(),
+            authority: listing_info,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        close_account(cpi_ctx)?;
+
+        // Close the listing, refunding rent to the maker
+        let listing_maker = listing.maker;
+        listing.close(maker_info.clone())?;
+
+        if fee_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: self.taker.to_account_info(),
+                to: self.treasury.to_account_info(),
+            };
+            transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), fee_amount)?;
+        }
+
+        if royalty > 0 {
+            // `CollectionConfig::royalty` required the recipient for any nonzero royalty
+            let recipient = self.royalty_recipient.as_ref().ok_or(MarketplaceError::InvalidRoyaltyRecipient)?;
+            let cpi_accounts = Transfer {
+                from: self.taker.to_account_info(),
+                to: recipient.to_account_info(),
+            };
+            transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), royalty)?;
+        }
+
+        // Pay the maker, or hold the proceeds in a sale escrow for the dispute window
+        let recipient = if self.marketplace.dispute_window > 0 {
+            let sale = SaleEscrow::new(&self.marketplace, listing_maker, self.taker.key(), maker_mint_key, maker_amount, 0)?;
+            SaleEscrow::create(sale_escrow_info, &self.taker.to_account_info(), &self.system_program.to_account_info(), sale)?;
+            sale_escrow_info.clone()
+        } else {
+            maker_info.clone()
+        };
+        let cpi_accounts = Transfer {
+            from: self.taker.to_account_info(),
+            to: recipient,
+        };
+        transfer(CpiContext::new(self.system_program.to_account_info(), cpi_accounts), maker_amount)?;
+
+        emit!(RoyaltiesBypassed {
+            marketplace: marketplace_key,
+            mint: maker_mint_key,
+            price,
+        });
+
+        msg!("NFT {} swept for {} lamports", maker_mint_key, price);
+        Ok(())
+    }
+
+    /// Mint the per-purchase reward for every NFT bought in the sweep, up to the reward supply cap
+    fn mint_rewards(&mut self, bought: u64) -> Result<()> {
+        let reward_amount = PURCHASE_REWARD_AMOUNT
+            .checked_mul(bought)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        let reward_amount = self.marketplace.mintable_reward(reward_amount, self.rewards_mint.supply);
+        if reward_amount == 0 {
+            return Ok(());
+        }
+
+        let marketplace_name = self.marketplace.name.clone();
+        let seeds = &[
+            b"marketplace",
+            marketplace_name.as_str().as_bytes(),
+            &[self.marketplace.bump]
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: self.rewards_mint.to_account_info(),
+            to: self.taker_ata_reward.to_account_info(),
+            authority: self.marketplace.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds);
+
+        mint_to(cpi_ctx, reward_amount)
+    }
+}
@@ -1,12 +1,14 @@
 /**
  * Withdraw Fees Context
  * 
- * Allows the marketplace admin to withdraw accumulated fees from the treasury.
+ * Allows the marketplace admin to withdraw accumulated fees from the treasury,
+ * emitting a `FeesWithdrawn` event with the balance left behind.
  */
 
 use anchor_lang::{prelude::*, system_program::{transfer, Transfer}};
 use crate::state::Marketplace;
 use crate::error::MarketplaceError;
+use crate::events::FeesWithdrawn;
 
 #[derive(Accounts)]
 pub struct WithdrawFees<'info> {
@@ -58,6 +60,13 @@ impl<'info> WithdrawFees<'info> {
 
         transfer(cpi_ctx, amount)?;
 
+        emit!(FeesWithdrawn {
+            marketplace: marketplace_key,
+            admin: self.admin.key(),
+            amount,
+            remaining: self.treasury.lamports(),
+        });
+
         msg!("Withdrew {} lamports to admin", amount);
         Ok(())
     }
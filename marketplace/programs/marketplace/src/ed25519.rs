@@ -0,0 +1,29 @@
+/**
+ * Ed25519 Signature Checks
+ *
+ * Programs can't verify signatures themselves. The Ed25519 native program
+ * checks them in its own instruction of the same transaction, which fails
+ * the whole transaction if a signature is invalid. These helpers read that
+ * instruction back through the instructions sysvar and confirm it checked
+ * the expected signer over the expected message. Parsing the instruction
+ * data is shared with the other programs through `common-ed25519`.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{ed25519_program, sysvar::instructions as instructions_sysvar};
+use common_ed25519::parse_ed25519_instruction;
+
+use crate::error::MarketplaceError;
+
+/// Require the instruction before this one to be an Ed25519 signature check
+/// by `signer` over exactly `message`
+pub fn verify_ed25519_signature(instructions: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = instructions_sysvar::load_current_index_checked(instructions)?;
+    require!(current > 0, MarketplaceError::InvalidSignedListing);
+    let ix = instructions_sysvar::load_instruction_at_checked(current as usize - 1, instructions)?;
+    require_keys_eq!(ix.program_id, ed25519_program::ID, MarketplaceError::InvalidSignedListing);
+
+    let (public_key, signed) = parse_ed25519_instruction(&ix.data).ok_or(MarketplaceError::InvalidSignedListing)?;
+    require!(public_key == signer.as_ref() && signed == message, MarketplaceError::InvalidSignedListing);
+    Ok(())
+}
@@ -43,4 +43,127 @@ pub enum MarketplaceError {
     
     #[msg("Invalid marketplace state.")]
     InvalidMarketplaceState,
+    
+    #[msg("Invalid batch accounts. Expected [listing, vault, mint, maker_ata] per NFT.")]
+    InvalidBatchAccounts,
+    
+    #[msg("Batch too large. Split the request into smaller batches.")]
+    BatchTooLarge,
+    
+    #[msg("Missing pNFT accounts. Token records and the instructions sysvar are required.")]
+    MissingPnftAccounts,
+    
+    #[msg("Unverified creator. This marketplace requires at least one verified creator.")]
+    UnverifiedCreator,
+    
+    #[msg("Insufficient funds. The maker cannot cover the listing fee.")]
+    InsufficientListingFee,
+    
+    #[msg("Sweep listings must be sorted by ascending price.")]
+    UnsortedSweep,
+    
+    #[msg("Invalid creator accounts. Pass every metadata creator, in order, to receive royalties.")]
+    InvalidCreatorAccounts,
+    
+    #[msg("Sweep cannot pay royalties. Use purchase on marketplaces that enforce them.")]
+    SweepRoyaltiesUnsupported,
+    
+    #[msg("No valid offers. None of the given offers can buy this listing.")]
+    NoValidOffers,
+    
+    #[msg("Offer sales cannot pay royalties. Use purchase on marketplaces that enforce them.")]
+    OfferRoyaltiesUnsupported,
+    
+    #[msg("Invalid floor candidate. Pass another active listing of the same collection.")]
+    InvalidFloorCandidate,
+    
+    #[msg("Invalid signed listing. The maker's signature is missing or doesn't match the terms.")]
+    InvalidSignedListing,
+    
+    #[msg("Signed listing has expired.")]
+    SignedListingExpired,
+    
+    #[msg("NFT not delegated. The maker must approve the marketplace as delegate of their token account.")]
+    NftNotDelegated,
+    
+    #[msg("Signed listings can't sell pNFTs or pay royalties. List the NFT instead.")]
+    SignedListingUnsupported,
+    
+    #[msg("Invalid dispute window. It must be zero or a positive number of seconds.")]
+    InvalidDisputeWindow,
+    
+    #[msg("Sale escrow mismatch. Pass a sale escrow exactly when the marketplace has a dispute window.")]
+    SaleEscrowMismatch,
+    
+    #[msg("Dispute window still open. The proceeds can't be released yet.")]
+    DisputeWindowOpen,
+    
+    #[msg("Dispute window closed. The sale can no longer be disputed.")]
+    DisputeWindowClosed,
+    
+    #[msg("Sale is under dispute. Only the marketplace admin can settle it.")]
+    SaleDisputed,
+    
+    #[msg("Sale is not under dispute.")]
+    SaleNotDisputed,
+    
+    #[msg("Invalid collection royalty. Must be between 0 and 10000 basis points.")]
+    InvalidRoyalty,
+    
+    #[msg("Invalid royalty recipient. Pass the collection's configured royalty recipient.")]
+    InvalidRoyaltyRecipient,
+    
+    #[msg("Bulk listing can't escrow pNFTs. List them one at a time.")]
+    BulkListPnftUnsupported,
+    
+    #[msg("Invalid Dutch auction. Prices must fall from start to a non-zero end over a positive duration.")]
+    InvalidDutchAuction,
+    
+    #[msg("Dutch auction listing. Buy it with purchase_dutch.")]
+    DutchAuctionListing,
+    
+    #[msg("Not a Dutch auction listing. Buy it with purchase.")]
+    NotDutchAuction,
+    
+    #[msg("Maker is not a system-owned account. Proceeds can only be paid to a wallet or data-less PDA.")]
+    MakerNotSystemOwned,
+    
+    #[msg("Invalid English auction. It needs a positive duration and settlement window.")]
+    InvalidEnglishAuction,
+    
+    #[msg("English auction listing. Bid on it with place_bid and settle the winning bid with settle_bid.")]
+    EnglishAuctionListing,
+    
+    #[msg("Not an English auction listing.")]
+    NotEnglishAuction,
+    
+    #[msg("Auction has ended. No more bids are accepted.")]
+    AuctionEnded,
+    
+    #[msg("Auction has not ended yet.")]
+    AuctionNotEnded,
+    
+    #[msg("Bid too low. The first bid must meet the listing price and later bids must beat the highest bid.")]
+    BidTooLow,
+    
+    #[msg("Invalid previous bid. Pass the highest bid and its bidder so their deposit can be refunded.")]
+    InvalidPreviousBid,
+    
+    #[msg("Not the highest bidder. Only the winning bid can be settled.")]
+    NotHighestBidder,
+    
+    #[msg("Settlement window closed. The winning bid can only be forfeited now.")]
+    SettlementWindowClosed,
+    
+    #[msg("Settlement window still open. The winner can still settle.")]
+    SettlementWindowOpen,
+    
+    #[msg("Auction has a live bid. It must be settled or forfeited first.")]
+    AuctionHasBids,
+    
+    #[msg("Seller stats account mismatch. Pass the seller stats PDA of each listing's maker.")]
+    SellerStatsMismatch,
+    
+    #[msg("NFT is frozen. pNFT sales can't be disputed because the NFT can't be escrowed.")]
+    DisputeNftFrozen,
 }
@@ -0,0 +1,43 @@
+/**
+ * Marketplace Events
+ * 
+ * Events emitted for off-chain indexers and explorers.
+ */
+
+use anchor_lang::prelude::*;
+
+/// A sale settled without paying creator royalties, because the marketplace
+/// has royalty enforcement turned off
+#[event]
+pub struct RoyaltiesBypassed {
+    /// The marketplace the sale happened on
+    pub marketplace: Pubkey,
+    /// The NFT that was sold
+    pub mint: Pubkey,
+    /// Sale price in lamports
+    pub price: u64,
+}
+
+/// The admin changed the marketplace fee with `update_marketplace`
+#[event]
+pub struct FeeUpdated {
+    /// The marketplace whose fee changed
+    pub marketplace: Pubkey,
+    /// Previous fee in basis points
+    pub old_fee: u16,
+    /// New fee in basis points
+    pub new_fee: u16,
+}
+
+/// The admin withdrew lamports from the marketplace treasury
+#[event]
+pub struct FeesWithdrawn {
+    /// The marketplace whose treasury was drawn on
+    pub marketplace: Pubkey,
+    /// The admin who received the lamports
+    pub admin: Pubkey,
+    /// Lamports withdrawn
+    pub amount: u64,
+    /// Treasury balance left after the withdrawal
+    pub remaining: u64,
+}
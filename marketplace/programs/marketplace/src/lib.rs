@@ -5,14 +5,28 @@
  * This program enables users to:
  * - Initialize a marketplace with configurable fees and rewards
  * - List NFTs for sale with automatic escrow
+ * - List several NFTs of a collection at one price
+ * - Dutch auction listings whose price falls over time
  * - Delist NFTs and return them to the owner
+ * - Reprice listings in place, keeping the NFT in escrow
  * - Purchase NFTs with automatic fee distribution and reward tokens
+ * - Pay for purchases in wrapped SOL
+ * - Sweep the cheapest listings within a budget
+ * - Buy lazy listings signed off-chain by the maker
+ * - Make offers on NFTs and sell to the best one
+ * - Optional buyer protection: proceeds escrowed for a dispute window
+ * - Track each collection's floor price
+ * - Seller reputation from sales and upheld disputes
+ * - Marketplace stats view for operators
  * 
  * Features:
  * - PDA-based security for all accounts
  * - Automatic fee collection to marketplace treasury
+ * - Per-collection fee overrides
  * - Reward token minting for buyers
+ * - Optional creator royalty enforcement
  * - Collection verification for NFTs
+ * - Programmable NFT (pNFT) support via Token Metadata transfers
  * - Comprehensive error handling
  */
 
@@ -27,6 +41,15 @@ use context::*;
 mod error;
 use error::*;
 
+mod pnft;
+
+mod pda;
+
+mod ed25519;
+
+mod events;
+use events::*;
+
 declare_id!("HYxi42pNZDn3dpnF8HPNeFurSLQSpcYWdvRSkfuqkkui");
 
 #[program]
@@ -38,12 +61,22 @@ pub mod marketplace {
      * 
      * @param name - Unique name for the marketplace
      * @param fee - Marketplace fee in basis points (e.g., 250 = 2.5%)
+     * @param require_verified_creator - Only accept NFTs with a verified creator
+     * @param listing_fee - Flat fee in lamports charged per listing (0 = free)
+     * @param enforce_royalties - Pay creators their metadata royalties on each sale
      */
-    pub fn initialize(ctx: Context<Initialize>, name: String, fee: u16) -> Result<()> {
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        name: String,
+        fee: u16,
+        require_verified_creator: bool,
+        listing_fee: u64,
+        enforce_royalties: bool,
+    ) -> Result<()> {
         require!(fee <= 10000, MarketplaceError::InvalidFee);
         require!(!name.is_empty() && name.len() <= 32, MarketplaceError::InvalidName);
         
-        ctx.accounts.init(name, fee, &ctx.bumps)?;
+        ctx.accounts.init(name, fee, require_verified_creator, listing_fee, enforce_royalties, &ctx.bumps)?;
         
         msg!("Marketplace initialized successfully");
         Ok(())
@@ -53,21 +86,73 @@ pub mod marketplace {
      * List an NFT for sale
      * 
      * @param price - Sale price in lamports
+     * @param allowed_buyer - Restrict the sale to this wallet (None = open sale)
      */
-    pub fn listing(ctx: Context<List>, price: u64) -> Result<()> {
+    pub fn listing(ctx: Context<List>, price: u64, allowed_buyer: Option<Pubkey>) -> Result<()> {
         require!(price > 0, MarketplaceError::InvalidPrice);
         
-        ctx.accounts.create_listing(price, &ctx.bumps)?;
+        ctx.accounts.charge_listing_fee()?;
+        ctx.accounts.create_listing(price, allowed_buyer, &ctx.bumps)?;
+        ctx.accounts.track_floor(price, &ctx.bumps)?;
         ctx.accounts.deposit_nft()?;
         
         msg!("NFT listed for sale at {} lamports", price);
         Ok(())
     }
 
+    /**
+     * List an NFT as a Dutch auction
+     * 
+     * The price falls linearly from `start_price` to `end_price` over
+     * `duration` seconds, then stays at `end_price`. Buy it with
+     * `purchase_dutch`. The listing isn't tracked as the collection floor.
+     * 
+     * @param start_price - Price in lamports when listed
+     * @param end_price - Lowest price in lamports, reached after `duration`
+     * @param duration - Seconds the price takes to fall
+     */
+    pub fn list_dutch(ctx: Context<List>, start_price: u64, end_price: u64, duration: i64) -> Result<()> {
+        ctx.accounts.charge_listing_fee()?;
+        ctx.accounts.create_listing(start_price, None, &ctx.bumps)?;
+        ctx.accounts.start_dutch_auction(start_price, end_price, duration)?;
+        ctx.accounts.deposit_nft()?;
+        
+        msg!("NFT listed as a Dutch auction");
+        Ok(())
+    }
+
+    /**
+     * List an NFT as an English auction
+     * 
+     * Bids start at `min_bid` and are taken with `place_bid` for `duration`
+     * seconds. The highest bidder then has `settle_window` seconds to pay with
+     * `settle_bid`, or their deposit is forfeited to the maker with
+     * `forfeit_bid`. The listing isn't tracked as the collection floor.
+     * 
+     * @param min_bid - Lowest accepted first bid in lamports
+     * @param duration - Seconds bidding stays open
+     * @param settle_window - Seconds the winner has to settle after bidding closes
+     */
+    pub fn list_english(ctx: Context<List>, min_bid: u64, duration: i64, settle_window: i64) -> Result<()> {
+        require!(min_bid > 0, MarketplaceError::InvalidPrice);
+        
+        ctx.accounts.charge_listing_fee()?;
+        ctx.accounts.create_listing(min_bid, None, &ctx.bumps)?;
+        ctx.accounts.start_english_auction(duration, settle_window)?;
+        ctx.accounts.deposit_nft()?;
+        
+        msg!("NFT listed as an English auction");
+        Ok(())
+    }
+
     /**
      * Remove an NFT listing and return it to the owner
+     * 
+     * Pass the collection's next-cheapest listing as `floor_candidate` when
+     * delisting the floor; the floor is cleared otherwise.
      */
     pub fn delist(ctx: Context<Delist>) -> Result<()> {
+        ctx.accounts.update_floor()?;
         ctx.accounts.delist()?;
         ctx.accounts.close_mint_vault()?;
         
@@ -75,31 +160,468 @@ pub mod marketplace {
         Ok(())
     }
 
+    /**
+     * Change a live listing's price without moving the escrowed NFT
+     * 
+     * As with `delist`, pass `floor_candidate` when raising the price of the
+     * collection's floor listing.
+     * 
+     * @param new_price - New sale price in lamports
+     */
+    pub fn relist(ctx: Context<Relist>, new_price: u64) -> Result<()> {
+        ctx.accounts.reprice(new_price)?;
+        ctx.accounts.update_floor()?;
+        
+        msg!("NFT relisted at {} lamports", new_price);
+        Ok(())
+    }
+
+    /**
+     * Remove several listings in one transaction
+     * 
+     * Remaining accounts: `[listing, vault, maker_mint, maker_ata]` per NFT,
+     * at most `MAX_DELIST_BATCH` groups, all from one collection. Fails
+     * atomically. Pass `floor_candidate` as for `delist`.
+     */
+    pub fn delist_batch<'info>(ctx: Context<'_, '_, 'info, 'info, DelistBatch<'info>>) -> Result<()> {
+        ctx.accounts.delist_all(ctx.remaining_accounts)?;
+        
+        msg!("Batch delist completed successfully");
+        Ok(())
+    }
+
+    /**
+     * List several NFTs from one collection at the same price
+     * 
+     * Remaining accounts: `[maker_mint, maker_ata, vault, listing, metadata,
+     * master_edition]` per NFT, at most `MAX_LIST_COLLECTION` groups. Fails
+     * atomically.
+     * 
+     * @param price - Sale price in lamports for every NFT
+     */
+    pub fn list_collection<'info>(ctx: Context<'_, '_, 'info, 'info, ListCollection<'info>>, price: u64) -> Result<()> {
+        require!(price > 0, MarketplaceError::InvalidPrice);
+        
+        ctx.accounts.list_all(ctx.remaining_accounts, price, &ctx.bumps)?;
+        
+        msg!("Collection batch listed successfully");
+        Ok(())
+    }
+
     /**
      * Purchase a listed NFT
+     * 
+     * Follows checks-effects-interactions: see `context/purchase.rs`.
+     * 
+     * Remaining accounts: the metadata creators, in order, when the
+     * marketplace enforces royalties. None otherwise. As with `delist`, pass
+     * `floor_candidate` when buying the collection's floor listing, and pass
+     * `royalty_recipient` when the collection config sets a royalty.
+     * 
+     * On marketplaces with a dispute window, pass `sale_escrow`: the maker's
+     * proceeds are held there until the sale is released or resolved.
      */
-    pub fn purchase(ctx: Context<Purchase>) -> Result<()> {
-        ctx.accounts.send_sol()?;
+    pub fn purchase<'info>(ctx: Context<'_, '_, 'info, 'info, Purchase<'info>>) -> Result<()> {
+        ctx.accounts.check_fixed_price()?;
+        ctx.accounts.check_buyer_protection()?;
+        let royalties = ctx.accounts.royalty_payouts()?;
+        let collection_royalty = ctx.accounts.collection_royalty()?;
+        let (fee_amount, maker_amount) = ctx.accounts.payment_split(&royalties, collection_royalty)?;
+        ctx.accounts.record_sale(&ctx.bumps)?;
+        ctx.accounts.update_floor()?;
+        ctx.accounts.open_sale_escrow(maker_amount, &ctx.bumps)?;
+
         ctx.accounts.receive_nft()?;
-        ctx.accounts.receive_rewards()?;
         ctx.accounts.close_mint_vault()?;
+
+        ctx.accounts.send_sol(fee_amount, maker_amount, PaymentSource::Taker)?;
+        ctx.accounts.pay_royalties(ctx.remaining_accounts, &royalties, PaymentSource::Taker)?;
+        ctx.accounts.pay_collection_royalty(collection_royalty, PaymentSource::Taker)?;
+        ctx.accounts.receive_rewards()?;
         
         msg!("NFT purchased successfully");
         Ok(())
     }
 
+    /**
+     * Purchase a Dutch auction listing at its current price
+     * 
+     * Same accounts and rules as `purchase`; the price is read off the
+     * auction's curve at the current time.
+     */
+    pub fn purchase_dutch<'info>(ctx: Context<'_, '_, 'info, 'info, Purchase<'info>>) -> Result<()> {
+        ctx.accounts.price_dutch_auction()?;
+        ctx.accounts.check_buyer_protection()?;
+        let royalties = ctx.accounts.royalty_payouts()?;
+        let collection_royalty = ctx.accounts.collection_royalty()?;
+        let (fee_amount, maker_amount) = ctx.accounts.payment_split(&royalties, collection_royalty)?;
+        ctx.accounts.record_sale(&ctx.bumps)?;
+        ctx.accounts.update_floor()?;
+        ctx.accounts.open_sale_escrow(maker_amount, &ctx.bumps)?;
+
+        ctx.accounts.receive_nft()?;
+        ctx.accounts.close_mint_vault()?;
+
+        ctx.accounts.send_sol(fee_amount, maker_amount, PaymentSource::Taker)?;
+        ctx.accounts.pay_royalties(ctx.remaining_accounts, &royalties, PaymentSource::Taker)?;
+        ctx.accounts.pay_collection_royalty(collection_royalty, PaymentSource::Taker)?;
+        ctx.accounts.receive_rewards()?;
+        
+        msg!("Dutch auction NFT purchased successfully");
+        Ok(())
+    }
+
+    /**
+     * Settle the winning bid of an English auction (highest bidder only)
+     * 
+     * Same accounts and rules as `purchase`, plus the taker's `bid`. Allowed
+     * from the end of bidding until the settlement window closes; the winner
+     * pays their full bid and the bid's deposit is refunded to them.
+     */
+    pub fn settle_bid<'info>(ctx: Context<'_, '_, 'info, 'info, Purchase<'info>>) -> Result<()> {
+        ctx.accounts.price_english_auction()?;
+        ctx.accounts.check_buyer_protection()?;
+        let royalties = ctx.accounts.royalty_payouts()?;
+        let collection_royalty = ctx.accounts.collection_royalty()?;
+        let (fee_amount, maker_amount) = ctx.accounts.payment_split(&royalties, collection_royalty)?;
+        ctx.accounts.record_sale(&ctx.bumps)?;
+        ctx.accounts.update_floor()?;
+        ctx.accounts.open_sale_escrow(maker_amount, &ctx.bumps)?;
+
+        ctx.accounts.receive_nft()?;
+        ctx.accounts.close_mint_vault()?;
+
+        ctx.accounts.send_sol(fee_amount, maker_amount, PaymentSource::Taker)?;
+        ctx.accounts.pay_royalties(ctx.remaining_accounts, &royalties, PaymentSource::Taker)?;
+        ctx.accounts.pay_collection_royalty(collection_royalty, PaymentSource::Taker)?;
+        ctx.accounts.receive_rewards()?;
+        
+        msg!("English auction settled successfully");
+        Ok(())
+    }
+
+    /**
+     * Purchase a listed NFT, paying from the taker's wrapped SOL account
+     * 
+     * Same accounts and rules as `purchase`, nested under `purchase`. The
+     * price is unwrapped into the treasury first and everyone is paid from
+     * there, so the maker and creators still receive native SOL.
+     */
+    pub fn purchase_wsol<'info>(ctx: Context<'_, '_, 'info, 'info, PurchaseWsol<'info>>) -> Result<()> {
+        let purchase = &mut ctx.accounts.purchase;
+        purchase.check_fixed_price()?;
+        purchase.check_buyer_protection()?;
+        let royalties = purchase.royalty_payouts()?;
+        let collection_royalty = purchase.collection_royalty()?;
+        let (fee_amount, maker_amount) = purchase.payment_split(&royalties, collection_royalty)?;
+        purchase.record_sale(&ctx.bumps.purchase)?;
+        purchase.update_floor()?;
+        purchase.open_sale_escrow(maker_amount, &ctx.bumps.purchase)?;
+
+        purchase.receive_nft()?;
+        purchase.close_mint_vault()?;
+
+        ctx.accounts.unwrap_payment()?;
+        let purchase = &mut ctx.accounts.purchase;
+        purchase.send_sol(fee_amount, maker_amount, PaymentSource::Treasury)?;
+        purchase.pay_royalties(ctx.remaining_accounts, &royalties, PaymentSource::Treasury)?;
+        purchase.pay_collection_royalty(collection_royalty, PaymentSource::Treasury)?;
+        purchase.receive_rewards()?;
+        
+        msg!("NFT purchased with wrapped SOL");
+        Ok(())
+    }
+
+    /**
+     * Buy the cheapest listings up to a spend budget
+     * 
+     * Remaining accounts: `[listing, vault, maker_mint, maker, taker_ata,
+     * sale_escrow, seller_stats]` per NFT, sorted by ascending price, at most
+     * `MAX_SWEEP` groups, all from one collection. Stops at the first listing
+     * that would exceed the budget; skips listings that are gone. The
+     * collection's fee and royalty apply as in `purchase`, and with a dispute
+     * window each sale's proceeds are escrowed. Only available on marketplaces
+     * that don't enforce royalties.
+     * 
+     * @param max_total_spend - Most lamports to spend across all purchases
+     */
+    pub fn sweep<'info>(ctx: Context<'_, '_, 'info, 'info, Sweep<'info>>, max_total_spend: u64) -> Result<()> {
+        ctx.accounts.sweep(ctx.remaining_accounts, max_total_spend)?;
+        
+        msg!("Sweep completed successfully");
+        Ok(())
+    }
+
+    /**
+     * Purchase an NFT from a listing the maker signed off-chain
+     * 
+     * The transaction must carry an Ed25519 program instruction verifying the
+     * maker's signature over the listing, right before this one. The NFT is
+     * pulled from the maker's token account, which must have approved the
+     * marketplace PDA as delegate. Each listing nonce can be bought once.
+     * The collection royalty and dispute window apply as in `purchase`.
+     * 
+     * @param listing - The signed sale terms
+     */
+    pub fn purchase_signed(ctx: Context<PurchaseSigned>, listing: SignedListing) -> Result<()> {
+        ctx.accounts.verify_listing(&listing, &ctx.bumps)?;
+        let (fee_amount, royalty, maker_amount) = ctx.accounts.record_sale(listing.price, &ctx.bumps)?;
+
+        ctx.accounts.receive_nft()?;
+        ctx.accounts.send_sol(listing.price, fee_amount, royalty, maker_amount)?;
+        ctx.accounts.receive_rewards()?;
+        
+        msg!("Signed listing purchased for {} lamports", listing.price);
+        Ok(())
+    }
+
+    /**
+     * Release a protected sale's proceeds to the maker after the dispute window
+     * 
+     * Permissionless; fails while the window is open or the sale is disputed.
+     */
+    pub fn release_sale(ctx: Context<ReleaseSale>) -> Result<()> {
+        ctx.accounts.release()?;
+        
+        msg!("Sale proceeds released to maker");
+        Ok(())
+    }
+
+    /**
+     * Confirm receipt of a protected sale, releasing the proceeds early (buyer only)
+     */
+    pub fn confirm_receipt(ctx: Context<ConfirmReceipt>) -> Result<()> {
+        ctx.accounts.confirm()?;
+        
+        msg!("Receipt confirmed, proceeds released to maker");
+        Ok(())
+    }
+
+    /**
+     * Dispute a protected sale within its dispute window (buyer only)
+     * 
+     * Freezes the proceeds until the admin calls `resolve_dispute`, and moves
+     * the NFT into a dispute vault until then.
+     */
+    pub fn raise_dispute(ctx: Context<RaiseDispute>) -> Result<()> {
+        ctx.accounts.dispute()?;
+        
+        msg!("Sale disputed, proceeds frozen for arbitration");
+        Ok(())
+    }
+
+    /**
+     * Settle a disputed sale (admin only)
+     * 
+     * The NFT goes to whichever party doesn't get the proceeds.
+     * 
+     * @param refund_buyer - Refund the proceeds to the buyer instead of paying the maker
+     */
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, refund_buyer: bool) -> Result<()> {
+        ctx.accounts.resolve(refund_buyer, &ctx.bumps)?;
+        
+        msg!("Dispute resolved in favor of the {}", if refund_buyer { "buyer" } else { "maker" });
+        Ok(())
+    }
+
+    /**
+     * Offer lamports for an NFT, escrowed in an offer PDA
+     * 
+     * @param amount - Offered price in lamports
+     */
+    pub fn make_offer(ctx: Context<MakeOffer>, amount: u64) -> Result<()> {
+        require!(amount > 0, MarketplaceError::InvalidPrice);
+        
+        ctx.accounts.place_offer(amount, &ctx.bumps)?;
+        
+        msg!("Offer of {} lamports placed", amount);
+        Ok(())
+    }
+
+    /**
+     * Withdraw an offer and refund the escrowed lamports to the bidder
+     */
+    pub fn cancel_offer(_ctx: Context<CancelOffer>) -> Result<()> {
+        msg!("Offer cancelled");
+        Ok(())
+    }
+
+    /**
+     * Sell a listed NFT to the highest of the given offers (maker only)
+     * 
+     * Remaining accounts: `[offer, bidder, bidder_ata]` per offer, at most
+     * `MAX_OFFERS` groups. Invalid offers are ignored; reverts if none is valid.
+     * The collection royalty is paid out of the bid, and with a dispute window
+     * the proceeds are escrowed for the winning bidder. Only available on
+     * marketplaces that don't enforce royalties.
+     */
+    pub fn sell_to_best_offer<'info>(ctx: Context<'_, '_, 'info, 'info, SellToBestOffer<'info>>) -> Result<()> {
+        ctx.accounts.sell(ctx.remaining_accounts, &ctx.bumps)?;
+        
+        msg!("NFT sold to the best offer");
+        Ok(())
+    }
+
+    /**
+     * Bid on an English auction listing, posting a refundable deposit
+     * 
+     * Once the auction has a bid, pass it as `previous_bid` with its
+     * `previous_bidder`: the bid is beaten and its deposit refunded.
+     * 
+     * @param amount - Bid in lamports, paid in full on settlement
+     */
+    pub fn place_bid(ctx: Context<PlaceBid>, amount: u64) -> Result<()> {
+        ctx.accounts.place_bid(amount, &ctx.bumps)?;
+        
+        msg!("Bid of {} lamports placed", amount);
+        Ok(())
+    }
+
+    /**
+     * Forfeit the deposit of a winning bid that wasn't settled in time (permissionless)
+     * 
+     * The deposit goes to the maker, who can then delist the NFT.
+     */
+    pub fn forfeit_bid(ctx: Context<ForfeitBid>) -> Result<()> {
+        ctx.accounts.forfeit()?;
+        
+        msg!("Unsettled bid forfeited");
+        Ok(())
+    }
+
+    /**
+     * Repair a collection's floor price (permissionless)
+     * 
+     * `floor_listing` is the listing PDA of the recorded floor mint: the floor
+     * is cleared if it was closed, or re-priced if it was relisted. The
+     * optional `floor_candidate` then becomes the floor if it is cheaper.
+     */
+    pub fn refresh_floor(ctx: Context<RefreshFloor>) -> Result<()> {
+        ctx.accounts.refresh()?;
+        
+        msg!("Floor price is {} lamports", ctx.accounts.collection_stats.floor_price);
+        Ok(())
+    }
+
+    /**
+     * Get a collection's floor price in lamports (0 = no active listing known)
+     */
+    pub fn get_floor_price(ctx: Context<GetFloorPrice>) -> Result<u64> {
+        Ok(ctx.accounts.collection_stats.floor_price)
+    }
+
+    /**
+     * Get a seller's reputation: one point per sale, less
+     * `DISPUTE_REPUTATION_PENALTY` per dispute upheld against them
+     */
+    pub fn get_seller_reputation(ctx: Context<GetSellerReputation>) -> Result<i64> {
+        Ok(ctx.accounts.seller_stats.reputation())
+    }
+
+    /**
+     * Get the marketplace's fee, treasury balance, lifetime volume and sales,
+     * and reward token supply in one read
+     */
+    pub fn get_marketplace_stats(ctx: Context<GetMarketplaceStats>) -> Result<MarketplaceStats> {
+        Ok(ctx.accounts.stats())
+    }
+
     /**
      * Update marketplace configuration (admin only)
      * 
      * @param new_fee - New marketplace fee in basis points
+     * @param require_verified_creator - New verified-creator listing requirement
+     * @param listing_fee - New flat listing fee in lamports
+     * @param enforce_royalties - New royalty enforcement setting
+     * @param dispute_window - New buyer protection window in seconds (0 = off)
+     * @param reward_supply_cap - New ceiling on the reward mint's supply (0 = uncapped)
      */
-    pub fn update_marketplace(ctx: Context<UpdateMarketplace>, new_fee: Option<u16>) -> Result<()> {
+    pub fn update_marketplace(
+        ctx: Context<UpdateMarketplace>,
+        new_fee: Option<u16>,
+        require_verified_creator: Option<bool>,
+        listing_fee: Option<u64>,
+        enforce_royalties: Option<bool>,
+        dispute_window: Option<i64>,
+        reward_supply_cap: Option<u64>,
+    ) -> Result<()> {
         if let Some(fee) = new_fee {
             require!(fee <= 10000, MarketplaceError::InvalidFee);
+            let old_fee = ctx.accounts.marketplace.fee;
             ctx.accounts.marketplace.fee = fee;
+            emit!(FeeUpdated {
+                marketplace: ctx.accounts.marketplace.key(),
+                old_fee,
+                new_fee: fee,
+            });
             msg!("Marketplace fee updated to {} basis points", fee);
         }
         
+        if let Some(required) = require_verified_creator {
+            ctx.accounts.marketplace.require_verified_creator = required;
+            msg!("Verified creator requirement set to {}", required);
+        }
+        
+        if let Some(listing_fee) = listing_fee {
+            ctx.accounts.marketplace.listing_fee = listing_fee;
+            msg!("Listing fee updated to {} lamports", listing_fee);
+        }
+        
+        if let Some(enforce) = enforce_royalties {
+            ctx.accounts.marketplace.enforce_royalties = enforce;
+            msg!("Royalty enforcement set to {}", enforce);
+        }
+        
+        if let Some(window) = dispute_window {
+            require!(window >= 0, MarketplaceError::InvalidDisputeWindow);
+            ctx.accounts.marketplace.dispute_window = window;
+            msg!("Dispute window set to {} seconds", window);
+        }
+        
+        if let Some(cap) = reward_supply_cap {
+            ctx.accounts.marketplace.reward_supply_cap = cap;
+            msg!("Reward supply cap set to {}", cap);
+        }
+        
+        Ok(())
+    }
+
+    /**
+     * Override the marketplace fee for one collection (admin only)
+     * 
+     * Applies to every sale path: `purchase`, sweeps, signed listings and
+     * offer sales.
+     * 
+     * @param collection_mint - Collection the override applies to
+     * @param fee_bps - Fee for the collection in basis points
+     */
+    pub fn set_collection_fee(ctx: Context<SetCollectionFee>, collection_mint: Pubkey, fee_bps: u16) -> Result<()> {
+        ctx.accounts.set_fee(collection_mint, fee_bps, &ctx.bumps)?;
+        
+        msg!("Collection {} fee set to {} basis points", collection_mint, fee_bps);
+        Ok(())
+    }
+
+    /**
+     * Route a royalty on one collection's sales to a recipient (admin only)
+     * 
+     * Applies to every sale path, out of the maker's proceeds and on top of
+     * any creator royalties `purchase` pays. For collections whose metadata
+     * lists no creators.
+     * 
+     * @param collection_mint - Collection the royalty applies to
+     * @param royalty_bps - Royalty in basis points of the price (0 = none)
+     * @param royalty_recipient - Wallet the royalty is paid to
+     */
+    pub fn set_collection_royalty(
+        ctx: Context<SetCollectionRoyalty>,
+        collection_mint: Pubkey,
+        royalty_bps: u16,
+        royalty_recipient: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.set_royalty(collection_mint, royalty_bps, royalty_recipient, &ctx.bumps)?;
+        
+        msg!("Collection {} royalty set to {} basis points", collection_mint, royalty_bps);
         Ok(())
     }
 
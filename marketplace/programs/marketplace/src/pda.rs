@@ -0,0 +1,55 @@
+/**
+ * Runtime PDA Creation
+ *
+ * Batch sale paths only learn some accounts' owners (the buyer of an offer
+ * sale, the maker of each swept listing) once the handler runs, so they
+ * can't use Anchor's `init` and create those PDAs here instead.
+ */
+
+use anchor_lang::{
+    prelude::*,
+    system_program::{allocate, assign, create_account, transfer, Allocate, Assign, CreateAccount, Transfer}
+};
+
+use crate::error::MarketplaceError;
+
+/// Create the program-owned PDA `info` with `space` bytes, signed for by
+/// `seeds` (bump included) and funded by `payer`. Lamports sent to the
+/// address beforehand must not block the sale, so a pre-funded address is
+/// topped up, allocated and assigned the way Anchor's `init` does. Fails
+/// with `mismatch` if the address already holds an account.
+pub fn create_pda<'info>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    space: usize,
+    mismatch: MarketplaceError,
+) -> Result<()> {
+    let signer_seeds = &[seeds];
+    let rent = Rent::get()?.minimum_balance(space);
+    let current_lamports = info.lamports();
+
+    if current_lamports == 0 {
+        let cpi_accounts = CreateAccount { from: payer.clone(), to: info.clone() };
+        create_account(
+            CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer_seeds),
+            rent,
+            space as u64,
+            &crate::ID,
+        )?;
+        return Ok(());
+    }
+
+    require_keys_eq!(*info.owner, System::id(), mismatch);
+    let top_up = rent.saturating_sub(current_lamports);
+    if top_up > 0 {
+        let cpi_accounts = Transfer { from: payer.clone(), to: info.clone() };
+        transfer(CpiContext::new(system_program.clone(), cpi_accounts), top_up)?;
+    }
+    let cpi_accounts = Allocate { account_to_allocate: info.clone() };
+    allocate(CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer_seeds), space as u64)?;
+    let cpi_accounts = Assign { account_to_assign: info.clone() };
+    assign(CpiContext::new_with_signer(system_program.clone(), cpi_accounts, signer_seeds), &crate::ID)?;
+    Ok(())
+}
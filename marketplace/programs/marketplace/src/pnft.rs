@@ -0,0 +1,89 @@
+/**
+ * Programmable NFT Transfers
+ *
+ * Token Metadata keeps pNFT token accounts frozen, so `transfer_checked`
+ * fails for them. These helpers detect pNFTs from their metadata and route
+ * the transfer through Token Metadata's `TransferV1`. That instruction thaws
+ * the token, moves it, re-freezes it and enforces the collection's
+ * authorization rules along the way.
+ */
+
+use anchor_lang::prelude::*;
+use anchor_spl::metadata::MetadataAccount;
+use mpl_token_metadata::{instructions::TransferV1CpiBuilder, types::TokenStandard};
+
+use crate::error::MarketplaceError;
+
+/// Whether the NFT must be moved through Token Metadata instead of SPL Token
+pub fn is_programmable(metadata: &MetadataAccount) -> bool {
+    matches!(
+        metadata.token_standard,
+        Some(TokenStandard::ProgrammableNonFungible | TokenStandard::ProgrammableNonFungibleEdition)
+    )
+}
+
+/// Accounts for moving a single pNFT between two token accounts
+pub struct PnftTransfer<'a, 'info> {
+    pub metadata_program: &'a AccountInfo<'info>,
+    pub token: &'a AccountInfo<'info>,
+    pub token_owner: &'a AccountInfo<'info>,
+    pub destination_token: &'a AccountInfo<'info>,
+    pub destination_owner: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub metadata: &'a AccountInfo<'info>,
+    pub edition: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+    pub associated_token_program: &'a AccountInfo<'info>,
+    /// Token record of `token`, required for pNFTs
+    pub token_record: Option<&'a AccountInfo<'info>>,
+    /// Token record of `destination_token`, required for pNFTs
+    pub destination_token_record: Option<&'a AccountInfo<'info>>,
+    /// Instructions sysvar, required for pNFTs
+    pub sysvar_instructions: Option<&'a AccountInfo<'info>>,
+    /// Only needed when the pNFT has a rule set
+    pub authorization_rules: Option<&'a AccountInfo<'info>>,
+    /// Only needed when the pNFT has a rule set
+    pub authorization_rules_program: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> PnftTransfer<'a, 'info> {
+    /// Transfer the pNFT, signing for `authority` with `signer_seeds` when it is a PDA
+    pub fn invoke_signed(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let (Some(token_record), Some(destination_token_record), Some(sysvar_instructions)) =
+            (self.token_record, self.destination_token_record, self.sysvar_instructions)
+        else {
+            return err!(MarketplaceError::MissingPnftAccounts);
+        };
+
+        TransferV1CpiBuilder::new(self.metadata_program)
+            .token(self.token)
+            .token_owner(self.token_owner)
+            .destination_token(self.destination_token)
+            .destination_owner(self.destination_owner)
+            .mint(self.mint)
+            .metadata(self.metadata)
+            .edition(Some(self.edition))
+            .token_record(Some(token_record))
+            .destination_token_record(Some(destination_token_record))
+            .authority(self.authority)
+            .payer(self.payer)
+            .system_program(self.system_program)
+            .sysvar_instructions(sysvar_instructions)
+            .spl_token_program(self.token_program)
+            .spl_ata_program(self.associated_token_program)
+            .authorization_rules(self.authorization_rules)
+            .authorization_rules_program(self.authorization_rules_program)
+            .amount(1)
+            .invoke_signed(signer_seeds)?;
+
+        Ok(())
+    }
+}
+
+/// Borrow an optional unchecked account as a plain `AccountInfo`
+pub fn optional_info<'a, 'info>(account: &'a Option<UncheckedAccount<'info>>) -> Option<&'a AccountInfo<'info>> {
+    account.as_ref().map(|account| account.as_ref())
+}
@@ -0,0 +1,53 @@
+/**
+ * Bid State Account
+ * 
+ * The highest bid on an English auction listing. Bids aren't escrowed in
+ * full like offers: the bidder posts a refundable good-faith deposit of
+ * `BID_DEPOSIT_BPS` of the bid, held in this account on top of its rent.
+ * The deposit is refunded when the bid is outbid or settled, and forfeited
+ * to the maker if the bidder wins but doesn't settle within the auction's
+ * settlement window. This account is a PDA derived from the listing and
+ * bidder.
+ */
+
+use anchor_lang::prelude::*;
+use common_math::mul_div_ceil;
+
+use crate::error::MarketplaceError;
+
+/// Good-faith deposit posted with each bid, in basis points of the bid (10%)
+pub const BID_DEPOSIT_BPS: u64 = 1000;
+
+#[account]
+pub struct Bid {
+    /// The wallet that placed the bid and receives the NFT if it wins
+    pub bidder: Pubkey,
+    /// The English auction listing the bid is on
+    pub listing: Pubkey,
+    /// The bid in lamports, paid in full on settlement
+    pub amount: u64,
+    /// The deposit in lamports, held by this account
+    pub deposit: u64,
+    /// PDA bump seed for the bid account
+    pub bump: u8,
+}
+
+impl Bid {
+    /// Deposit owed on a bid of `amount`, rounded up so it is never zero
+    pub fn deposit_for(amount: u64) -> Result<u64> {
+        let deposit = mul_div_ceil(amount, BID_DEPOSIT_BPS, 10000)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(deposit)
+    }
+}
+
+impl Space for Bid {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for bidder
+    /// - 32 bytes: Pubkey for listing
+    /// - 8 bytes: u64 for amount
+    /// - 8 bytes: u64 for deposit
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 8 + 1;
+}
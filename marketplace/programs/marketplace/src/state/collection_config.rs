@@ -0,0 +1,85 @@
+/**
+ * Collection Config Account
+ *
+ * Per-collection settings the marketplace admin can set:
+ * - A fee override, charged in place of the marketplace's global `fee`
+ *   (see `CollectionConfig::fee_bps`).
+ * - A collection royalty: every sale path takes `royalty_bps` out of the
+ *   maker's proceeds and pays it to `royalty_recipient`, on top of any
+ *   metadata creator royalties `purchase` pays. Meant for collections whose
+ *   metadata has no creators.
+ * This account is a PDA derived from the marketplace and collection mint.
+ */
+
+use anchor_lang::prelude::*;
+use common_math::mul_div_floor;
+
+use crate::error::MarketplaceError;
+
+#[account]
+pub struct CollectionConfig {
+    /// The marketplace this config belongs to
+    pub marketplace: Pubkey,
+    /// The collection this config applies to
+    pub collection_mint: Pubkey,
+    /// Marketplace fee for this collection in basis points (None = global fee)
+    pub fee_bps: Option<u16>,
+    /// Collection royalty in basis points of the price (0 = none)
+    pub royalty_bps: u16,
+    /// Wallet the collection royalty is paid to
+    pub royalty_recipient: Pubkey,
+    /// PDA bump seed for the collection config account
+    pub bump: u8,
+}
+
+impl CollectionConfig {
+    /// The config stored in `config`, an address-checked collection config
+    /// PDA that may not have been created yet
+    pub fn load(config: &AccountInfo) -> Result<Option<CollectionConfig>> {
+        if config.owner != &crate::ID || config.data_is_empty() {
+            return Ok(None);
+        }
+        let config = CollectionConfig::try_deserialize(&mut &config.try_borrow_data()?[..])?;
+        Ok(Some(config))
+    }
+
+    /// Fee charged on a sale of the collection: the override stored in
+    /// `config`, or `global_fee` when there is none. Every sale path
+    /// (`purchase`, sweeps, signed listings and offer sales) charges this, and
+    /// each requires the config PDA so the override can't be skipped by
+    /// leaving it out; the PDA need not exist.
+    pub fn fee_bps(config: &AccountInfo, global_fee: u16) -> Result<u16> {
+        Ok(CollectionConfig::load(config)?
+            .and_then(|config| config.fee_bps)
+            .unwrap_or(global_fee))
+    }
+
+    /// Collection royalty owed on a sale at `price` (0 when the collection has
+    /// none), after checking `recipient` matches the config's `royalty_recipient`
+    pub fn royalty(config: &AccountInfo, recipient: Option<&AccountInfo>, price: u64) -> Result<u64> {
+        let Some(config) = CollectionConfig::load(config)? else {
+            return Ok(0);
+        };
+        if config.royalty_bps == 0 {
+            return Ok(0);
+        }
+
+        let recipient = recipient.ok_or(MarketplaceError::InvalidRoyaltyRecipient)?;
+        require_keys_eq!(recipient.key(), config.royalty_recipient, MarketplaceError::InvalidRoyaltyRecipient);
+        let amount = mul_div_floor(price, config.royalty_bps as u64, 10000)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(amount)
+    }
+}
+
+impl Space for CollectionConfig {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for marketplace
+    /// - 32 bytes: Pubkey for collection_mint
+    /// - 3 bytes: Option<u16> for fee_bps
+    /// - 2 bytes: u16 for royalty_bps
+    /// - 32 bytes: Pubkey for royalty_recipient
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 3 + 2 + 32 + 1;
+}
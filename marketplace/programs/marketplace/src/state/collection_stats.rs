@@ -0,0 +1,106 @@
+/**
+ * Collection Stats Account
+ * 
+ * Tracks the floor price of a collection on a marketplace: the cheapest
+ * active listing known to the program. It is updated as listings are created,
+ * delisted and purchased. When the floor listing leaves the book, the caller
+ * may pass the next-cheapest listing as a candidate to take over; without
+ * one the floor is cleared until the next listing, or until `refresh_floor`
+ * is given a candidate.
+ * This account is a PDA derived from the marketplace and collection mint.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::state::Listing;
+use crate::error::MarketplaceError;
+
+#[account]
+pub struct CollectionStats {
+    /// The marketplace these stats belong to
+    pub marketplace: Pubkey,
+    /// The collection these stats track
+    pub collection_mint: Pubkey,
+    /// Lowest known active listing price in lamports (0 = no known floor)
+    pub floor_price: u64,
+    /// NFT mint of the floor listing (default when there is no floor)
+    pub floor_mint: Pubkey,
+    /// PDA bump seed for the collection stats account
+    pub bump: u8,
+}
+
+impl CollectionStats {
+    /// Whether a floor listing is currently known
+    pub fn has_floor(&self) -> bool {
+        self.floor_price > 0
+    }
+
+    /// A listing of `mint` at `price` becomes the floor if it undercuts it
+    pub fn on_listed(&mut self, mint: Pubkey, price: u64) {
+        if !self.has_floor() || price < self.floor_price {
+            self.floor_price = price;
+            self.floor_mint = mint;
+        }
+    }
+
+    /// The listing of `mint` left the book. If it was the floor, `candidate`
+    /// (mint, price) takes over, or the floor is cleared without one.
+    pub fn on_removed(&mut self, mint: Pubkey, candidate: Option<(Pubkey, u64)>) {
+        if self.has_floor() && self.floor_mint == mint {
+            self.floor_price = 0;
+            self.floor_mint = Pubkey::default();
+        }
+        if let Some((candidate_mint, price)) = candidate {
+            self.on_listed(candidate_mint, price);
+        }
+    }
+
+    /// Several listings, given as (listing, mint), left the book at once. If
+    /// one of them was the floor, `candidate` takes over; it can't be one of
+    /// the removed listings.
+    pub fn on_removed_many(&mut self, removed: &[(Pubkey, Pubkey)], candidate: Option<&Account<Listing>>) -> Result<()> {
+        let candidate = match candidate {
+            Some(candidate) => {
+                require!(
+                    removed.iter().all(|(listing, _)| *listing != candidate.key()),
+                    MarketplaceError::InvalidFloorCandidate
+                );
+                Some(self.validate_candidate(candidate, Pubkey::default())?)
+            }
+            None => None,
+        };
+
+        for (_, mint) in removed {
+            self.on_removed(*mint, None);
+        }
+        if let Some((mint, price)) = candidate {
+            self.on_listed(mint, price);
+        }
+        Ok(())
+    }
+
+    /// Check that `candidate` is a live listing of this collection on this
+    /// marketplace, other than `excluded`, and return its (mint, price)
+    pub fn validate_candidate(&self, candidate: &Account<Listing>, excluded: Pubkey) -> Result<(Pubkey, u64)> {
+        let expected = Pubkey::create_program_address(
+            &[self.marketplace.as_ref(), candidate.maker_mint.as_ref(), &[candidate.bump]],
+            &crate::ID,
+        ).map_err(|_| MarketplaceError::InvalidFloorCandidate)?;
+        require_keys_eq!(expected, candidate.key(), MarketplaceError::InvalidFloorCandidate);
+        require_keys_eq!(candidate.collection, self.collection_mint, MarketplaceError::InvalidFloorCandidate);
+        require_keys_neq!(candidate.key(), excluded, MarketplaceError::InvalidFloorCandidate);
+
+        Ok((candidate.maker_mint, candidate.price))
+    }
+}
+
+impl Space for CollectionStats {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for marketplace
+    /// - 32 bytes: Pubkey for collection_mint
+    /// - 8 bytes: u64 for floor_price
+    /// - 32 bytes: Pubkey for floor_mint
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 32 + 1;
+}
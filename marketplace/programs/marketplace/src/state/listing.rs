@@ -2,10 +2,19 @@
  * Listing State Account
  * 
  * Stores information about an individual NFT listing.
+ * Dutch auction listings also store their price curve: the price falls
+ * linearly from `start_price` to `end_price` over `duration` seconds and
+ * stays at `end_price` afterwards.
+ * English auction listings also store their bidding state: bids open at
+ * `price` and each must beat the last until `end_time`, after which the
+ * highest bidder has `settle_window` seconds to pay (see `state/bid.rs`).
  * This account is a PDA derived from the marketplace and NFT mint.
  */
 
 use anchor_lang::prelude::*;
+use common_math::mul_div_floor;
+
+use crate::error::MarketplaceError;
 
 #[account]
 pub struct Listing {
@@ -15,16 +24,83 @@ pub struct Listing {
     pub maker_mint: Pubkey,
     /// The selling price in lamports (SOL's smallest unit)
     pub price: u64,
+    /// If set, only this wallet may purchase the listing (private sale)
+    pub allowed_buyer: Option<Pubkey>,
+    /// The verified collection the NFT was listed under
+    pub collection: Pubkey,
+    /// Price curve of a Dutch auction listing (None = fixed price)
+    pub dutch_auction: Option<DutchAuction>,
+    /// Bidding state of an English auction listing (None = not an English auction)
+    pub english_auction: Option<EnglishAuction>,
     /// PDA bump seed for the listing account
     pub bump: u8,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct DutchAuction {
+    /// Price in lamports when the auction starts
+    pub start_price: u64,
+    /// Price in lamports from the end of the auction on
+    pub end_price: u64,
+    /// Unix timestamp the auction started at
+    pub start_time: i64,
+    /// Seconds the price takes to fall from start to end
+    pub duration: i64,
+}
+
+impl DutchAuction {
+    /// The price at `now`, interpolated linearly and rounded up
+    pub fn price_at(&self, now: i64) -> Result<u64> {
+        let elapsed = now.saturating_sub(self.start_time).clamp(0, self.duration);
+        let decrease = mul_div_floor(
+            self.start_price - self.end_price,
+            elapsed as u64,
+            self.duration as u64,
+        ).ok_or(MarketplaceError::MathOverflow)?;
+        Ok(self.start_price - decrease)
+    }
+}
+
+impl Listing {
+    /// Whether an English auction listing has a live highest bid, which
+    /// keeps the NFT in the listing until it is settled or forfeited
+    pub fn has_bids(&self) -> bool {
+        self.english_auction.is_some_and(|auction| auction.highest_bidder.is_some())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct EnglishAuction {
+    /// Unix timestamp bidding closes at
+    pub end_time: i64,
+    /// Seconds after `end_time` the highest bidder has to settle
+    pub settle_window: i64,
+    /// Highest bid in lamports so far (0 = no bids)
+    pub highest_bid: u64,
+    /// Wallet holding the highest bid, if any
+    pub highest_bidder: Option<Pubkey>,
+}
+
+impl EnglishAuction {
+    /// Unix timestamp after which an unsettled winning bid can be forfeited
+    pub fn settle_deadline(&self) -> Result<i64> {
+        let deadline = self.end_time
+            .checked_add(self.settle_window)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(deadline)
+    }
+}
+
 impl Space for Listing {
     /// Calculate the exact space needed for this account:
     /// - 8 bytes: Account discriminator (automatically added by Anchor)
     /// - 32 bytes: Pubkey for maker
     /// - 32 bytes: Pubkey for maker_mint
     /// - 8 bytes: u64 for price
+    /// - 33 bytes: Option<Pubkey> for allowed_buyer (1 tag + 32)
+    /// - 32 bytes: Pubkey for collection
+    /// - 33 bytes: Option<DutchAuction> for dutch_auction (1 tag + 8 + 8 + 8 + 8)
+    /// - 58 bytes: Option<EnglishAuction> for english_auction (1 tag + 8 + 8 + 8 + 33)
     /// - 1 byte: u8 for bump
-    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 1;
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + (1 + 32) + 32 + (1 + 8 + 8 + 8 + 8) + (1 + 8 + 8 + 8 + 1 + 32) + 1;
 }
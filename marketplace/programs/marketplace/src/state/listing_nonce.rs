@@ -0,0 +1,22 @@
+/**
+ * Listing Nonce Account
+ * 
+ * Marks a signed (lazy) listing as consumed. Its PDA is derived from the
+ * marketplace, maker and the nonce the maker signed, so creating it a second
+ * time fails and the same signed listing can't be bought twice.
+ */
+
+use anchor_lang::prelude::*;
+
+#[account]
+pub struct ListingNonce {
+    /// PDA bump seed for the listing nonce account
+    pub bump: u8,
+}
+
+impl Space for ListingNonce {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 1;
+}
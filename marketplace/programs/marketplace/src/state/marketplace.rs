@@ -7,6 +7,8 @@
 
 use anchor_lang::prelude::*;
 
+use crate::error::MarketplaceError;
+
 #[account]
 pub struct Marketplace {
     /// The wallet address of the marketplace administrator/authority
@@ -21,6 +23,53 @@ pub struct Marketplace {
     pub rewards_bump: u8,
     /// The name of the marketplace used for branding and identification
     pub name: String,
+    /// Sum of all sale prices in lamports, for leaderboards
+    pub total_volume: u64,
+    /// Number of completed purchases, for leaderboards
+    pub total_sales: u64,
+    /// When set, listed NFTs must have at least one verified creator
+    pub require_verified_creator: bool,
+    /// Flat fee in lamports charged to the maker on each listing (0 = free)
+    pub listing_fee: u64,
+    /// When set, purchases pay creators their metadata royalties; when unset,
+    /// royalties are skipped and a `RoyaltiesBypassed` event is emitted
+    pub enforce_royalties: bool,
+    /// Seconds the proceeds of a sale are held in a sale escrow, during which
+    /// the buyer can raise a dispute (0 = paid to the maker immediately)
+    pub dispute_window: i64,
+    /// Most reward tokens that may ever be minted; once the reward mint's supply
+    /// reaches it, sales complete without rewards (0 = uncapped)
+    pub reward_supply_cap: u64,
+}
+
+impl Marketplace {
+    /// Add a sale at `price` to the cumulative volume and count
+    pub fn record_sale(&mut self, price: u64) -> Result<()> {
+        self.total_volume = self.total_volume
+            .checked_add(price)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        self.total_sales = self.total_sales
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Require a sale escrow exactly when the marketplace holds proceeds for a dispute window
+    pub fn check_buyer_protection(&self, has_sale_escrow: bool) -> Result<()> {
+        require!(
+            has_sale_escrow == (self.dispute_window > 0),
+            MarketplaceError::SaleEscrowMismatch
+        );
+        Ok(())
+    }
+
+    /// Part of a `requested` reward that may still be minted on top of `current_supply`
+    pub fn mintable_reward(&self, requested: u64, current_supply: u64) -> u64 {
+        if self.reward_supply_cap == 0 {
+            return requested;
+        }
+        requested.min(self.reward_supply_cap.saturating_sub(current_supply))
+    }
 }
 
 impl Space for Marketplace {
@@ -32,5 +81,12 @@ impl Space for Marketplace {
     /// - 1 byte: u8 for treasury_bump
     /// - 1 byte: u8 for rewards_bump
     /// - 4 bytes: String prefix (length) + 32 bytes max for name content
-    const INIT_SPACE: usize = 8 + 32 + 2 + 1 + 1 + 1 + (4 + 32);
+    /// - 8 bytes: u64 for total_volume
+    /// - 8 bytes: u64 for total_sales
+    /// - 1 byte: bool for require_verified_creator
+    /// - 8 bytes: u64 for listing_fee
+    /// - 1 byte: bool for enforce_royalties
+    /// - 8 bytes: i64 for dispute_window
+    /// - 8 bytes: u64 for reward_supply_cap
+    const INIT_SPACE: usize = 8 + 32 + 2 + 1 + 1 + 1 + (4 + 32) + 8 + 8 + 1 + 8 + 1 + 8 + 8;
 }
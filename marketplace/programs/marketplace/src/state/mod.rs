@@ -3,3 +3,24 @@ pub use marketplace::*;
 
 pub mod listing;
 pub use listing::*;
+
+pub mod offer;
+pub use offer::*;
+
+pub mod bid;
+pub use bid::*;
+
+pub mod collection_stats;
+pub use collection_stats::*;
+
+pub mod listing_nonce;
+pub use listing_nonce::*;
+
+pub mod sale_escrow;
+pub use sale_escrow::*;
+
+pub mod collection_config;
+pub use collection_config::*;
+
+pub mod seller_stats;
+pub use seller_stats::*;
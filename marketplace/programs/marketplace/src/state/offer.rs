@@ -0,0 +1,34 @@
+/**
+ * Offer State Account
+ * 
+ * A bid on a specific NFT. The offered lamports are escrowed in this account,
+ * on top of its rent, until the seller accepts or the bidder cancels.
+ * This account is a PDA derived from the marketplace, NFT mint and bidder.
+ */
+
+use anchor_lang::prelude::*;
+
+#[account]
+pub struct Offer {
+    /// The wallet that made the offer and receives the NFT
+    pub bidder: Pubkey,
+    /// The marketplace the offer was made on
+    pub marketplace: Pubkey,
+    /// The mint address of the NFT being bid on
+    pub maker_mint: Pubkey,
+    /// The offered price in lamports, held by this account
+    pub amount: u64,
+    /// PDA bump seed for the offer account
+    pub bump: u8,
+}
+
+impl Space for Offer {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for bidder
+    /// - 32 bytes: Pubkey for marketplace
+    /// - 32 bytes: Pubkey for maker_mint
+    /// - 8 bytes: u64 for amount
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 32 + 8 + 1;
+}
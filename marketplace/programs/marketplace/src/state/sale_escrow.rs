@@ -0,0 +1,118 @@
+/**
+ * Sale Escrow State Account
+ * 
+ * Holds a protected sale's proceeds for the marketplace's dispute window.
+ * The maker's share of the price sits in this account, on top of its rent,
+ * until it is released to the maker or, after an upheld dispute, refunded
+ * to the buyer. This account is a PDA derived from the marketplace, NFT mint
+ * and buyer.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::error::MarketplaceError;
+use crate::pda::create_pda;
+use crate::state::Marketplace;
+
+#[account]
+pub struct SaleEscrow {
+    /// The marketplace the sale happened on
+    pub marketplace: Pubkey,
+    /// The seller the proceeds are owed to
+    pub maker: Pubkey,
+    /// The buyer who may confirm receipt or raise a dispute
+    pub buyer: Pubkey,
+    /// The mint address of the NFT sold
+    pub maker_mint: Pubkey,
+    /// The escrowed proceeds in lamports, held by this account
+    pub amount: u64,
+    /// Unix timestamp when the dispute window ends and the proceeds can be released
+    pub release_at: i64,
+    /// Set by the buyer; freezes the proceeds until the admin resolves the dispute
+    pub disputed: bool,
+    /// PDA bump seed for the sale escrow account
+    pub bump: u8,
+}
+
+impl SaleEscrow {
+    /// Escrow for `buyer`'s purchase of `maker_mint` on `marketplace`, holding
+    /// `amount` until the marketplace's dispute window has passed
+    pub fn new(
+        marketplace: &Account<Marketplace>,
+        maker: Pubkey,
+        buyer: Pubkey,
+        maker_mint: Pubkey,
+        amount: u64,
+        bump: u8,
+    ) -> Result<SaleEscrow> {
+        let release_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(marketplace.dispute_window)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(SaleEscrow {
+            marketplace: marketplace.key(),
+            maker,
+            buyer,
+            maker_mint,
+            amount,
+            release_at,
+            disputed: false,
+            bump,
+        })
+    }
+
+    /// Create the PDA `escrow_info` for `sale`, with `payer` funding its rent,
+    /// for sales whose buyer is only known once the handler runs. The caller
+    /// then moves `sale.amount` into it.
+    pub fn create<'info>(
+        escrow_info: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        mut sale: SaleEscrow,
+    ) -> Result<()> {
+        let (expected, bump) = Pubkey::find_program_address(
+            &[b"sale_escrow", sale.marketplace.as_ref(), sale.maker_mint.as_ref(), sale.buyer.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(escrow_info.key(), expected, MarketplaceError::SaleEscrowMismatch);
+        sale.bump = bump;
+
+        let seeds = &[
+            b"sale_escrow",
+            sale.marketplace.as_ref(),
+            sale.maker_mint.as_ref(),
+            sale.buyer.as_ref(),
+            &[bump]
+        ];
+        create_pda(escrow_info, payer, system_program, seeds, SaleEscrow::INIT_SPACE, MarketplaceError::SaleEscrowMismatch)?;
+
+        sale.try_serialize(&mut &mut escrow_info.try_borrow_mut_data()?[..])
+    }
+
+    /// Reject settling or disputing a sale that is under dispute
+    pub fn ensure_undisputed(&self) -> Result<()> {
+        require!(!self.disputed, MarketplaceError::SaleDisputed);
+        Ok(())
+    }
+
+    /// Pay the escrowed proceeds out to `recipient`
+    pub fn pay_out<'info>(escrow: &Account<'info, SaleEscrow>, recipient: &AccountInfo<'info>) -> Result<()> {
+        escrow.sub_lamports(escrow.amount)?;
+        recipient.add_lamports(escrow.amount)?;
+        Ok(())
+    }
+}
+
+impl Space for SaleEscrow {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for marketplace
+    /// - 32 bytes: Pubkey for maker
+    /// - 32 bytes: Pubkey for buyer
+    /// - 32 bytes: Pubkey for maker_mint
+    /// - 8 bytes: u64 for amount
+    /// - 8 bytes: i64 for release_at
+    /// - 1 byte: bool for disputed
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 32 + 32 + 8 + 8 + 1 + 1;
+}
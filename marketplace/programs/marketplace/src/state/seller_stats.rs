@@ -0,0 +1,104 @@
+/**
+ * Seller Stats Account
+ * 
+ * Tracks a seller's track record on a marketplace: completed purchases of
+ * their listings, the lamport volume of those sales, and disputes upheld
+ * against them. Every sale path (`purchase`, sweeps, offer sales and signed
+ * listings) creates it on the seller's first sale and counts each sale, and
+ * `resolve_dispute` counts upheld disputes. This account is a PDA derived
+ * from the marketplace and seller.
+ */
+
+use anchor_lang::prelude::*;
+
+use crate::error::MarketplaceError;
+use crate::pda::create_pda;
+
+/// Reputation lost per upheld dispute, in sales
+pub const DISPUTE_REPUTATION_PENALTY: i64 = 5;
+
+#[account]
+pub struct SellerStats {
+    /// The marketplace these stats belong to
+    pub marketplace: Pubkey,
+    /// The seller these stats describe
+    pub seller: Pubkey,
+    /// Number of the seller's completed sales
+    pub sales_count: u64,
+    /// Sum of those sales' prices in lamports
+    pub total_volume: u64,
+    /// Number of disputes resolved in the buyer's favor
+    pub disputes: u64,
+    /// PDA bump seed for the seller stats account
+    pub bump: u8,
+}
+
+impl SellerStats {
+    /// Add a sale at `price` to the seller's record
+    pub fn record_sale(&mut self, price: u64) -> Result<()> {
+        self.sales_count = self.sales_count
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        self.total_volume = self.total_volume
+            .checked_add(price)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Record a sale at `price` for `seller` in their stats PDA `stats_info`,
+    /// created with `payer`'s lamports on their first sale, for sale paths
+    /// that only know the seller once the handler runs
+    pub fn record_sale_for<'info>(
+        stats_info: &AccountInfo<'info>,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+        marketplace: Pubkey,
+        seller: Pubkey,
+        price: u64,
+    ) -> Result<()> {
+        let (expected, bump) = Pubkey::find_program_address(
+            &[b"seller_stats", marketplace.as_ref(), seller.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(stats_info.key(), expected, MarketplaceError::SellerStatsMismatch);
+
+        let mut stats = if stats_info.owner == &crate::ID {
+            SellerStats::try_deserialize(&mut &stats_info.try_borrow_data()?[..])?
+        } else {
+            let seeds = &[b"seller_stats", marketplace.as_ref(), seller.as_ref(), &[bump]];
+            create_pda(stats_info, payer, system_program, seeds, SellerStats::INIT_SPACE, MarketplaceError::SellerStatsMismatch)?;
+            SellerStats { marketplace, seller, sales_count: 0, total_volume: 0, disputes: 0, bump }
+        };
+        stats.record_sale(price)?;
+        stats.try_serialize(&mut &mut stats_info.try_borrow_mut_data()?[..])
+    }
+
+    /// Count a dispute upheld against the seller
+    pub fn record_dispute(&mut self) -> Result<()> {
+        self.disputes = self.disputes
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// One point per sale, less `DISPUTE_REPUTATION_PENALTY` per upheld dispute
+    pub fn reputation(&self) -> i64 {
+        let sales = i64::try_from(self.sales_count).unwrap_or(i64::MAX);
+        let penalty = i64::try_from(self.disputes)
+            .unwrap_or(i64::MAX)
+            .saturating_mul(DISPUTE_REPUTATION_PENALTY);
+        sales.saturating_sub(penalty)
+    }
+}
+
+impl Space for SellerStats {
+    /// Calculate the exact space needed for this account:
+    /// - 8 bytes: Account discriminator (automatically added by Anchor)
+    /// - 32 bytes: Pubkey for marketplace
+    /// - 32 bytes: Pubkey for seller
+    /// - 8 bytes: u64 for sales_count
+    /// - 8 bytes: u64 for total_volume
+    /// - 8 bytes: u64 for disputes
+    /// - 1 byte: u8 for bump
+    const INIT_SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1;
+}
@@ -0,0 +1,1409 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Turbin3Rust } from "../target/types/turbin3_rust";
+import {
+  Ed25519Program,
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  sendAndConfirmTransaction,
+} from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
+  approve,
+  createMint,
+  mintTo,
+  getAccount,
+  getMint,
+  getMintLen,
+  getAssociatedTokenAddressSync,
+  createAssociatedTokenAccount,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeMintInstruction,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import {
+  Lp,
+  Pool,
+  balance,
+  claimRewards,
+  createPool,
+  createStakingPool,
+  deposit,
+  fundLp,
+  fundRewards,
+  fundedKeypair,
+  lpPosition,
+  pda,
+  poolAddresses,
+  quote,
+  seededPool,
+  sleep,
+  sortedMints,
+  stake,
+  swap,
+  swapAccounts,
+  userStakeOf,
+} from "./helpers";
+
+describe("AMM", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Turbin3Rust as Program<Turbin3Rust>;
+
+  const PRICE_PRECISION = new anchor.BN(1_000_000_000);
+
+  // B-per-A price of the vaults, scaled like the program's
+  const spotPrice = async (pool: Pool) =>
+    (await balance(pool.tokenBVault)).mul(PRICE_PRECISION).div(await balance(pool.tokenAVault));
+
+  describe("Initialize And Deposit", () => {
+    let admin: Keypair;
+
+    // 4 B per A; the first deposit mints the geometric mean of the reserves
+    const AMOUNT_A = new anchor.BN(1_000_000_000);
+    const AMOUNT_B = new anchor.BN(4_000_000_000);
+    const EXPECTED_LP = new anchor.BN(2_000_000_000);
+
+    // Fresh mints in canonical order, with the admin's funded token accounts
+    const setupPool = async () => {
+      const pool = poolAddresses(...(await sortedMints(admin)));
+      const adminTokenA = await createAssociatedTokenAccount(provider.connection, admin, pool.tokenAMint, admin.publicKey);
+      const adminTokenB = await createAssociatedTokenAccount(provider.connection, admin, pool.tokenBMint, admin.publicKey);
+      await mintTo(provider.connection, admin, pool.tokenAMint, adminTokenA, admin, AMOUNT_A.toNumber());
+      await mintTo(provider.connection, admin, pool.tokenBMint, adminTokenB, admin, AMOUNT_B.toNumber());
+
+      return {
+        ...pool,
+        adminTokenA,
+        adminTokenB,
+        adminLpToken: getAssociatedTokenAddressSync(pool.lpMint, admin.publicKey),
+        lpPosition: lpPosition(pool, admin.publicKey),
+      };
+    };
+
+    const initializeAndDeposit = (pool: Awaited<ReturnType<typeof setupPool>>, minLpTokens: anchor.BN) =>
+      program.methods
+        .initializeAmmAndDeposit(30, AMOUNT_A, AMOUNT_B, minLpTokens)
+        .accounts({
+          admin: admin.publicKey,
+          ...pool,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      admin = await fundedKeypair();
+    });
+
+    it("Creates and seeds a pool in one instruction", async () => {
+      const pool = await setupPool();
+      await initializeAndDeposit(pool, EXPECTED_LP);
+
+      const amm = await program.account.ammState.fetch(pool.amm);
+      expect(amm.admin.toString()).to.equal(admin.publicKey.toString());
+      expect(amm.fee).to.equal(30);
+      expect(amm.curveType).to.have.property("constantProduct");
+
+      expect((await balance(pool.tokenAVault)).toString()).to.equal(AMOUNT_A.toString());
+      expect((await balance(pool.tokenBVault)).toString()).to.equal(AMOUNT_B.toString());
+      expect((await balance(pool.adminLpToken)).toString()).to.equal(EXPECTED_LP.toString());
+      console.log("✅ Pool created with", EXPECTED_LP.toString(), "LP minted to the admin");
+    });
+
+    it("Creates nothing when the first deposit misses its minimum", async () => {
+      const pool = await setupPool();
+      try {
+        await initializeAndDeposit(pool, EXPECTED_LP.addn(1));
+        expect.fail("Seeded a pool below the LP minimum");
+      } catch (error) {
+        expect(error.toString()).to.include("SlippageExceeded");
+      }
+      expect(await provider.connection.getAccountInfo(pool.amm)).to.be.null;
+      expect((await balance(pool.adminTokenA)).toString()).to.equal(AMOUNT_A.toString());
+    });
+  });
+
+  describe("Pool Events", () => {
+    it("Emits PoolInitialized with the created pool's config", async () => {
+      const admin = await fundedKeypair(2);
+      const pool = poolAddresses(...(await sortedMints(admin)));
+
+      const tx = await program.methods
+        .initializeAmm(45, 0, null, null, 0, null, null)
+        .accounts({
+          admin: admin.publicKey,
+          amm: pool.amm,
+          tokenAMint: pool.tokenAMint,
+          tokenBMint: pool.tokenBMint,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc({ commitment: "confirmed" });
+
+      const txInfo = await provider.connection.getTransaction(tx, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const events = [...parser.parseLogs(txInfo.meta.logMessages)].filter(event => event.name === "poolInitialized");
+      expect(events).to.have.lengthOf(1);
+
+      const payload = events[0].data;
+      const state = await program.account.ammState.fetch(pool.amm);
+      expect(payload.amm.toString()).to.equal(pool.amm.toString());
+      expect(payload.admin.toString()).to.equal(state.admin.toString());
+      expect(payload.tokenAMint.toString()).to.equal(state.tokenAMint.toString());
+      expect(payload.tokenBMint.toString()).to.equal(state.tokenBMint.toString());
+      expect(payload.tokenAVault.toString()).to.equal(state.tokenAVault.toString());
+      expect(payload.tokenBVault.toString()).to.equal(state.tokenBVault.toString());
+      expect(payload.lpMint.toString()).to.equal(state.lpMint.toString());
+      expect(payload.fee).to.equal(state.fee);
+    });
+  });
+
+  describe("LP Decimals", () => {
+    let user: Keypair;
+
+    // 1 and 4 whole 9-decimal tokens
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000_000);
+
+    before(async () => {
+      user = await fundedKeypair();
+    });
+
+    it("Defaults LP decimals to the pool mints' and keeps LP units in whole tokens", async () => {
+      const { pool, lp } = await seededPool(user, DEPOSIT_A, DEPOSIT_B, { decimals: 9 });
+      const lpTokens = await balance(lp.lpToken);
+
+      const lpMintInfo = await getMint(provider.connection, pool.lpMint);
+      expect(lpMintInfo.decimals).to.equal(9);
+      expect((await program.account.ammState.fetch(pool.amm)).lpDecimals).to.equal(9);
+
+      // sqrt(1e9 * 4e9) = 2e9 base units: 2 whole LP tokens for 1 A + 4 B
+      expect(lpTokens.toString()).to.equal("2000000000");
+      expect(lpTokens.toNumber() / 10 ** lpMintInfo.decimals).to.equal(2);
+    });
+
+    it("Honors explicit LP decimals", async () => {
+      const { pool } = await seededPool(user, DEPOSIT_A, DEPOSIT_B, { decimals: 9, lpDecimals: 6 });
+
+      expect((await getMint(provider.connection, pool.lpMint)).decimals).to.equal(6);
+      expect((await program.account.ammState.fetch(pool.amm)).lpDecimals).to.equal(6);
+    });
+  });
+
+  describe("Spot Price", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    // 4 B per A, and a swap far too small to move the price
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000_000);
+    const SWAP_AMOUNT = new anchor.BN(100_000);
+    const FEE_BPS = 30;
+
+    const getSpotPrice = () =>
+      program.methods
+        .getSpotPrice()
+        .accounts({ amm: pool.amm, tokenAVault: pool.tokenAVault, tokenBVault: pool.tokenBVault, observations: null })
+        .view();
+
+    before(async () => {
+      const user = await fundedKeypair();
+      pool = await createPool(user, { fee: FEE_BPS });
+      lp = await fundLp(pool, user, user, DEPOSIT_A.muln(2), DEPOSIT_B);
+    });
+
+    it("Refuses to price an empty pool", async () => {
+      try {
+        await getSpotPrice();
+        expect.fail("Priced a pool with no reserves");
+      } catch (error) {
+        expect(error.toString()).to.include("PoolInsufficientLiquidity");
+      }
+    });
+
+    it("Returns the price a small swap fills at", async () => {
+      await deposit(pool, lp, DEPOSIT_A, DEPOSIT_B);
+
+      const spot = await getSpotPrice();
+      expect(spot.priceBPerA.toString()).to.equal(PRICE_PRECISION.muln(4).toString());
+      expect(spot.priceAPerB.toString()).to.equal(PRICE_PRECISION.divn(4).toString());
+
+      const before = await balance(lp.tokenB);
+      await swap(pool, lp, true, SWAP_AMOUNT);
+      const amountOut = (await balance(lp.tokenB)).sub(before);
+
+      // Once the fee is added back, the fill matches the spot price to within 0.1%
+      const expectedOut = SWAP_AMOUNT.mul(spot.priceBPerA).div(PRICE_PRECISION);
+      const grossOut = amountOut.muln(10_000).divn(10_000 - FEE_BPS);
+      expect(grossOut.lte(expectedOut)).to.be.true;
+      expect(expectedOut.sub(grossOut).muln(1_000).lte(expectedOut)).to.be.true;
+      console.log("✅ Spot price", spot.priceBPerA.toString(), "vs small swap fill", amountOut.toString(), "B for", SWAP_AMOUNT.toString(), "A");
+    });
+  });
+
+  describe("Explicit Swap Direction", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const SWAP_AMOUNT = new anchor.BN(10_000_000);
+
+    const swapWith = (zeroForOne: boolean, userTokenIn: PublicKey, userTokenOut: PublicKey) =>
+      program.methods
+        .swapTokens(SWAP_AMOUNT, new anchor.BN(0), zeroForOne)
+        .accounts({ ...swapAccounts(pool, lp, zeroForOne), userTokenIn, userTokenOut })
+        .signers([lp.wallet])
+        .rpc();
+
+    before(async () => {
+      const user = await fundedKeypair();
+      ({ pool, lp } = await seededPool(user, DEPOSIT, DEPOSIT, { extraA: SWAP_AMOUNT, extraB: SWAP_AMOUNT }));
+    });
+
+    it("Swaps A for B with zero_for_one", async () => {
+      const vaultABefore = await balance(pool.tokenAVault);
+      const userBBefore = await balance(lp.tokenB);
+
+      await swapWith(true, lp.tokenA, lp.tokenB);
+
+      expect((await balance(pool.tokenAVault)).sub(vaultABefore).toString()).to.equal(SWAP_AMOUNT.toString());
+      expect((await balance(lp.tokenB)).gt(userBBefore)).to.be.true;
+    });
+
+    it("Swaps B for A without it", async () => {
+      const vaultBBefore = await balance(pool.tokenBVault);
+      const userABefore = await balance(lp.tokenA);
+
+      await swapWith(false, lp.tokenB, lp.tokenA);
+
+      expect((await balance(pool.tokenBVault)).sub(vaultBBefore).toString()).to.equal(SWAP_AMOUNT.toString());
+      expect((await balance(lp.tokenA)).gt(userABefore)).to.be.true;
+    });
+
+    it("Names each misordering of the token accounts", async () => {
+      // In and out swapped, for each direction; A and B both in the same slot
+      const misorderings: [boolean, PublicKey, PublicKey][] = [
+        [true, lp.tokenB, lp.tokenA],
+        [false, lp.tokenA, lp.tokenB],
+        [true, lp.tokenA, lp.tokenA],
+        [false, lp.tokenB, lp.tokenB],
+      ];
+      for (const [zeroForOne, userTokenIn, userTokenOut] of misorderings) {
+        try {
+          await swapWith(zeroForOne, userTokenIn, userTokenOut);
+          expect.fail("Swapped with misordered token accounts");
+        } catch (error) {
+          expect(error.toString()).to.include("WrongTokenAccountForDirection");
+        }
+      }
+    });
+
+    it("Still rejects token accounts outside the pool", async () => {
+      const user = lp.wallet;
+      const foreignMint = await createMint(provider.connection, user, user.publicKey, null, 6);
+      const foreignAccount = await createAssociatedTokenAccount(provider.connection, user, foreignMint, user.publicKey);
+      await mintTo(provider.connection, user, foreignMint, foreignAccount, user, SWAP_AMOUNT.toNumber());
+
+      try {
+        await swapWith(true, foreignAccount, lp.tokenB);
+        expect.fail("Swapped in a token the pool doesn't hold");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidSwapMint");
+      }
+
+      try {
+        await swapWith(true, lp.tokenA, foreignAccount);
+        expect.fail("Swapped out into a token the pool doesn't hold");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidOutputAccount");
+      }
+    });
+  });
+
+  describe("Arbitrage Hints", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000_000);
+
+    const hint = (referencePrice: anchor.BN) =>
+      program.methods
+        .arbitrageHint(referencePrice)
+        .accounts({ amm: pool.amm, tokenAVault: pool.tokenAVault, tokenBVault: pool.tokenBVault, observations: null })
+        .view();
+
+    // |actual - expected| within 0.1% of expected
+    const expectNear = (actual: anchor.BN, expected: anchor.BN) =>
+      expect(actual.sub(expected).abs().lte(expected.divn(1000))).to.be.true;
+
+    before(async () => {
+      const user = await fundedKeypair();
+      ({ pool, lp } = await seededPool(user, DEPOSIT_A, DEPOSIT_B, { extraA: DEPOSIT_A, extraB: DEPOSIT_B }));
+    });
+
+    it("Suggests a swap that moves the spot price to a higher reference", async () => {
+      const reference = PRICE_PRECISION.muln(5);
+      const suggestion = await hint(reference);
+
+      // Raising the B-per-A price means selling B into the pool
+      expect(suggestion.zeroForOne).to.be.false;
+      expect(suggestion.amountIn.gtn(0)).to.be.true;
+
+      await swap(pool, lp, suggestion.zeroForOne, suggestion.amountIn);
+      expectNear(await spotPrice(pool), reference);
+    });
+
+    it("Suggests a swap that moves the spot price to a lower reference", async () => {
+      const reference = PRICE_PRECISION.muln(3);
+      const suggestion = await hint(reference);
+      expect(suggestion.zeroForOne).to.be.true;
+
+      await swap(pool, lp, suggestion.zeroForOne, suggestion.amountIn);
+      expectNear(await spotPrice(pool), reference);
+
+      // Once aligned, only a rounding-sized swap is left to suggest
+      expect((await hint(await spotPrice(pool))).amountIn.toNumber()).to.be.lessThan(10);
+    });
+  });
+
+  describe("StableSwap Curve", () => {
+    let user: Keypair;
+
+    // 1_000 whole 6-decimal tokens on each side, and a 1% trade against them
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const SWAP_AMOUNT = new anchor.BN(10_000_000);
+    const STABLE = { stableSwap: { amp: new anchor.BN(100) } };
+
+    // A fresh 1:1 pool on `curveType` seeded with DEPOSIT of each token
+    const seedPool = (curveType: any) => seededPool(user, DEPOSIT, DEPOSIT, { fee: 4, curveType, extraA: SWAP_AMOUNT });
+
+    before(async () => {
+      user = await fundedKeypair();
+    });
+
+    it("Prices near-1:1 swaps tighter than constant product", async () => {
+      const constantProduct = await seedPool(null);
+      const stable = await seedPool(STABLE);
+      expect((await program.account.ammState.fetch(stable.pool.amm)).curveType).to.have.property("stableSwap");
+
+      const constantProductQuote = await quote(constantProduct.pool, SWAP_AMOUNT);
+      const stableQuote = await quote(stable.pool, SWAP_AMOUNT);
+      expect(stableQuote.amountOut.gt(constantProductQuote.amountOut)).to.be.true;
+      expect(stableQuote.priceImpactBps.lt(constantProductQuote.priceImpactBps)).to.be.true;
+      // Within 0.01% of 1:1 after the 0.04% fee
+      expect(stableQuote.amountOut.gte(SWAP_AMOUNT.sub(stableQuote.feeAmount).muln(9999).divn(10000))).to.be.true;
+
+      // The swap executes at the quoted stable price
+      await swap(stable.pool, stable.lp, true, SWAP_AMOUNT, stableQuote.amountOut);
+      expect((await balance(stable.lp.tokenB)).toString()).to.equal(stableQuote.amountOut.toString());
+
+      console.log(
+        "✅ 1% swap out: stable",
+        stableQuote.amountOut.toString(),
+        "vs constant product",
+        constantProductQuote.amountOut.toString()
+      );
+    });
+
+    it("Rejects constant-product-only instructions on stable pools", async () => {
+      const { pool } = await seedPool(STABLE);
+
+      try {
+        await program.methods
+          .arbitrageHint(new anchor.BN(1_000_000_000))
+          .accounts({ amm: pool.amm, tokenAVault: pool.tokenAVault, tokenBVault: pool.tokenBVault, observations: null })
+          .view();
+        expect.fail("Hinted a stable pool with constant-product math");
+      } catch (error) {
+        expect(error.toString()).to.include("UnsupportedCurve");
+      }
+    });
+  });
+
+  describe("Concentrated Liquidity", () => {
+    let user: Keypair;
+
+    // 1_000 whole 6-decimal tokens on each side, and a 1% trade against them
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const SWAP_AMOUNT = new anchor.BN(10_000_000);
+    // Liquidity active between 0.5 and 2 B per A (scaled by PRICE_PRECISION)
+    const BAND = { concentrated: { priceLower: PRICE_PRECISION.divn(2), priceUpper: PRICE_PRECISION.muln(2) } };
+
+    // A fresh 1:1 pool on `curveType` seeded with DEPOSIT of each token
+    const seedPool = (curveType: any) => seededPool(user, DEPOSIT, DEPOSIT, { curveType, extraA: DEPOSIT.muln(2) });
+
+    before(async () => {
+      user = await fundedKeypair();
+    });
+
+    it("Fills an in-range swap deeper than a full-range pool", async () => {
+      const fullRange = await seedPool(null);
+      const concentrated = await seedPool(BAND);
+      expect((await program.account.ammState.fetch(concentrated.pool.amm)).curveType).to.have.property("concentrated");
+
+      const fullRangeQuote = await quote(fullRange.pool, SWAP_AMOUNT);
+      const concentratedQuote = await quote(concentrated.pool, SWAP_AMOUNT);
+      expect(concentratedQuote.amountOut.gt(fullRangeQuote.amountOut)).to.be.true;
+      expect(concentratedQuote.priceImpactBps.lt(fullRangeQuote.priceImpactBps)).to.be.true;
+
+      // The swap executes at the quoted concentrated price, against the same real reserves
+      const before = await balance(concentrated.lp.tokenB);
+      await swap(concentrated.pool, concentrated.lp, true, SWAP_AMOUNT, concentratedQuote.amountOut);
+      expect((await balance(concentrated.lp.tokenB)).sub(before).toString()).to.equal(concentratedQuote.amountOut.toString());
+
+      console.log(
+        "✅ 1% swap out: concentrated",
+        concentratedQuote.amountOut.toString(),
+        "vs full range",
+        fullRangeQuote.amountOut.toString()
+      );
+    });
+
+    it("Rejects a price move that would leave the range, then trades back inside it", async () => {
+      const { pool, lp } = await seedPool(BAND);
+
+      // Selling about 1_414 A walks the price down to the 0.5 bound; 2_000 would cross it
+      const tooFar = DEPOSIT.muln(2);
+      try {
+        await quote(pool, tooFar);
+        expect.fail("Quoted a swap past the range");
+      } catch (error) {
+        expect(error.toString()).to.include("PriceOutOfRange");
+      }
+      try {
+        await swap(pool, lp, true, tooFar);
+        expect.fail("Swapped past the range");
+      } catch (error) {
+        expect(error.toString()).to.include("PriceOutOfRange");
+      }
+
+      // The rejected swap moved nothing; a move that stays inside the band still fills
+      expect((await balance(pool.tokenBVault)).toString()).to.equal(DEPOSIT.toString());
+      const inside = DEPOSIT.muln(13).divn(10);
+      const insideQuote = await quote(pool, inside);
+      await swap(pool, lp, true, inside, insideQuote.amountOut);
+
+      // Nearly all of the band's B is gone, and the pool still holds what's left
+      const vaultB = await balance(pool.tokenBVault);
+      expect(vaultB.lt(DEPOSIT.divn(10))).to.be.true;
+      expect(vaultB.gtn(0)).to.be.true;
+      console.log("✅ Range held; B left in the band after a deep in-range swap:", vaultB.toString());
+    });
+  });
+
+  describe("Dynamic Fee", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const MIN_FEE = 10;
+    const MAX_FEE = 200;
+    const OBSERVATION_CAPACITY = 16;
+
+    // Fee in bps a swap would pay right now; a 1M quote's fee is 100x the bps
+    const effectiveFee = async (withObservations = true) => {
+      const quoted = await quote(pool, new anchor.BN(1_000_000), true, withObservations ? pool.observations : null);
+      return quoted.feeAmount.toNumber() / 100;
+    };
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      ({ pool, lp } = await seededPool(admin, DEPOSIT, DEPOSIT, { extraA: DEPOSIT, extraB: DEPOSIT }));
+
+      await program.methods
+        .setDynamicFee(MIN_FEE, MAX_FEE)
+        .accounts({ admin: admin.publicKey, amm: pool.amm })
+        .signers([admin])
+        .rpc();
+    });
+
+    it("Starts at the floor and charges the ceiling without observations", async () => {
+      expect(await effectiveFee()).to.equal(MIN_FEE);
+      expect(await effectiveFee(false)).to.equal(MAX_FEE);
+    });
+
+    it("Raises the fee after volatile swaps, then decays back during calm", async () => {
+      // Swing the price down and back up, in separate seconds
+      await sleep(1100);
+      await swap(pool, lp, true, DEPOSIT.divn(10));
+      await sleep(1100);
+      await swap(pool, lp, false, DEPOSIT.divn(5));
+
+      const volatileFee = await effectiveFee();
+      expect(volatileFee).to.be.greaterThan(MIN_FEE);
+      expect(volatileFee).to.be.at.most(MAX_FEE);
+
+      // Tiny back-and-forth swaps push the swings out of the observation buffer
+      console.log(`Running ${OBSERVATION_CAPACITY + 1} calm swaps, about a second apart...`);
+      let calmFee = volatileFee;
+      for (let i = 0; i <= OBSERVATION_CAPACITY; i++) {
+        await sleep(1100);
+        await swap(pool, lp, i % 2 === 0, new anchor.BN(1_000));
+        const fee = await effectiveFee();
+        expect(fee).to.be.at.most(calmFee);
+        calmFee = fee;
+      }
+
+      expect(calmFee).to.equal(MIN_FEE);
+      console.log("✅ Fee rose to", volatileFee, "bps and decayed to", calmFee, "bps");
+    });
+  });
+
+  describe("TWAP Observations", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000_000);
+
+    const twap = (period: number) =>
+      program.methods
+        .getTwap(new anchor.BN(period))
+        .accounts({ amm: pool.amm, observations: pool.observations })
+        .view();
+
+    before(async () => {
+      const user = await fundedKeypair();
+      ({ pool, lp } = await seededPool(user, DEPOSIT_A, DEPOSIT_B, { extraA: DEPOSIT_A, extraB: DEPOSIT_B }));
+    });
+
+    it("Records an observation per swap and averages prices over two windows", async () => {
+      const initialPrice = await spotPrice(pool);
+      const { count: initialCount } = await program.account.ammObservations.fetch(pool.observations);
+
+      // Push the price up, then partway back down, a few seconds apart
+      await sleep(2000);
+      await swap(pool, lp, false, new anchor.BN(1_000_000_000));
+      const highPrice = await spotPrice(pool);
+      await sleep(3000);
+      await swap(pool, lp, true, new anchor.BN(100_000_000));
+      const finalPrice = await spotPrice(pool);
+      await sleep(3000);
+
+      const stored = await program.account.ammObservations.fetch(pool.observations);
+      expect(stored.count).to.equal(initialCount + 2);
+      expect(stored.lastPrice.toString()).to.equal(finalPrice.toString());
+
+      // Short window: only the price left by the last swap
+      const recent = await twap(1);
+      expect(recent.toString()).to.equal(finalPrice.toString());
+
+      // Longer window: a blend of the prices since the first swap
+      const blended = await twap(5);
+      expect(blended.gte(finalPrice)).to.be.true;
+      expect(blended.lte(highPrice)).to.be.true;
+      expect(highPrice.gt(initialPrice)).to.be.true;
+
+      // The buffer can't answer for a period older than its first observation
+      try {
+        await twap(100_000);
+        expect.fail("TWAP over missing history succeeded");
+      } catch (error) {
+        expect(error.toString()).to.include("TwapWindowUnavailable");
+      }
+
+      console.log("✅ TWAP over 1s:", recent.toString(), "over 5s:", blended.toString());
+    });
+  });
+
+  describe("Batched Swaps", () => {
+    let user: Keypair;
+
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(2_000_000_000);
+    const AMOUNTS_IN = [10_000_000, 25_000_000, 5_000_000].map(amount => new anchor.BN(amount));
+    const TOTAL_IN = AMOUNTS_IN.reduce((sum, amount) => sum.add(amount), new anchor.BN(0));
+
+    // A fresh pool seeded with DEPOSIT_A and DEPOSIT_B, with TOTAL_IN of A left to swap
+    const seedPool = () => seededPool(user, DEPOSIT_A, DEPOSIT_B, { extraA: TOTAL_IN });
+
+    before(async () => {
+      user = await fundedKeypair();
+    });
+
+    it("Matches the same swaps sent one by one", async () => {
+      const batched = await seedPool();
+      const sequential = await seedPool();
+
+      await program.methods
+        .swapBatch(AMOUNTS_IN, new anchor.BN(0), true)
+        .accounts(swapAccounts(batched.pool, batched.lp, true))
+        .signers([user])
+        .rpc();
+
+      for (const amountIn of AMOUNTS_IN) {
+        await swap(sequential.pool, sequential.lp, true, amountIn);
+      }
+
+      expect((await balance(batched.lp.tokenB)).toString()).to.equal((await balance(sequential.lp.tokenB)).toString());
+      expect((await balance(batched.pool.tokenAVault)).toString()).to.equal((await balance(sequential.pool.tokenAVault)).toString());
+      expect((await balance(batched.pool.tokenBVault)).toString()).to.equal((await balance(sequential.pool.tokenBVault)).toString());
+
+      const batchedState = await program.account.ammState.fetch(batched.pool.amm);
+      const sequentialState = await program.account.ammState.fetch(sequential.pool.amm);
+      expect(batchedState.feeGrowthA.toString()).to.equal(sequentialState.feeGrowthA.toString());
+
+      console.log("✅ Batch of", AMOUNTS_IN.length, "swaps paid out", (await balance(batched.lp.tokenB)).toString());
+    });
+
+    it("Checks only the aggregate minimum output", async () => {
+      const { pool, lp } = await seedPool();
+      const quoted = await quote(pool, TOTAL_IN);
+
+      // Far above anything the batch can return
+      try {
+        await program.methods
+          .swapBatch(AMOUNTS_IN, quoted.amountOut.muln(2), true)
+          .accounts(swapAccounts(pool, lp, true))
+          .signers([user])
+          .rpc();
+        expect.fail("Batch returned less than min_total_out");
+      } catch (error) {
+        expect(error.toString()).to.include("SlippageExceeded");
+      }
+
+      try {
+        await program.methods
+          .swapBatch(Array(9).fill(new anchor.BN(1_000)), new anchor.BN(0), true)
+          .accounts(swapAccounts(pool, lp, true))
+          .signers([user])
+          .rpc();
+        expect.fail("Ran an oversized batch");
+      } catch (error) {
+        expect(error.toString()).to.include("SwapBatchTooLarge");
+      }
+    });
+  });
+
+  describe("Single-Sided Deposits", () => {
+    let user: Keypair;
+
+    const DEPOSIT_A = new anchor.BN(1_000_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000_000);
+    const SINGLE_IN = new anchor.BN(100_000_000);
+
+    // A fresh 1:4 pool seeded by `user`, with SINGLE_IN of A left to deposit
+    const seedPool = () => seededPool(user, DEPOSIT_A, DEPOSIT_B, { extraA: SINGLE_IN });
+
+    const depositSingle = (pool: Pool, lp: Lp, minLpTokens: anchor.BN) =>
+      program.methods
+        .depositLiquiditySingle(SINGLE_IN, true, minLpTokens)
+        .accounts({
+          user: user.publicKey,
+          amm: pool.amm,
+          userTokenIn: lp.tokenA,
+          userLpToken: lp.lpToken,
+          lpPosition: lpPosition(pool, user.publicKey),
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      user = await fundedKeypair(10);
+    });
+
+    it("Mints at least as much LP as swapping half and depositing by hand", async () => {
+      // Two identical pools: one takes the single-sided deposit, the other the manual route
+      const single = await seedPool();
+      const manual = await seedPool();
+
+      const singleLpBefore = await balance(single.lp.lpToken);
+      await depositSingle(single.pool, single.lp, new anchor.BN(1));
+      const singleLp = (await balance(single.lp.lpToken)).sub(singleLpBefore);
+
+      // The whole input landed in vault A; nothing left the pool
+      expect((await balance(single.pool.tokenAVault)).toString()).to.equal(DEPOSIT_A.add(SINGLE_IN).toString());
+      expect((await balance(single.pool.tokenBVault)).toString()).to.equal(DEPOSIT_B.toString());
+      expect((await balance(single.lp.tokenA)).toString()).to.equal("0");
+
+      // Manual route: swap half of the input for B, then deposit both sides
+      const half = SINGLE_IN.divn(2);
+      const manualBBefore = await balance(manual.lp.tokenB);
+      await swap(manual.pool, manual.lp, true, half);
+      const swappedB = (await balance(manual.lp.tokenB)).sub(manualBBefore);
+
+      const manualLpBefore = await balance(manual.lp.lpToken);
+      await deposit(manual.pool, manual.lp, half, swappedB);
+      const manualLp = (await balance(manual.lp.lpToken)).sub(manualLpBefore);
+
+      console.log("✅ LP minted single-sided:", singleLp.toString(), "manually:", manualLp.toString());
+      expect(singleLp.gte(manualLp)).to.be.true;
+
+      // The fee and price move mean less LP than a balanced deposit of equal value
+      const lpSupply = new anchor.BN((await provider.connection.getTokenSupply(single.pool.lpMint)).value.amount);
+      const balancedLp = SINGLE_IN.mul(lpSupply.sub(singleLp)).div(DEPOSIT_A.muln(2));
+      expect(singleLp.lt(balancedLp)).to.be.true;
+    });
+
+    it("Rejects a single-sided deposit below the minimum LP", async () => {
+      const { pool, lp } = await seedPool();
+      try {
+        await depositSingle(pool, lp, new anchor.BN("1000000000000"));
+        expect.fail("Deposit minted less LP than the minimum");
+      } catch (error) {
+        expect(error.toString()).to.include("SlippageExceeded");
+      }
+    });
+  });
+
+  describe("LP Lock", () => {
+    let user: Keypair;
+    let pool: Pool;
+    let lp: Lp;
+    let position: PublicKey;
+    let lockedLpVault: PublicKey;
+
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const LOCK_SECONDS = 3;
+
+    const depositLocked = (lockDuration: number, vault: PublicKey | null = lockDuration > 0 ? lockedLpVault : null) =>
+      program.methods
+        .depositLiquidity(DEPOSIT, DEPOSIT, new anchor.BN(0), new anchor.BN(lockDuration))
+        .accounts({
+          user: user.publicKey,
+          amm: pool.amm,
+          tokenAMint: pool.tokenAMint,
+          tokenBMint: pool.tokenBMint,
+          userTokenA: lp.tokenA,
+          userTokenB: lp.tokenB,
+          userLpToken: lp.lpToken,
+          lpPosition: position,
+          lockedLpVault: vault,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    const withdraw = (lpAmount: anchor.BN, withPosition: PublicKey = position) =>
+      program.methods
+        .withdrawLiquidity(lpAmount, new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          user: user.publicKey,
+          amm: pool.amm,
+          userTokenA: lp.tokenA,
+          userTokenB: lp.tokenB,
+          userLpToken: lp.lpToken,
+          lpPosition: withPosition,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    const release = () =>
+      program.methods
+        .releaseLockedLp()
+        .accounts({
+          user: user.publicKey,
+          amm: pool.amm,
+          lpMint: pool.lpMint,
+          lpPosition: position,
+          lockedLpVault,
+          userLpToken: lp.lpToken,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      user = await fundedKeypair();
+      pool = await createPool(user);
+      position = lpPosition(pool, user.publicKey);
+      lockedLpVault = pda([Buffer.from("locked_lp"), position.toBuffer()]);
+
+      await program.methods
+        .openLpPosition()
+        .accounts({ user: user.publicKey, amm: pool.amm, lpPosition: position, systemProgram: SystemProgram.programId })
+        .signers([user])
+        .rpc();
+
+      lp = await fundLp(pool, user, user, DEPOSIT.muln(2), DEPOSIT.muln(2));
+    });
+
+    it("Refuses a locked deposit without the locked LP vault", async () => {
+      try {
+        await depositLocked(LOCK_SECONDS, null);
+        expect.fail("Locked a deposit with nowhere to escrow it");
+      } catch (error) {
+        expect(error.toString()).to.include("LockedLpVaultRequired");
+      }
+    });
+
+    it("Mints locked LP into escrow instead of the user's account", async () => {
+      // An unlocked deposit first, then a locked one on top
+      await depositLocked(0);
+      const freeLp = await balance(lp.lpToken);
+      await depositLocked(LOCK_SECONDS);
+      expect((await balance(lp.lpToken)).toString()).to.equal(freeLp.toString());
+
+      const lockedLp = await balance(lockedLpVault);
+      expect(lockedLp.toNumber()).to.be.greaterThan(0);
+      const stored = await program.account.lpPosition.fetch(position);
+      expect(stored.lockedLp.toString()).to.equal(lockedLp.toString());
+    });
+
+    it("Can't withdraw locked LP before the vest time, with or without the position", async () => {
+      const freeLp = await balance(lp.lpToken);
+
+      // The locked LP isn't in the user's account to burn: the token program fails with InsufficientFunds
+      try {
+        await withdraw(freeLp.addn(1));
+        expect.fail("Withdrew locked LP before the vest time");
+      } catch (error) {
+        expect(error.toString()).to.include("custom program error: 0x1");
+      }
+
+      // Nor can the position be left out for one that carries no lock
+      const strangerPosition = lpPosition(pool, Keypair.generate().publicKey);
+      try {
+        await withdraw(freeLp, strangerPosition);
+        expect.fail("Withdrew without the LP position");
+      } catch (error) {
+        expect(error.toString()).to.include("AccountNotInitialized");
+      }
+
+      try {
+        await release();
+        expect.fail("Released LP before the vest time");
+      } catch (error) {
+        expect(error.toString()).to.include("LpLocked");
+      }
+
+      // The free LP can still leave
+      await withdraw(freeLp);
+      expect((await balance(lp.lpToken)).toNumber()).to.equal(0);
+    });
+
+    it("Releases the locked LP after the vest time", async () => {
+      const stored = await program.account.lpPosition.fetch(position);
+      const waitMs = (stored.vestTime.toNumber() + 1) * 1000 - Date.now();
+      await sleep(Math.max(waitMs, 0) + 1000);
+
+      const lockedLp = await balance(lockedLpVault);
+      await release();
+      expect((await balance(lp.lpToken)).toString()).to.equal(lockedLp.toString());
+      expect((await balance(lockedLpVault)).toNumber()).to.equal(0);
+      expect((await program.account.lpPosition.fetch(position)).lockedLp.toNumber()).to.equal(0);
+
+      await withdraw(lockedLp);
+      expect((await balance(lp.lpToken)).toNumber()).to.equal(0);
+      console.log("✅ Withdrew", lockedLp.toString(), "LP after it vested");
+    });
+  });
+
+  describe("LP Performance", () => {
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+
+    const performance = (entryReserveA: anchor.BN, entryReserveB: anchor.BN) =>
+      program.methods
+        .getLpPerformance(entryReserveA, entryReserveB)
+        .accounts({
+          amm: pool.amm,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpPosition: lpPosition(pool, lp.wallet.publicKey),
+          userLpToken: lp.lpToken,
+        })
+        .view();
+
+    before(async () => {
+      const user = await fundedKeypair();
+      pool = await createPool(user);
+
+      await program.methods
+        .openLpPosition()
+        .accounts({
+          user: user.publicKey,
+          amm: pool.amm,
+          lpPosition: lpPosition(pool, user.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      lp = await fundLp(pool, user, user, DEPOSIT.muln(2), DEPOSIT.muln(2));
+      await deposit(pool, lp, DEPOSIT, DEPOSIT);
+    });
+
+    it("Reports no loss or fees before the price moves", async () => {
+      const result = await performance(DEPOSIT, DEPOSIT);
+      expect(result.impermanentLossBps.toNumber()).to.equal(0);
+      expect(result.fees.feesA.toNumber()).to.equal(0);
+      expect(result.fees.feesB.toNumber()).to.equal(0);
+    });
+
+    it("Matches the closed-form loss after the price roughly quadruples", async () => {
+      // Selling as much B as the pool holds pushes the B-per-A price up about 4x
+      await swap(pool, lp, false, DEPOSIT);
+
+      const ratio = (await balance(pool.tokenBVault)).toNumber() / (await balance(pool.tokenAVault)).toNumber();
+      const expectedBps = Math.round((1 - (2 * Math.sqrt(ratio)) / (1 + ratio)) * 10_000);
+
+      const result = await performance(DEPOSIT, DEPOSIT);
+      expect(result.impermanentLossBps.toNumber()).to.equal(expectedBps);
+      expect(expectedBps).to.be.closeTo(2_000, 20);
+
+      // The swap paid its fee in B
+      expect(result.fees.feesA.toNumber()).to.equal(0);
+      expect(result.fees.feesB.toNumber()).to.be.greaterThan(0);
+      console.log("✅ Impermanent loss", expectedBps, "bps against", result.fees.feesB.toString(), "B of fees");
+    });
+  });
+
+  describe("Permit Withdrawals", () => {
+    let relayer: Keypair;
+    let pool: Pool;
+    let lp: Lp;
+
+    const DEPOSIT_A = new anchor.BN(1_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000);
+    const PERMIT_DOMAIN = Buffer.from("turbin3:withdraw_liquidity_permit");
+
+    // The LP signs this off-chain; the relayer only ever sees the signature
+    const signPermit = (permit: any) => {
+      const message = Buffer.concat([PERMIT_DOMAIN, program.coder.types.encode("WithdrawPermit", permit)]);
+      return Ed25519Program.createInstructionWithPrivateKey({ privateKey: lp.wallet.secretKey, message });
+    };
+
+    const relay = (permit: any, signed = signPermit(permit)) =>
+      program.methods
+        .withdrawLiquidityWithAuthority(permit)
+        .accounts({
+          relayer: relayer.publicKey,
+          amm: pool.amm,
+          permitNonce: pda([
+            Buffer.from("permit_nonce"),
+            pool.amm.toBuffer(),
+            lp.wallet.publicKey.toBuffer(),
+            permit.nonce.toArrayLike(Buffer, "le", 8),
+          ]),
+          ownerTokenA: lp.tokenA,
+          ownerTokenB: lp.tokenB,
+          ownerLpToken: lp.lpToken,
+          lpPosition: lpPosition(pool, lp.wallet.publicKey),
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          instructionsSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([signed])
+        .signers([relayer])
+        .rpc();
+
+    const permitFor = (lpAmount: number, nonce: number) => ({
+      amm: pool.amm,
+      owner: lp.wallet.publicKey,
+      lpAmount: new anchor.BN(lpAmount),
+      minAmountA: new anchor.BN(0),
+      minAmountB: new anchor.BN(0),
+      nonce: new anchor.BN(nonce),
+      deadline: new anchor.BN(Math.floor(Date.now() / 1000) + 600),
+    });
+
+    before(async () => {
+      const owner = await fundedKeypair(2);
+      relayer = await fundedKeypair(2);
+      ({ pool, lp } = await seededPool(owner, DEPOSIT_A, DEPOSIT_B));
+
+      // One-time on-chain approval: the AMM may burn up to 1_000_000 LP tokens
+      await approve(provider.connection, owner, lp.lpToken, pool.amm, owner, 1_000_000);
+    });
+
+    it("Relayer withdraws for the LP with a signed permit, and the permit can't be replayed", async () => {
+      const permit = permitFor(500_000, 1);
+
+      await relay(permit);
+
+      // 500_000 of 2_000_000 LP tokens is a quarter of each reserve
+      expect((await balance(lp.lpToken)).toString()).to.equal("1500000");
+      expect((await balance(lp.tokenA)).toString()).to.equal("250000");
+      expect((await balance(lp.tokenB)).toString()).to.equal("1000000");
+
+      try {
+        await relay(permit);
+        expect.fail("Replayed permit should have been rejected");
+      } catch (err: any) {
+        // The nonce marker already exists
+        expect(err.toString()).to.include("already in use");
+      }
+    });
+
+    it("Rejects a permit the relayer tampered with", async () => {
+      const permit = permitFor(100_000, 2);
+      const signed = signPermit(permit);
+
+      try {
+        await relay({ ...permit, lpAmount: new anchor.BN(400_000) }, signed);
+        expect.fail("Tampered permit should have been rejected");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidPermit");
+      }
+    });
+  });
+
+  describe("Protocol-Owned Liquidity", () => {
+    let admin: Keypair;
+
+    const SEED_A = new anchor.BN(1_000_000_000);
+    const SEED_B = new anchor.BN(2_000_000_000);
+    const LOCK_SECONDS = 3;
+
+    // A fresh pool whose launch liquidity is seeded by the protocol, locked for `lockPeriod` seconds
+    const seedPool = async (lockPeriod: number) => {
+      const pool = await createPool(admin);
+      const lp = await fundLp(pool, admin, admin, SEED_A, SEED_B);
+      const protocolLiquidity = pda([Buffer.from("protocol_liquidity"), pool.amm.toBuffer()]);
+      const protocolLpVault = pda([Buffer.from("protocol_lp"), pool.amm.toBuffer()]);
+
+      await program.methods
+        .seedProtocolLiquidity(SEED_A, SEED_B, new anchor.BN(lockPeriod))
+        .accounts({
+          admin: admin.publicKey,
+          amm: pool.amm,
+          adminTokenA: lp.tokenA,
+          adminTokenB: lp.tokenB,
+          protocolLiquidity,
+          protocolLpVault,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+      return { pool, lp, protocolLiquidity, protocolLpVault };
+    };
+
+    const withdraw = ({ pool, lp, protocolLiquidity, protocolLpVault }: Awaited<ReturnType<typeof seedPool>>, lpAmount: anchor.BN) =>
+      program.methods
+        .withdrawProtocolLiquidity(lpAmount, new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          admin: admin.publicKey,
+          amm: pool.amm,
+          adminTokenA: lp.tokenA,
+          adminTokenB: lp.tokenB,
+          protocolLiquidity,
+          protocolLpVault,
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      admin = await fundedKeypair(10);
+    });
+
+    it("Mints the seeded LP to the protocol vault, not the admin", async () => {
+      const seeded = await seedPool(86400);
+      const { pool, protocolLiquidity, protocolLpVault } = seeded;
+
+      const lpMinted = (await provider.connection.getTokenSupply(pool.lpMint)).value.amount;
+      expect((await balance(protocolLpVault)).toString()).to.equal(lpMinted);
+      expect((await getAccount(provider.connection, protocolLpVault)).owner.toString()).to.equal(protocolLiquidity.toString());
+      expect((await balance(pool.tokenAVault)).toString()).to.equal(SEED_A.toString());
+      expect((await balance(pool.tokenBVault)).toString()).to.equal(SEED_B.toString());
+
+      try {
+        await withdraw(seeded, new anchor.BN(1));
+        expect.fail("Withdrew protocol liquidity during the lock");
+      } catch (error) {
+        expect(error.toString()).to.include("ProtocolLiquidityLocked");
+      }
+    });
+
+    it("Releases the LP to the admin once the lock elapses", async () => {
+      const seeded = await seedPool(LOCK_SECONDS);
+
+      try {
+        await withdraw(seeded, new anchor.BN(1));
+        expect.fail("Withdrew protocol liquidity during the lock");
+      } catch (error) {
+        expect(error.toString()).to.include("ProtocolLiquidityLocked");
+      }
+
+      console.log(`Waiting ${LOCK_SECONDS + 1} seconds for the lock to elapse...`);
+      await sleep((LOCK_SECONDS + 1) * 1000);
+
+      const lpHeld = await balance(seeded.protocolLpVault);
+      await withdraw(seeded, lpHeld.divn(2));
+
+      expect((await balance(seeded.protocolLpVault)).toString()).to.equal(lpHeld.sub(lpHeld.divn(2)).toString());
+      expect((await balance(seeded.lp.tokenA)).gtn(0)).to.be.true;
+      expect((await balance(seeded.lp.tokenB)).gtn(0)).to.be.true;
+      console.log("✅ Withdrew", lpHeld.divn(2).toString(), "protocol LP after the lock");
+    });
+  });
+
+  describe("Token Rescue", () => {
+    let admin: Keypair;
+    let pool: Pool;
+    let adminTokenA: PublicKey;
+
+    const rescue = (strayMint: PublicKey, strayTokenAccount: PublicKey, adminTokenAccount: PublicKey, amount: anchor.BN) =>
+      program.methods
+        .rescueTokens(amount)
+        .accounts({
+          admin: admin.publicKey,
+          amm: pool.amm,
+          strayTokenAccount,
+          strayMint,
+          adminTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      admin = await fundedKeypair();
+      pool = await createPool(admin);
+
+      adminTokenA = await createAssociatedTokenAccount(provider.connection, admin, pool.tokenAMint, admin.publicKey);
+      await mintTo(provider.connection, admin, pool.tokenAMint, pool.tokenAVault, admin, 1_000);
+    });
+
+    it("Returns a stray mint sent to the pool to the admin", async () => {
+      // Someone sends an unrelated token to the pool's own ATA
+      const strayMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
+      const poolStray = await createAssociatedTokenAccount(
+        provider.connection, admin, strayMint, pool.amm, undefined, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, true
+      );
+      const adminStray = await createAssociatedTokenAccount(provider.connection, admin, strayMint, admin.publicKey);
+      await mintTo(provider.connection, admin, strayMint, poolStray, admin, 5_000);
+
+      await rescue(strayMint, poolStray, adminStray, new anchor.BN(5_000));
+
+      expect((await balance(poolStray)).toString()).to.equal("0");
+      expect((await balance(adminStray)).toString()).to.equal("5000");
+      console.log("✅ Rescued 5000 stray tokens");
+    });
+
+    it("Refuses to move the pool's own tokens", async () => {
+      try {
+        await rescue(pool.tokenAMint, pool.tokenAVault, adminTokenA, new anchor.BN(1_000));
+        expect.fail("Rescued pool reserves");
+      } catch (error) {
+        expect(error.toString()).to.include("PoolMintNotRescuable");
+      }
+      expect((await balance(pool.tokenAVault)).toString()).to.equal("1000");
+    });
+  });
+
+  describe("Withdrawal Dust", () => {
+    let admin: Keypair;
+    let pool: Pool;
+    let first: Lp;
+    let last: Lp;
+
+    // A funded LP that deposits `amountA` and `amountB` into the pool
+    const addLp = async (amountA: number, amountB: number) => {
+      const lp = await fundLp(pool, admin, await fundedKeypair(2), amountA, amountB);
+      await deposit(pool, lp, new anchor.BN(amountA), new anchor.BN(amountB));
+      return lp;
+    };
+
+    const withdraw = (lp: Lp, lpAmount: anchor.BN) =>
+      program.methods
+        .withdrawLiquidity(lpAmount, new anchor.BN(0), new anchor.BN(0))
+        .accounts({
+          user: lp.wallet.publicKey,
+          amm: pool.amm,
+          userTokenA: lp.tokenA,
+          userTokenB: lp.tokenB,
+          userLpToken: lp.lpToken,
+          lpPosition: lpPosition(pool, lp.wallet.publicKey),
+          tokenAVault: pool.tokenAVault,
+          tokenBVault: pool.tokenBVault,
+          lpMint: pool.lpMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([lp.wallet])
+        .rpc();
+
+    before(async () => {
+      admin = await fundedKeypair();
+      pool = await createPool(admin);
+
+      // Odd reserves so that partial withdrawals don't divide evenly
+      first = await addLp(1_000_003, 2_000_009);
+      last = await addLp(1_000_000, 2_000_000);
+    });
+
+    it("Hands the last LP out every token left in the vaults", async () => {
+      // The first LP exits in uneven chunks, each floored in the pool's favor
+      const firstLp = await balance(first.lpToken);
+      const chunk = firstLp.divn(7);
+      for (let i = 0; i < 6; i++) {
+        await withdraw(first, chunk);
+      }
+      await withdraw(first, firstLp.sub(chunk.muln(6)));
+      expect((await balance(first.lpToken)).toNumber()).to.equal(0);
+
+      const residualA = await balance(pool.tokenAVault);
+      const residualB = await balance(pool.tokenBVault);
+      const lastLp = await balance(last.lpToken);
+      expect(lastLp.toString()).to.equal((await provider.connection.getTokenSupply(pool.lpMint)).value.amount);
+
+      await withdraw(last, lastLp);
+
+      expect((await balance(pool.tokenAVault)).toNumber()).to.equal(0);
+      expect((await balance(pool.tokenBVault)).toNumber()).to.equal(0);
+      expect((await balance(last.tokenA)).toString()).to.equal(residualA.toString());
+      expect((await balance(last.tokenB)).toString()).to.equal(residualB.toString());
+      console.log("✅ Last LP received", residualA.toString(), "A and", residualB.toString(), "B");
+    });
+  });
+
+  describe("Token-2022 Transfer Fees", () => {
+    let user: Keypair;
+    let pool: Pool;
+    let lp: Lp;
+
+    // Both mints withhold 1% of every transfer
+    const TRANSFER_FEE_BPS = 100;
+    const DEPOSIT = new anchor.BN(1_000_000_000);
+    const SWAP_AMOUNT = new anchor.BN(10_000_000);
+    const afterTransferFee = (amount: anchor.BN) => amount.muln(10_000 - TRANSFER_FEE_BPS).divn(10_000);
+    const balance2022 = (account: PublicKey) => balance(account, TOKEN_2022_PROGRAM_ID);
+
+    const createTransferFeeMint = async () => {
+      const mint = Keypair.generate();
+      const space = getMintLen([ExtensionType.TransferFeeConfig]);
+      const lamports = await provider.connection.getMinimumBalanceForRentExemption(space);
+      const transaction = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: user.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mint.publicKey, user.publicKey, user.publicKey, TRANSFER_FEE_BPS, BigInt(DEPOSIT.toString()), TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(mint.publicKey, 6, user.publicKey, null, TOKEN_2022_PROGRAM_ID)
+      );
+      await sendAndConfirmTransaction(provider.connection, transaction, [user, mint]);
+      return mint.publicKey;
+    };
+
+    before(async () => {
+      user = await fundedKeypair();
+
+      const mintX = await createTransferFeeMint();
+      const mintY = await createTransferFeeMint();
+      const mints: [PublicKey, PublicKey] = Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0 ? [mintX, mintY] : [mintY, mintX];
+
+      pool = await createPool(user, { mints, tokenProgram: TOKEN_2022_PROGRAM_ID });
+      const funding = DEPOSIT.add(SWAP_AMOUNT.muln(2));
+      lp = await fundLp(pool, user, user, funding, funding);
+    });
+
+    it("Prices the first deposit on what reached the vaults", async () => {
+      await deposit(pool, lp, DEPOSIT, DEPOSIT);
+
+      // The mints withheld their fee on the way in, so the reserves hold the net amounts
+      expect((await balance2022(pool.tokenAVault)).toString()).to.equal(afterTransferFee(DEPOSIT).toString());
+      expect((await balance2022(pool.tokenBVault)).toString()).to.equal(afterTransferFee(DEPOSIT).toString());
+      expect((await balance2022(lp.lpToken)).gtn(0)).to.be.true;
+    });
+
+    it("Swaps the amount that reached the vault", async () => {
+      const received = afterTransferFee(SWAP_AMOUNT);
+      const expected = await quote(pool, received, true, pool.observations);
+      const vaultABefore = await balance2022(pool.tokenAVault);
+      const vaultBBefore = await balance2022(pool.tokenBVault);
+      const userBBefore = await balance2022(lp.tokenB);
+
+      await swap(pool, lp, true, SWAP_AMOUNT);
+
+      // Priced on the net input; the output pays the mint's fee on the way out
+      expect((await balance2022(pool.tokenAVault)).sub(vaultABefore).toString()).to.equal(received.toString());
+      expect(vaultBBefore.sub(await balance2022(pool.tokenBVault)).toString()).to.equal(expected.amountOut.toString());
+      expect((await balance2022(lp.tokenB)).sub(userBBefore).toString()).to.equal(afterTransferFee(expected.amountOut).toString());
+    });
+
+    it("Refuses a batch whose input withholds a transfer fee", async () => {
+      try {
+        await program.methods
+          .swapBatch([SWAP_AMOUNT], new anchor.BN(0), true)
+          .accounts(swapAccounts(pool, lp, true))
+          .signers([user])
+          .rpc();
+        expect.fail("Batch swapped a fee-withholding input");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidAmount");
+      }
+    });
+  });
+
+  describe("LP Token Staking", () => {
+    const DEPOSIT_A = new anchor.BN(1_000_000);
+    const DEPOSIT_B = new anchor.BN(4_000_000);
+    const REWARD_RATE = new anchor.BN(1_000);
+
+    it("Deposits liquidity, stakes the LP tokens and claims rewards", async () => {
+      const user = await fundedKeypair();
+      const { pool, lp } = await seededPool(user, DEPOSIT_A, DEPOSIT_B);
+
+      // sqrt(1_000_000 * 4_000_000)
+      const lpBalance = await balance(lp.lpToken);
+      expect(lpBalance.toString()).to.equal("2000000");
+
+      // A staking pool whose stake mint is the AMM's LP mint
+      const stakingPool = await createStakingPool(user, REWARD_RATE, { stakeMint: pool.lpMint });
+      const rewardAccount = await fundRewards(stakingPool, user, new anchor.BN(10_000_000));
+      const staker = {
+        wallet: user,
+        userStake: userStakeOf(stakingPool, user.publicKey),
+        stakeAccount: lp.lpToken,
+        rewardAccount,
+      };
+
+      await stake(stakingPool, staker, lpBalance);
+      expect((await balance(stakingPool.stakeVault)).toString()).to.equal(lpBalance.toString());
+      expect((await balance(lp.lpToken)).toString()).to.equal("0");
+
+      console.log("Waiting 3 seconds to accumulate rewards...");
+      await sleep(3000);
+
+      await claimRewards(stakingPool, staker);
+      expect((await balance(rewardAccount)).toNumber()).to.be.greaterThan(0);
+
+      console.log("✅ LP tokens staked and rewards claimed");
+    });
+  });
+});
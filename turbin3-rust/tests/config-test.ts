@@ -0,0 +1,110 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Turbin3Rust } from "../target/types/turbin3_rust";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import { expect } from "chai";
+
+describe("Program Config", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Turbin3Rust as Program<Turbin3Rust>;
+
+  let globalAdmin: Keypair;
+  let feeCollector: Keypair;
+  let config: PublicKey;
+
+  before(async () => {
+    globalAdmin = Keypair.generate();
+    feeCollector = Keypair.generate();
+
+    const signature = await provider.connection.requestAirdrop(
+      globalAdmin.publicKey,
+      anchor.web3.LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(signature);
+
+    [config] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
+  });
+
+  it("Initializes the singleton config", async () => {
+    await program.methods
+      .initializeProgramConfig(feeCollector.publicKey)
+      .accounts({
+        globalAdmin: globalAdmin.publicKey,
+        config,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([globalAdmin])
+      .rpc();
+
+    const configAccount = await program.account.programConfig.fetch(config);
+    expect(configAccount.globalAdmin.toString()).to.equal(globalAdmin.publicKey.toString());
+    expect(configAccount.feeCollector.toString()).to.equal(feeCollector.publicKey.toString());
+
+    // The config can only be created once
+    try {
+      await program.methods
+        .initializeProgramConfig(feeCollector.publicKey)
+        .accounts({
+          globalAdmin: globalAdmin.publicKey,
+          config,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([globalAdmin])
+        .rpc();
+      expect.fail("Config was initialized twice");
+    } catch (error) {
+      expect(error.toString()).to.include("already in use");
+    }
+
+    console.log("✅ Program config initialized");
+  });
+
+  it("Lets the global admin update the fee collector", async () => {
+    const newCollector = Keypair.generate();
+
+    await program.methods
+      .updateProgramConfig(null, newCollector.publicKey)
+      .accounts({
+        globalAdmin: globalAdmin.publicKey,
+        config,
+      })
+      .signers([globalAdmin])
+      .rpc();
+
+    const configAccount = await program.account.programConfig.fetch(config);
+    expect(configAccount.feeCollector.toString()).to.equal(newCollector.publicKey.toString());
+    expect(configAccount.globalAdmin.toString()).to.equal(globalAdmin.publicKey.toString());
+
+    console.log("✅ Fee collector updated");
+  });
+
+  it("Rejects updates from anyone but the global admin", async () => {
+    const intruder = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(
+      intruder.publicKey,
+      anchor.web3.LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(signature);
+
+    try {
+      await program.methods
+        .updateProgramConfig(intruder.publicKey, intruder.publicKey)
+        .accounts({
+          globalAdmin: intruder.publicKey,
+          config,
+        })
+        .signers([intruder])
+        .rpc();
+      expect.fail("Unauthorized update succeeded");
+    } catch (error) {
+      expect(error.toString()).to.include("Unauthorized");
+    }
+
+    const configAccount = await program.account.programConfig.fetch(config);
+    expect(configAccount.globalAdmin.toString()).to.equal(globalAdmin.publicKey.toString());
+
+    console.log("✅ Unauthorized update rejected");
+  });
+});
@@ -0,0 +1,837 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Turbin3Rust } from "../target/types/turbin3_rust";
+import {
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  sendAndConfirmTransaction,
+} from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  mintTo,
+  getAccount,
+  getAssociatedTokenAddress,
+  createAssociatedTokenAccount,
+  closeAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import { balance, fundedKeypair, pda, sleep } from "./helpers";
+
+describe("Escrow System", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Turbin3Rust as Program<Turbin3Rust>;
+
+  // Test accounts
+  let maker: Keypair;
+  let taker: Keypair;
+  let mintA: PublicKey;
+  let mintB: PublicKey;
+  let makerAtaA: PublicKey;
+  let makerAtaB: PublicKey;
+  let takerAtaA: PublicKey;
+  let takerAtaB: PublicKey;
+  let escrow: PublicKey;
+  let escrowVault: PublicKey;
+
+  // Test constants
+  const ESCROW_SEED = "escrow";
+  const ESCROW_VAULT_SEED = "escrow_vault";
+  const OFFER_AMOUNT = new anchor.BN(1_000_000); // 1 token A (6 decimals)
+  const RECEIVE_AMOUNT = new anchor.BN(2_000_000); // 2 token B (6 decimals)
+  const TAKER_FEE_LAMPORTS = 100_000; // Enough for transaction fees, not ATA rent
+
+  const tokens = async (account: PublicKey) => (await balance(account)).toNumber();
+
+  before(async () => {
+    // Generate test accounts
+    maker = Keypair.generate();
+    taker = Keypair.generate();
+
+    // Airdrop SOL to maker only
+    const signature = await provider.connection.requestAirdrop(
+      maker.publicKey,
+      2 * anchor.web3.LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(signature);
+
+    // Give the taker just enough to pay transaction fees
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: maker.publicKey,
+          toPubkey: taker.publicKey,
+          lamports: TAKER_FEE_LAMPORTS,
+        })
+      ),
+      [maker]
+    );
+
+    // Create both mints
+    mintA = await createMint(provider.connection, maker, maker.publicKey, null, 6);
+    mintB = await createMint(provider.connection, maker, maker.publicKey, null, 6);
+
+    makerAtaA = await getAssociatedTokenAddress(mintA, maker.publicKey);
+    makerAtaB = await getAssociatedTokenAddress(mintB, maker.publicKey);
+    takerAtaA = await getAssociatedTokenAddress(mintA, taker.publicKey);
+    takerAtaB = await getAssociatedTokenAddress(mintB, taker.publicKey);
+
+    // The maker pays for every account except the taker's receive ATA
+    await createAssociatedTokenAccount(provider.connection, maker, mintA, maker.publicKey);
+    await createAssociatedTokenAccount(provider.connection, maker, mintB, maker.publicKey);
+    await createAssociatedTokenAccount(provider.connection, maker, mintB, taker.publicKey);
+
+    await mintTo(provider.connection, maker, mintA, makerAtaA, maker, OFFER_AMOUNT.toNumber());
+    await mintTo(provider.connection, maker, mintB, takerAtaB, maker, RECEIVE_AMOUNT.toNumber());
+
+    [escrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), maker.publicKey.toBuffer()],
+      program.programId
+    );
+    [escrowVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), escrow.toBuffer()],
+      program.programId
+    );
+
+    console.log("Test setup completed");
+    console.log("Maker:", maker.publicKey.toString());
+    console.log("Taker:", taker.publicKey.toString());
+    console.log("Escrow PDA:", escrow.toString());
+  });
+
+  it("Initialize escrow with a taker ATA rent rebate", async () => {
+    const tx = await program.methods
+      .initializeEscrow(OFFER_AMOUNT, RECEIVE_AMOUNT, true, [], [], null)
+      .accounts({
+        maker: maker.publicKey,
+        escrow,
+        tokenMintA: mintA,
+        tokenMintB: mintB,
+        makerTokenAccount: makerAtaA,
+        makerReceiveTokenAccount: makerAtaB,
+        escrowTokenAccount: escrowVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([maker])
+      .rpc();
+
+    console.log("Initialize escrow tx:", tx);
+
+    const ataRent = await provider.connection.getMinimumBalanceForRentExemption(165);
+    const escrowAccount = await program.account.escrowState.fetch(escrow);
+    expect(escrowAccount.takerAtaRebate.toNumber()).to.equal(ataRent);
+
+    console.log("✅ Escrow initialized with rebate");
+  });
+
+  it("Taker without ATA rent completes the exchange", async () => {
+    const ataRent = await provider.connection.getMinimumBalanceForRentExemption(165);
+    const takerLamports = await provider.connection.getBalance(taker.publicKey);
+    expect(takerLamports).to.be.lessThan(ataRent);
+    expect(await provider.connection.getAccountInfo(takerAtaA)).to.be.null;
+
+    const tx = await program.methods
+      .exchangeEscrow()
+      .accounts({
+        taker: taker.publicKey,
+        escrow,
+        maker: maker.publicKey,
+        makerReceiveTokenAccount: makerAtaB,
+        takerTokenAccount: takerAtaB,
+        takerReceiveTokenAccount: takerAtaA,
+        tokenMintA: mintA,
+        escrowTokenAccount: escrowVault,
+        priceFeed: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([taker])
+      .rpc();
+
+    console.log("Exchange escrow tx:", tx);
+
+    const takerReceive = await getAccount(provider.connection, takerAtaA);
+    expect(takerReceive.amount.toString()).to.equal(OFFER_AMOUNT.toString());
+
+    const makerReceive = await getAccount(provider.connection, makerAtaB);
+    expect(makerReceive.amount.toString()).to.equal(RECEIVE_AMOUNT.toString());
+    expect(await provider.connection.getAccountInfo(escrowVault)).to.be.null;
+
+    // The taker only spent transaction fees
+    expect(await provider.connection.getBalance(taker.publicKey)).to.be.greaterThan(0);
+
+    console.log("✅ Exchange completed with maker-funded ATA rent");
+  });
+
+  it("Bundled escrow swaps two mints for one atomically", async () => {
+    const bundleMaker = Keypair.generate();
+    const bundleTaker = Keypair.generate();
+    for (const wallet of [bundleMaker, bundleTaker]) {
+      const sig = await provider.connection.requestAirdrop(wallet.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    }
+
+    // Maker gives 2 X + 3 Y, taker pays 1 Z
+    const mintX = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+    const mintY = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+    const mintZ = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+
+    const makerX = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintX, bundleMaker.publicKey);
+    const makerY = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintY, bundleMaker.publicKey);
+    const makerZ = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintZ, bundleMaker.publicKey);
+    const takerX = await getAssociatedTokenAddress(mintX, bundleTaker.publicKey);
+    const takerY = await createAssociatedTokenAccount(provider.connection, bundleTaker, mintY, bundleTaker.publicKey);
+    const takerZ = await createAssociatedTokenAccount(provider.connection, bundleTaker, mintZ, bundleTaker.publicKey);
+    await mintTo(provider.connection, bundleMaker, mintX, makerX, bundleMaker, 2);
+    await mintTo(provider.connection, bundleMaker, mintY, makerY, bundleMaker, 3);
+    await mintTo(provider.connection, bundleMaker, mintZ, takerZ, bundleMaker, 1);
+
+    const [bundleEscrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), bundleMaker.publicKey.toBuffer()],
+      program.programId
+    );
+    const [bundleVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), bundleEscrow.toBuffer()],
+      program.programId
+    );
+    // Extra legs sit in the escrow PDA's ATA for each leg mint
+    const legVaultY = await createAssociatedTokenAccount(
+      provider.connection, bundleMaker, mintY, bundleEscrow, undefined, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, true
+    );
+
+    await program.methods
+      .initializeEscrow(new anchor.BN(2), new anchor.BN(1), false, [{ mint: mintY, amount: new anchor.BN(3) }], [], null)
+      .accounts({
+        maker: bundleMaker.publicKey,
+        escrow: bundleEscrow,
+        tokenMintA: mintX,
+        tokenMintB: mintZ,
+        makerTokenAccount: makerX,
+        makerReceiveTokenAccount: makerZ,
+        escrowTokenAccount: bundleVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: makerY, isWritable: true, isSigner: false },
+        { pubkey: legVaultY, isWritable: true, isSigner: false },
+      ])
+      .signers([bundleMaker])
+      .rpc();
+
+    expect((await getAccount(provider.connection, legVaultY)).amount.toString()).to.equal("3");
+
+    await program.methods
+      .exchangeEscrow()
+      .accounts({
+        taker: bundleTaker.publicKey,
+        escrow: bundleEscrow,
+        maker: bundleMaker.publicKey,
+        makerReceiveTokenAccount: makerZ,
+        takerTokenAccount: takerZ,
+        takerReceiveTokenAccount: takerX,
+        tokenMintA: mintX,
+        escrowTokenAccount: bundleVault,
+        priceFeed: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: legVaultY, isWritable: true, isSigner: false },
+        { pubkey: mintY, isWritable: false, isSigner: false },
+        { pubkey: takerY, isWritable: true, isSigner: false },
+      ])
+      .signers([bundleTaker])
+      .rpc();
+
+    expect((await getAccount(provider.connection, takerX)).amount.toString()).to.equal("2");
+    expect((await getAccount(provider.connection, takerY)).amount.toString()).to.equal("3");
+    expect((await getAccount(provider.connection, makerZ)).amount.toString()).to.equal("1");
+    // The emptied leg vault is closed back to the maker
+    expect(await provider.connection.getAccountInfo(legVaultY)).to.be.null;
+
+    console.log("✅ Bundled escrow settled every leg");
+  });
+
+  it("Cancels a bundle whose leg destination was closed and whose leg vault was topped up", async () => {
+    const bundleMaker = Keypair.generate();
+    const sig = await provider.connection.requestAirdrop(bundleMaker.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(sig);
+
+    const mintX = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+    const mintY = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+    const mintZ = await createMint(provider.connection, bundleMaker, bundleMaker.publicKey, null, 0);
+    const makerX = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintX, bundleMaker.publicKey);
+    const makerY = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintY, bundleMaker.publicKey);
+    const makerZ = await createAssociatedTokenAccount(provider.connection, bundleMaker, mintZ, bundleMaker.publicKey);
+    await mintTo(provider.connection, bundleMaker, mintX, makerX, bundleMaker, 2);
+    await mintTo(provider.connection, bundleMaker, mintY, makerY, bundleMaker, 3);
+
+    const [bundleEscrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), bundleMaker.publicKey.toBuffer()],
+      program.programId
+    );
+    const [bundleVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), bundleEscrow.toBuffer()],
+      program.programId
+    );
+    const legVaultY = await createAssociatedTokenAccount(
+      provider.connection, bundleMaker, mintY, bundleEscrow, undefined, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID, true
+    );
+
+    await program.methods
+      .initializeEscrow(new anchor.BN(2), new anchor.BN(1), false, [{ mint: mintY, amount: new anchor.BN(3) }], [], null)
+      .accounts({
+        maker: bundleMaker.publicKey,
+        escrow: bundleEscrow,
+        tokenMintA: mintX,
+        tokenMintB: mintZ,
+        makerTokenAccount: makerX,
+        makerReceiveTokenAccount: makerZ,
+        escrowTokenAccount: bundleVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: makerY, isWritable: true, isSigner: false },
+        { pubkey: legVaultY, isWritable: true, isSigner: false },
+      ])
+      .signers([bundleMaker])
+      .rpc();
+
+    // The leg's destination no longer exists, and a stray token sits in the leg vault
+    await closeAccount(provider.connection, bundleMaker, makerY, bundleMaker.publicKey, bundleMaker);
+    await mintTo(provider.connection, bundleMaker, mintY, legVaultY, bundleMaker, 1);
+
+    await program.methods
+      .cancelEscrow()
+      .accounts({
+        maker: bundleMaker.publicKey,
+        escrow: bundleEscrow,
+        makerTokenAccount: makerX,
+        escrowTokenAccount: bundleVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts([
+        { pubkey: legVaultY, isWritable: true, isSigner: false },
+        { pubkey: mintY, isWritable: false, isSigner: false },
+        { pubkey: makerY, isWritable: true, isSigner: false },
+      ])
+      .signers([bundleMaker])
+      .rpc();
+
+    // The maker's ATA is recreated and receives the whole leg vault
+    expect((await getAccount(provider.connection, makerX)).amount.toString()).to.equal("2");
+    expect((await getAccount(provider.connection, makerY)).amount.toString()).to.equal("4");
+    expect(await provider.connection.getAccountInfo(legVaultY)).to.be.null;
+    expect(await provider.connection.getAccountInfo(bundleEscrow)).to.be.null;
+
+    console.log("✅ Bundle cancelled despite the closed leg destination");
+  });
+
+  it("Lets a third party reclaim an abandoned, expired escrow", async () => {
+    const absentMaker = Keypair.generate();
+    const cleaner = Keypair.generate();
+    for (const wallet of [absentMaker, cleaner]) {
+      const sig = await provider.connection.requestAirdrop(wallet.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    }
+
+    const mintX = await createMint(provider.connection, absentMaker, absentMaker.publicKey, null, 0);
+    const mintY = await createMint(provider.connection, absentMaker, absentMaker.publicKey, null, 0);
+    const makerX = await createAssociatedTokenAccount(provider.connection, absentMaker, mintX, absentMaker.publicKey);
+    const makerY = await createAssociatedTokenAccount(provider.connection, absentMaker, mintY, absentMaker.publicKey);
+    await mintTo(provider.connection, absentMaker, mintX, makerX, absentMaker, 5);
+
+    const [abandonedEscrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), absentMaker.publicKey.toBuffer()],
+      program.programId
+    );
+    const [abandonedVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), abandonedEscrow.toBuffer()],
+      program.programId
+    );
+
+    // Expires in 2s, and the maker keeps the escrow to themselves for 1s after that
+    const now = Math.floor(Date.now() / 1000);
+    await program.methods
+      .initializeEscrow(new anchor.BN(5), new anchor.BN(1), false, [], [], {
+        expiresAt: new anchor.BN(now + 2),
+        gracePeriod: new anchor.BN(1),
+      })
+      .accounts({
+        maker: absentMaker.publicKey,
+        escrow: abandonedEscrow,
+        tokenMintA: mintX,
+        tokenMintB: mintY,
+        makerTokenAccount: makerX,
+        makerReceiveTokenAccount: makerY,
+        escrowTokenAccount: abandonedVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([absentMaker])
+      .rpc();
+
+    const graceCancel = () =>
+      program.methods
+        .graceCancel()
+        .accounts({
+          caller: cleaner.publicKey,
+          maker: absentMaker.publicKey,
+          escrow: abandonedEscrow,
+          makerTokenAccount: makerX,
+          escrowTokenAccount: abandonedVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([cleaner])
+        .rpc();
+
+    try {
+      await graceCancel();
+      expect.fail("Grace cancel succeeded before expiry");
+    } catch (error) {
+      expect(error.toString()).to.include("EscrowNotAbandoned");
+    }
+
+    console.log("Waiting for expiry and grace period...");
+    await new Promise(resolve => setTimeout(resolve, 5000));
+
+    const cleanerBefore = await provider.connection.getBalance(cleaner.publicKey);
+    await graceCancel();
+
+    expect((await getAccount(provider.connection, makerX)).amount.toString()).to.equal("5");
+    expect(await provider.connection.getAccountInfo(abandonedEscrow)).to.be.null;
+    expect(await provider.connection.getAccountInfo(abandonedVault)).to.be.null;
+    // The bounty outweighs the caller's transaction fee
+    expect(await provider.connection.getBalance(cleaner.publicKey)).to.be.greaterThan(cleanerBefore);
+
+    console.log("✅ Abandoned escrow returned to its maker");
+  });
+
+  it("Fills a ratio escrow in varying amounts, rounding each payment up", async () => {
+    const ratioMaker = Keypair.generate();
+    const filler = Keypair.generate();
+    for (const wallet of [ratioMaker, filler]) {
+      const sig = await provider.connection.requestAirdrop(wallet.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    }
+
+    const mintX = await createMint(provider.connection, ratioMaker, ratioMaker.publicKey, null, 0);
+    const mintY = await createMint(provider.connection, ratioMaker, ratioMaker.publicKey, null, 0);
+    const makerX = await createAssociatedTokenAccount(provider.connection, ratioMaker, mintX, ratioMaker.publicKey);
+    const makerY = await createAssociatedTokenAccount(provider.connection, ratioMaker, mintY, ratioMaker.publicKey);
+    const fillerX = await createAssociatedTokenAccount(provider.connection, filler, mintX, filler.publicKey);
+    const fillerY = await createAssociatedTokenAccount(provider.connection, filler, mintY, filler.publicKey);
+    await mintTo(provider.connection, ratioMaker, mintX, makerX, ratioMaker, 100);
+    await mintTo(provider.connection, ratioMaker, mintY, fillerY, ratioMaker, 1_000);
+
+    const [ratioEscrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), ratioMaker.publicKey.toBuffer()],
+      program.programId
+    );
+    const [ratioVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), ratioEscrow.toBuffer()],
+      program.programId
+    );
+
+    // 100 X offered at 3 Y per 7 X
+    const NUM = 3;
+    const DEN = 7;
+    await program.methods
+      .initializeRatioEscrow(new anchor.BN(100), { numerator: new anchor.BN(NUM), denominator: new anchor.BN(DEN) }, null)
+      .accounts({
+        maker: ratioMaker.publicKey,
+        escrow: ratioEscrow,
+        tokenMintA: mintX,
+        tokenMintB: mintY,
+        makerTokenAccount: makerX,
+        makerReceiveTokenAccount: makerY,
+        escrowTokenAccount: ratioVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([ratioMaker])
+      .rpc();
+
+    const fill = (amount: number, maxPayment: number) =>
+      program.methods
+        .fillEscrow(new anchor.BN(amount), new anchor.BN(maxPayment))
+        .accounts({
+          taker: filler.publicKey,
+          escrow: ratioEscrow,
+          maker: ratioMaker.publicKey,
+          makerReceiveTokenAccount: makerY,
+          takerTokenAccount: fillerY,
+          takerReceiveTokenAccount: fillerX,
+          escrowTokenAccount: ratioVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([filler])
+        .rpc();
+
+    let received = 0;
+    for (const amount of [10, 33, 7, 50]) {
+      const expected = Math.ceil((amount * NUM) / DEN);
+      const before = await tokens(makerY);
+
+      // A max payment below the rounded-up price is refused
+      try {
+        await fill(amount, expected - 1);
+        expect.fail("Fill accepted a max payment below the price");
+      } catch (error) {
+        expect(error.toString()).to.include("SlippageExceeded");
+      }
+
+      await fill(amount, expected);
+      expect((await tokens(makerY)) - before).to.equal(expected);
+      received += amount;
+      expect(await tokens(fillerX)).to.equal(received);
+    }
+
+    // The last fill emptied the escrow and closed it back to the maker
+    expect(await provider.connection.getAccountInfo(ratioEscrow)).to.be.null;
+    expect(await provider.connection.getAccountInfo(ratioVault)).to.be.null;
+
+    console.log("✅ Ratio escrow filled in four parts");
+  });
+
+  it("Reduces an escrow and lets a taker exchange what is left", async () => {
+    const reduceMaker = Keypair.generate();
+    const reduceTaker = Keypair.generate();
+    for (const wallet of [reduceMaker, reduceTaker]) {
+      const sig = await provider.connection.requestAirdrop(wallet.publicKey, anchor.web3.LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    }
+
+    const mintX = await createMint(provider.connection, reduceMaker, reduceMaker.publicKey, null, 0);
+    const mintY = await createMint(provider.connection, reduceMaker, reduceMaker.publicKey, null, 0);
+    const makerX = await createAssociatedTokenAccount(provider.connection, reduceMaker, mintX, reduceMaker.publicKey);
+    const makerY = await createAssociatedTokenAccount(provider.connection, reduceMaker, mintY, reduceMaker.publicKey);
+    const takerX = await createAssociatedTokenAccount(provider.connection, reduceTaker, mintX, reduceTaker.publicKey);
+    const takerY = await createAssociatedTokenAccount(provider.connection, reduceTaker, mintY, reduceTaker.publicKey);
+    await mintTo(provider.connection, reduceMaker, mintX, makerX, reduceMaker, 300);
+    await mintTo(provider.connection, reduceMaker, mintY, takerY, reduceMaker, 100);
+
+    const [reduceEscrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_SEED), reduceMaker.publicKey.toBuffer()],
+      program.programId
+    );
+    const [reduceVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from(ESCROW_VAULT_SEED), reduceEscrow.toBuffer()],
+      program.programId
+    );
+
+    // 300 X for 100 Y
+    await program.methods
+      .initializeEscrow(new anchor.BN(300), new anchor.BN(100), false, [], [], null)
+      .accounts({
+        maker: reduceMaker.publicKey,
+        escrow: reduceEscrow,
+        tokenMintA: mintX,
+        tokenMintB: mintY,
+        makerTokenAccount: makerX,
+        makerReceiveTokenAccount: makerY,
+        escrowTokenAccount: reduceVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([reduceMaker])
+      .rpc();
+
+    // Pull 100 X back, keeping the price: 200 X for 67 Y (rounded up)
+    await program.methods
+      .reduceEscrow(new anchor.BN(100), true)
+      .accounts({
+        maker: reduceMaker.publicKey,
+        escrow: reduceEscrow,
+        makerTokenAccount: makerX,
+        escrowTokenAccount: reduceVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([reduceMaker])
+      .rpc();
+
+    const reduced = await program.account.escrowState.fetch(reduceEscrow);
+    expect(reduced.amountA.toNumber()).to.equal(200);
+    expect(reduced.amountB.toNumber()).to.equal(67);
+    expect((await getAccount(provider.connection, makerX)).amount.toString()).to.equal("100");
+    expect((await getAccount(provider.connection, reduceVault)).amount.toString()).to.equal("200");
+
+    await program.methods
+      .exchangeEscrow()
+      .accounts({
+        taker: reduceTaker.publicKey,
+        escrow: reduceEscrow,
+        maker: reduceMaker.publicKey,
+        makerReceiveTokenAccount: makerY,
+        takerTokenAccount: takerY,
+        takerReceiveTokenAccount: takerX,
+        tokenMintA: mintX,
+        escrowTokenAccount: reduceVault,
+        priceFeed: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([reduceTaker])
+      .rpc();
+
+    expect((await getAccount(provider.connection, takerX)).amount.toString()).to.equal("200");
+    expect((await getAccount(provider.connection, makerY)).amount.toString()).to.equal("67");
+    expect((await getAccount(provider.connection, takerY)).amount.toString()).to.equal("33");
+
+    console.log("✅ Reduced escrow exchanged at the same price");
+  });
+
+  describe("Batch Exchange", () => {
+    type MakerSet = {
+      maker: Keypair;
+      escrow: PublicKey;
+      escrowVault: PublicKey;
+      makerX: PublicKey;
+      makerY: PublicKey;
+    };
+
+    let taker: Keypair;
+    let takerX: PublicKey;
+    let takerY: PublicKey;
+    let makers: MakerSet[] = [];
+
+    const OFFER_AMOUNT = 100;
+    const ASK_AMOUNT = 50;
+
+    const writable = (pubkey: PublicKey) => ({ pubkey, isWritable: true, isSigner: false });
+
+    // [escrow, maker, escrow_vault, maker_receive, taker_token, taker_receive] per escrow
+    const exchangeSet = (set: MakerSet, makerReceive = set.makerY) =>
+      [set.escrow, set.maker.publicKey, set.escrowVault, makerReceive, takerY, takerX].map(writable);
+
+    const exchangeBatch = (sets: ReturnType<typeof exchangeSet>[]) =>
+      program.methods
+        .exchangeEscrowBatch()
+        .accounts({ taker: taker.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(sets.flat())
+        .signers([taker])
+        .rpc();
+
+    before(async () => {
+      taker = await fundedKeypair(1);
+
+      const mintX = await createMint(provider.connection, taker, taker.publicKey, null, 0);
+      const mintY = await createMint(provider.connection, taker, taker.publicKey, null, 0);
+      takerX = await createAssociatedTokenAccount(provider.connection, taker, mintX, taker.publicKey);
+      takerY = await createAssociatedTokenAccount(provider.connection, taker, mintY, taker.publicKey);
+      await mintTo(provider.connection, taker, mintY, takerY, taker, 3 * ASK_AMOUNT);
+
+      for (let i = 0; i < 3; i++) {
+        const maker = await fundedKeypair(1);
+        const makerX = await createAssociatedTokenAccount(provider.connection, maker, mintX, maker.publicKey);
+        const makerY = await createAssociatedTokenAccount(provider.connection, maker, mintY, maker.publicKey);
+        await mintTo(provider.connection, taker, mintX, makerX, taker, OFFER_AMOUNT);
+
+        const escrow = pda([Buffer.from(ESCROW_SEED), maker.publicKey.toBuffer()]);
+        const escrowVault = pda([Buffer.from(ESCROW_VAULT_SEED), escrow.toBuffer()]);
+        await program.methods
+          .initializeEscrow(new anchor.BN(OFFER_AMOUNT), new anchor.BN(ASK_AMOUNT), false, [], [], null)
+          .accounts({
+            maker: maker.publicKey,
+            escrow,
+            tokenMintA: mintX,
+            tokenMintB: mintY,
+            makerTokenAccount: makerX,
+            makerReceiveTokenAccount: makerY,
+            escrowTokenAccount: escrowVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([maker])
+          .rpc();
+
+        makers.push({ maker, escrow, escrowVault, makerX, makerY });
+      }
+    });
+
+    it("Rolls back the whole batch when the second set is invalid", async () => {
+      // The second maker's payment is routed to the taker's own account
+      try {
+        await exchangeBatch([exchangeSet(makers[0]), exchangeSet(makers[1], takerY), exchangeSet(makers[2])]);
+        expect.fail("Exchanged a batch with an invalid set");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidEscrowBatchAccounts");
+      }
+
+      // Not even the first escrow was taken
+      expect(await provider.connection.getAccountInfo(makers[0].escrow)).to.not.be.null;
+      expect(await tokens(makers[0].escrowVault)).to.equal(OFFER_AMOUNT);
+      expect(await tokens(makers[0].makerY)).to.equal(0);
+      expect(await tokens(takerX)).to.equal(0);
+      expect(await tokens(takerY)).to.equal(3 * ASK_AMOUNT);
+    });
+
+    it("Fills three escrows in one transaction", async () => {
+      await exchangeBatch(makers.map(set => exchangeSet(set)));
+
+      expect(await tokens(takerX)).to.equal(3 * OFFER_AMOUNT);
+      expect(await tokens(takerY)).to.equal(0);
+      for (const set of makers) {
+        expect(await tokens(set.makerY)).to.equal(ASK_AMOUNT);
+        expect(await provider.connection.getAccountInfo(set.escrow)).to.be.null;
+        expect(await provider.connection.getAccountInfo(set.escrowVault)).to.be.null;
+      }
+      console.log("✅ Took", makers.length, "escrows for", 3 * OFFER_AMOUNT, "X in one transaction");
+    });
+  });
+
+  describe("Oracle Pricing", () => {
+    let publisher: Keypair;
+    let maker: Keypair;
+    let taker: Keypair;
+    let mintX: PublicKey;
+    let makerY: PublicKey;
+    let takerX: PublicKey;
+    let takerY: PublicKey;
+    let priceFeed: PublicKey;
+    let escrow: PublicKey;
+    let escrowVault: PublicKey;
+
+    const OFFER_AMOUNT = 1_000;
+    const EXPO = -2; // Prices in hundredths of a Y per X
+    const MAX_STALENESS = 2;
+    const MAX_CONFIDENCE_BPS = 100;
+
+    const publish = (price: number, conf: number) =>
+      program.methods
+        .updatePriceFeed(new anchor.BN(price), new anchor.BN(conf), EXPO)
+        .accounts({ publisher: publisher.publicKey, priceFeed })
+        .signers([publisher])
+        .rpc();
+
+    const exchange = () =>
+      program.methods
+        .exchangeEscrow()
+        .accounts({
+          taker: taker.publicKey,
+          escrow,
+          maker: maker.publicKey,
+          makerReceiveTokenAccount: makerY,
+          takerTokenAccount: takerY,
+          takerReceiveTokenAccount: takerX,
+          tokenMintA: mintX,
+          escrowTokenAccount: escrowVault,
+          priceFeed,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([taker])
+        .rpc();
+
+    before(async () => {
+      publisher = await fundedKeypair(1);
+      maker = await fundedKeypair(1);
+      taker = await fundedKeypair(1);
+
+      mintX = await createMint(provider.connection, maker, maker.publicKey, null, 0);
+      const mintY = await createMint(provider.connection, maker, maker.publicKey, null, 0);
+      const makerX = await createAssociatedTokenAccount(provider.connection, maker, mintX, maker.publicKey);
+      makerY = await createAssociatedTokenAccount(provider.connection, maker, mintY, maker.publicKey);
+      takerX = await createAssociatedTokenAccount(provider.connection, taker, mintX, taker.publicKey);
+      takerY = await createAssociatedTokenAccount(provider.connection, taker, mintY, taker.publicKey);
+      await mintTo(provider.connection, maker, mintX, makerX, maker, OFFER_AMOUNT);
+      await mintTo(provider.connection, maker, mintY, takerY, maker, 10_000);
+
+      // A mock feed: 2.50 Y per X, give or take 0.01
+      priceFeed = pda([Buffer.from("price_feed"), publisher.publicKey.toBuffer(), mintX.toBuffer(), mintY.toBuffer()]);
+      await program.methods
+        .initializePriceFeed(new anchor.BN(250), new anchor.BN(1), EXPO)
+        .accounts({
+          publisher: publisher.publicKey,
+          baseMint: mintX,
+          quoteMint: mintY,
+          priceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([publisher])
+        .rpc();
+
+      escrow = pda([Buffer.from(ESCROW_SEED), maker.publicKey.toBuffer()]);
+      escrowVault = pda([Buffer.from(ESCROW_VAULT_SEED), escrow.toBuffer()]);
+      await program.methods
+        .initializeOracleEscrow(
+          new anchor.BN(OFFER_AMOUNT),
+          { priceFeed, maxStaleness: new anchor.BN(MAX_STALENESS), maxConfidenceBps: MAX_CONFIDENCE_BPS },
+          null
+        )
+        .accounts({
+          maker: maker.publicKey,
+          escrow,
+          tokenMintA: mintX,
+          tokenMintB: mintY,
+          makerTokenAccount: makerX,
+          makerReceiveTokenAccount: makerY,
+          escrowTokenAccount: escrowVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([maker])
+        .rpc();
+    });
+
+    it("Rejects an exchange against a stale feed", async () => {
+      await sleep((MAX_STALENESS + 1) * 1000);
+      try {
+        await exchange();
+        expect.fail("Exchanged at a stale price");
+      } catch (error) {
+        expect(error.toString()).to.include("StalePriceFeed");
+      }
+    });
+
+    it("Rejects an exchange while the feed is uncertain", async () => {
+      // 0.10 either side of 3.00 is wider than 1%
+      await publish(300, 10);
+      try {
+        await exchange();
+        expect.fail("Exchanged at an uncertain price");
+      } catch (error) {
+        expect(error.toString()).to.include("PriceFeedUncertain");
+      }
+    });
+
+    it("Charges the taker the oracle-derived amount", async () => {
+      await publish(300, 1);
+      const takerYBefore = await tokens(takerY);
+
+      await exchange();
+
+      // 1,000 X at 3.00 Y each, not the 2.50 the feed showed at init
+      expect(await tokens(makerY)).to.equal(3_000);
+      expect(takerYBefore - (await tokens(takerY))).to.equal(3_000);
+      expect(await tokens(takerX)).to.equal(OFFER_AMOUNT);
+      expect(await provider.connection.getAccountInfo(escrow)).to.be.null;
+      console.log("✅ Taker paid 3,000 Y for 1,000 X at the feed's price");
+    });
+  });
+});
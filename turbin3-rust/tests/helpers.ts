@@ -0,0 +1,313 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Turbin3Rust } from "../target/types/turbin3_rust";
+import { ConfirmOptions, Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  mintTo,
+  getAccount,
+  createAssociatedTokenAccount,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+
+// Fixtures shared by the suites: PDAs, balances, funded wallets, AMM pools and staking pools
+
+const provider = anchor.AnchorProvider.env();
+anchor.setProvider(provider);
+
+const program = anchor.workspace.Turbin3Rust as Program<Turbin3Rust>;
+
+export const pda = (seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+export const sleep = (ms: number) => new Promise(resolve => setTimeout(resolve, ms));
+
+// Token balance of `account` in base units
+export const balance = async (account: PublicKey, tokenProgram: PublicKey = TOKEN_PROGRAM_ID) =>
+  new anchor.BN((await getAccount(provider.connection, account, undefined, tokenProgram)).amount.toString());
+
+export const fundedKeypair = async (sol = 5) => {
+  const wallet = Keypair.generate();
+  const signature = await provider.connection.requestAirdrop(wallet.publicKey, sol * anchor.web3.LAMPORTS_PER_SOL);
+  await provider.connection.confirmTransaction(signature);
+  return wallet;
+};
+
+// Two fresh mints in the canonical (sorted) order the AMM requires
+export const sortedMints = async (authority: Keypair, decimals = 6): Promise<[PublicKey, PublicKey]> => {
+  const mintX = await createMint(provider.connection, authority, authority.publicKey, null, decimals);
+  const mintY = await createMint(provider.connection, authority, authority.publicKey, null, decimals);
+  return Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0 ? [mintX, mintY] : [mintY, mintX];
+};
+
+export type Pool = {
+  tokenAMint: PublicKey;
+  tokenBMint: PublicKey;
+  amm: PublicKey;
+  lpMint: PublicKey;
+  tokenAVault: PublicKey;
+  tokenBVault: PublicKey;
+  observations: PublicKey;
+  tokenProgram: PublicKey;
+};
+
+// A wallet's A, B and LP token accounts for one pool
+export type Lp = { wallet: Keypair; tokenA: PublicKey; tokenB: PublicKey; lpToken: PublicKey };
+
+export const poolAddresses = (tokenAMint: PublicKey, tokenBMint: PublicKey, tokenProgram = TOKEN_PROGRAM_ID): Pool => {
+  const amm = pda([Buffer.from("amm"), tokenAMint.toBuffer(), tokenBMint.toBuffer()]);
+  return {
+    tokenAMint,
+    tokenBMint,
+    amm,
+    lpMint: pda([Buffer.from("lp_mint"), amm.toBuffer()]),
+    tokenAVault: pda([Buffer.from("vault_a"), amm.toBuffer()]),
+    tokenBVault: pda([Buffer.from("vault_b"), amm.toBuffer()]),
+    observations: pda([Buffer.from("observations"), amm.toBuffer()]),
+    tokenProgram,
+  };
+};
+
+export type PoolOptions = {
+  fee?: number;
+  lpDecimals?: number | null;
+  curveType?: any;
+  decimals?: number;
+  // Pre-made sorted mints, e.g. Token-2022 mints with extensions
+  mints?: [PublicKey, PublicKey];
+  tokenProgram?: PublicKey;
+};
+
+// Initializes an empty pool administered by `admin`, over fresh mints unless `mints` is given
+export const createPool = async (admin: Keypair, options: PoolOptions = {}): Promise<Pool> => {
+  const [tokenAMint, tokenBMint] = options.mints ?? (await sortedMints(admin, options.decimals));
+  const pool = poolAddresses(tokenAMint, tokenBMint, options.tokenProgram);
+
+  await program.methods
+    .initializeAmm(options.fee ?? 30, 0, null, null, 0, options.lpDecimals ?? null, options.curveType ?? null)
+    .accounts({
+      admin: admin.publicKey,
+      amm: pool.amm,
+      tokenAMint,
+      tokenBMint,
+      tokenAVault: pool.tokenAVault,
+      tokenBVault: pool.tokenBVault,
+      lpMint: pool.lpMint,
+      tokenProgram: pool.tokenProgram,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  return pool;
+};
+
+// `wallet`'s A, B and LP accounts, holding `amountA` and `amountB` minted by the mints' `authority`
+export const fundLp = async (
+  pool: Pool,
+  authority: Keypair,
+  wallet: Keypair,
+  amountA: anchor.BN | number,
+  amountB: anchor.BN | number
+): Promise<Lp> => {
+  const createAccount = (mint: PublicKey) =>
+    createAssociatedTokenAccount(provider.connection, wallet, mint, wallet.publicKey, undefined, pool.tokenProgram);
+  const tokenA = await createAccount(pool.tokenAMint);
+  const tokenB = await createAccount(pool.tokenBMint);
+  const lpToken = await createAccount(pool.lpMint);
+  await mintTo(provider.connection, authority, pool.tokenAMint, tokenA, authority, BigInt(amountA.toString()), [], undefined, pool.tokenProgram);
+  await mintTo(provider.connection, authority, pool.tokenBMint, tokenB, authority, BigInt(amountB.toString()), [], undefined, pool.tokenProgram);
+  return { wallet, tokenA, tokenB, lpToken };
+};
+
+export const lpPosition = (pool: Pool, owner: PublicKey) =>
+  pda([Buffer.from("lp_position"), pool.amm.toBuffer(), owner.toBuffer()]);
+
+export const deposit = (pool: Pool, lp: Lp, amountA: anchor.BN, amountB: anchor.BN, minLpTokens = new anchor.BN(0)) =>
+  program.methods
+    .depositLiquidity(amountA, amountB, minLpTokens, new anchor.BN(0))
+    .accounts({
+      user: lp.wallet.publicKey,
+      amm: pool.amm,
+      tokenAMint: pool.tokenAMint,
+      tokenBMint: pool.tokenBMint,
+      userTokenA: lp.tokenA,
+      userTokenB: lp.tokenB,
+      userLpToken: lp.lpToken,
+      lpPosition: lpPosition(pool, lp.wallet.publicKey),
+      lockedLpVault: null,
+      tokenAVault: pool.tokenAVault,
+      tokenBVault: pool.tokenBVault,
+      lpMint: pool.lpMint,
+      tokenProgram: pool.tokenProgram,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([lp.wallet])
+    .rpc();
+
+// A pool funded by and seeded with `amountA` and `amountB` from `admin`, plus `extraA` and `extraB` left over to trade
+export const seededPool = async (
+  admin: Keypair,
+  amountA: anchor.BN,
+  amountB: anchor.BN,
+  options: PoolOptions & { extraA?: anchor.BN; extraB?: anchor.BN } = {}
+) => {
+  const pool = await createPool(admin, options);
+  const lp = await fundLp(
+    pool,
+    admin,
+    admin,
+    amountA.add(options.extraA ?? new anchor.BN(0)),
+    amountB.add(options.extraB ?? new anchor.BN(0))
+  );
+  await deposit(pool, lp, amountA, amountB);
+  return { pool, lp };
+};
+
+// Accounts for `swapTokens` and `swapBatch`, trading `lp`'s A for B when `zeroForOne`
+export const swapAccounts = (pool: Pool, lp: Lp, zeroForOne: boolean) => ({
+  user: lp.wallet.publicKey,
+  amm: pool.amm,
+  tokenAMint: pool.tokenAMint,
+  tokenBMint: pool.tokenBMint,
+  userTokenIn: zeroForOne ? lp.tokenA : lp.tokenB,
+  userTokenOut: zeroForOne ? lp.tokenB : lp.tokenA,
+  tokenAVault: pool.tokenAVault,
+  tokenBVault: pool.tokenBVault,
+  lpMint: pool.lpMint,
+  referralTokenAccount: null,
+  observations: pool.observations,
+  tokenProgram: pool.tokenProgram,
+});
+
+export const swap = (pool: Pool, lp: Lp, zeroForOne: boolean, amountIn: anchor.BN, minAmountOut = new anchor.BN(0)) =>
+  program.methods
+    .swapTokens(amountIn, minAmountOut, zeroForOne)
+    .accounts(swapAccounts(pool, lp, zeroForOne))
+    .signers([lp.wallet])
+    .rpc();
+
+export const quote = (pool: Pool, amountIn: anchor.BN, zeroForOne = true, observations: PublicKey | null = null) =>
+  program.methods
+    .quote(amountIn, zeroForOne)
+    .accounts({ amm: pool.amm, tokenAVault: pool.tokenAVault, tokenBVault: pool.tokenBVault, observations })
+    .view();
+
+export type StakingPool = {
+  stakeMint: PublicKey;
+  rewardMint: PublicKey;
+  stakingPool: PublicKey;
+  stakeVault: PublicKey;
+  rewardVault: PublicKey;
+};
+
+// A staker's wallet and accounts in one staking pool
+export type Staker = { wallet: Keypair; userStake: PublicKey; stakeAccount: PublicKey; rewardAccount: PublicKey };
+
+export const userStakeOf = (pool: StakingPool, owner: PublicKey) =>
+  pda([Buffer.from("user_stake"), pool.stakingPool.toBuffer(), owner.toBuffer()]);
+
+export type StakingPoolOptions = {
+  // Fresh mints minted by `admin` unless given
+  stakeMint?: PublicKey;
+  rewardMint?: PublicKey;
+  cooldown?: anchor.BN;
+};
+
+// Initializes a staking pool emitting `rewardRate` a second, with a one-second
+// cooldown and no cap, penalty, halving or minimum lock
+export const createStakingPool = async (
+  admin: Keypair,
+  rewardRate: anchor.BN,
+  options: StakingPoolOptions = {}
+): Promise<StakingPool> => {
+  const stakeMint = options.stakeMint ?? (await createMint(provider.connection, admin, admin.publicKey, null, 6));
+  const rewardMint = options.rewardMint ?? (await createMint(provider.connection, admin, admin.publicKey, null, 6));
+  const stakingPool = pda([Buffer.from("staking_pool"), stakeMint.toBuffer(), rewardMint.toBuffer()]);
+  const pool = {
+    stakeMint,
+    rewardMint,
+    stakingPool,
+    stakeVault: pda([Buffer.from("stake_vault"), stakingPool.toBuffer()]),
+    rewardVault: pda([Buffer.from("reward_vault"), stakingPool.toBuffer()]),
+  };
+
+  await program.methods
+    .initializeStakingPool(rewardRate, options.cooldown ?? new anchor.BN(1), new anchor.BN(0), 0, new anchor.BN(0), new anchor.BN(0))
+    .accounts({
+      admin: admin.publicKey,
+      ...pool,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([admin])
+    .rpc();
+
+  return pool;
+};
+
+// Mints `amount` of reward tokens to the admin and funds the pool with them,
+// returning the admin's reward account
+export const fundRewards = async (pool: StakingPool, admin: Keypair, amount: anchor.BN) => {
+  const adminRewardAccount = (
+    await getOrCreateAssociatedTokenAccount(provider.connection, admin, pool.rewardMint, admin.publicKey)
+  ).address;
+  await mintTo(provider.connection, admin, pool.rewardMint, adminRewardAccount, admin, BigInt(amount.toString()));
+
+  await program.methods
+    .fundRewards(amount)
+    .accounts({
+      admin: admin.publicKey,
+      stakingPool: pool.stakingPool,
+      adminRewardAccount,
+      rewardVault: pool.rewardVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([admin])
+    .rpc();
+
+  return adminRewardAccount;
+};
+
+// `wallet`'s stake and reward accounts, holding `amount` of stake tokens minted by `authority`
+export const newStaker = async (pool: StakingPool, authority: Keypair, amount: anchor.BN, wallet?: Keypair): Promise<Staker> => {
+  wallet = wallet ?? (await fundedKeypair(2));
+  const stakeAccount = (
+    await getOrCreateAssociatedTokenAccount(provider.connection, wallet, pool.stakeMint, wallet.publicKey)
+  ).address;
+  const rewardAccount = (
+    await getOrCreateAssociatedTokenAccount(provider.connection, wallet, pool.rewardMint, wallet.publicKey)
+  ).address;
+  await mintTo(provider.connection, authority, pool.stakeMint, stakeAccount, authority, BigInt(amount.toString()));
+  return { wallet, userStake: userStakeOf(pool, wallet.publicKey), stakeAccount, rewardAccount };
+};
+
+export const stake = (pool: StakingPool, staker: Staker, amount: anchor.BN, options?: ConfirmOptions) =>
+  program.methods
+    .stakeTokens(amount)
+    .accounts({
+      user: staker.wallet.publicKey,
+      stakingPool: pool.stakingPool,
+      userStake: staker.userStake,
+      userStakeAccount: staker.stakeAccount,
+      stakeVault: pool.stakeVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([staker.wallet])
+    .rpc(options);
+
+export const claimRewards = (pool: StakingPool, staker: Staker, options?: ConfirmOptions) =>
+  program.methods
+    .claimRewards()
+    .accounts({
+      user: staker.wallet.publicKey,
+      stakingPool: pool.stakingPool,
+      userStake: staker.userStake,
+      userRewardAccount: staker.rewardAccount,
+      rewardVault: pool.rewardVault,
+      solRewardVault: null,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    .signers([staker.wallet])
+    .rpc(options);
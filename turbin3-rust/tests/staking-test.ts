@@ -6,10 +6,12 @@ import {
   PublicKey,
   SystemProgram,
   SYSVAR_RENT_PUBKEY,
+  Transaction,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
+  NATIVE_MINT,
   createMint,
   createAccount,
   mintTo,
@@ -18,6 +20,20 @@ import {
   createAssociatedTokenAccount,
 } from "@solana/spl-token";
 import { expect } from "chai";
+import {
+  Staker,
+  StakingPool,
+  balance,
+  claimRewards,
+  createStakingPool,
+  fundRewards,
+  fundedKeypair,
+  newStaker,
+  pda,
+  sleep,
+  stake,
+  userStakeOf,
+} from "./helpers";
 
 describe("Staking System", () => {
   const provider = anchor.AnchorProvider.env();
@@ -42,6 +58,12 @@ describe("Staking System", () => {
   const STAKE_AMOUNT = new anchor.BN(1000_000); // 1 token (6 decimals)
   const REWARD_RATE = new anchor.BN(100); // 100 rewards per second
   const MIN_STAKE_AMOUNT = new anchor.BN(100_000); // 0.1 token minimum
+  const MAX_TOTAL_STAKED = new anchor.BN(0); // 0 = no cap
+  const EARLY_UNSTAKE_PENALTY_BPS = 0; // Stake and reward mints differ
+  const HALVING_INTERVAL = new anchor.BN(0); // Constant reward rate
+  const MIN_LOCK_PERIOD = new anchor.BN(0); // Unstake can be requested immediately
+  const COOLDOWN_SECONDS = 2; // Wait between request_unstake and execute_unstake
+  const COOLDOWN_PERIOD = new anchor.BN(COOLDOWN_SECONDS);
 
   before(async () => {
     // Generate test accounts
@@ -134,7 +156,7 @@ describe("Staking System", () => {
   it("Initialize staking pool", async () => {
     try {
       const tx = await program.methods
-        .initializeStakingPool(REWARD_RATE, MIN_STAKE_AMOUNT)
+        .initializeStakingPool(REWARD_RATE, COOLDOWN_PERIOD, MAX_TOTAL_STAKED, EARLY_UNSTAKE_PENALTY_BPS, HALVING_INTERVAL, MIN_LOCK_PERIOD)
         .accounts({
           stakingPool: stakingPool.publicKey,
           stakeMint: mint,
@@ -284,6 +306,7 @@ describe("Staking System", () => {
           userStake: userStake,
           rewardVault: rewardPoolAta,
           userRewardAccount: userRewardAta,
+          solRewardVault: null,
           user: user.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
@@ -306,6 +329,104 @@ describe("Staking System", () => {
     }
   });
 
+  it("Claim rewards from two pools at once", async () => {
+    // A second pool paying a different reward token on the same stake mint
+    const secondPool = Keypair.generate();
+    const secondRewardMint = await createMint(provider.connection, user, user.publicKey, null, 6);
+    const secondStakeVault = await getAssociatedTokenAddress(mint, secondPool.publicKey);
+    const secondRewardVault = await getAssociatedTokenAddress(secondRewardMint, secondPool.publicKey);
+    const secondUserRewardAta = await createAssociatedTokenAccount(
+      provider.connection,
+      user,
+      secondRewardMint,
+      user.publicKey
+    );
+    await mintTo(provider.connection, user, secondRewardMint, secondUserRewardAta, user, 50_000_000);
+
+    const [secondUserStake] = PublicKey.findProgramAddressSync(
+      [Buffer.from(USER_STAKE_SEED), secondPool.publicKey.toBuffer(), user.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .initializeStakingPool(REWARD_RATE, COOLDOWN_PERIOD, MAX_TOTAL_STAKED, EARLY_UNSTAKE_PENALTY_BPS, HALVING_INTERVAL, MIN_LOCK_PERIOD)
+      .accounts({
+        stakingPool: secondPool.publicKey,
+        stakeMint: mint,
+        rewardMint: secondRewardMint,
+        stakeVault: secondStakeVault,
+        rewardVault: secondRewardVault,
+        authority: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+      })
+      .signers([secondPool, user])
+      .rpc();
+
+    await program.methods
+      .fundRewards(new anchor.BN(50_000_000))
+      .accounts({
+        stakingPool: secondPool.publicKey,
+        rewardVault: secondRewardVault,
+        funderRewardAccount: secondUserRewardAta,
+        funder: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([user])
+      .rpc();
+
+    await program.methods
+      .stakeTokens(STAKE_AMOUNT)
+      .accounts({
+        stakingPool: secondPool.publicKey,
+        userStake: secondUserStake,
+        stakeVault: secondStakeVault,
+        userStakeAccount: userStakeAta,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    console.log("Waiting 3 seconds to accumulate rewards in both pools...");
+    await new Promise(resolve => setTimeout(resolve, 3000));
+
+    const firstBefore = await getAccount(provider.connection, userRewardAta);
+    const secondBefore = await getAccount(provider.connection, secondUserRewardAta);
+
+    const writable = (pubkey: PublicKey) => ({ pubkey, isWritable: true, isSigner: false });
+    const tx = await program.methods
+      .claimRewardsMulti()
+      .accounts({
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        writable(stakingPool.publicKey),
+        writable(userStake),
+        writable(rewardPoolAta),
+        writable(userRewardAta),
+        writable(secondPool.publicKey),
+        writable(secondUserStake),
+        writable(secondRewardVault),
+        writable(secondUserRewardAta),
+      ])
+      .signers([user])
+      .rpc();
+
+    console.log("Claim rewards multi tx:", tx);
+
+    const firstAfter = await getAccount(provider.connection, userRewardAta);
+    const secondAfter = await getAccount(provider.connection, secondUserRewardAta);
+    expect(firstAfter.amount > firstBefore.amount).to.be.true;
+    expect(secondAfter.amount > secondBefore.amount).to.be.true;
+
+    console.log("✅ Rewards claimed from both pools in one transaction");
+  });
+
   it("Partial unstake", async () => {
     try {
       const UNSTAKE_AMOUNT = new anchor.BN(750_000); // 0.75 tokens
@@ -314,14 +435,66 @@ describe("Staking System", () => {
       const initialUserBalance = await getAccount(provider.connection, userStakeAta);
       const initialVaultBalance = await getAccount(provider.connection, stakingPoolAta);
 
+      // Can't queue more than is staked
+      try {
+        await program.methods
+          .requestUnstake(STAKE_AMOUNT.muln(10))
+          .accounts({
+            stakingPool: stakingPool.publicKey,
+            userStake: userStake,
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+        expect.fail("Requested more than the user has staked");
+      } catch (error) {
+        expect(error.toString()).to.include("StakeInsufficientBalance");
+      }
+
+      await program.methods
+        .requestUnstake(UNSTAKE_AMOUNT)
+        .accounts({
+          stakingPool: stakingPool.publicKey,
+          userStake: userStake,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      // Principal stays put until the cooldown has run from the request
+      try {
+        await program.methods
+          .executeUnstake()
+          .accounts({
+            stakingPool: stakingPool.publicKey,
+            userStake: userStake,
+            stakeVault: stakingPoolAta,
+            userStakeAccount: userStakeAta,
+            user: user.publicKey,
+            cooldownExemption: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc();
+        expect.fail("Unstake executed before the cooldown elapsed");
+      } catch (error) {
+        expect(error.toString()).to.include("CooldownNotMet");
+      }
+      const pendingStake = await program.account.userStake.fetch(userStake);
+      expect(pendingStake.unstakeRequestAmount.toString()).to.equal(UNSTAKE_AMOUNT.toString());
+
+      console.log(`Waiting ${COOLDOWN_SECONDS} seconds for the unstake cooldown...`);
+      await new Promise(resolve => setTimeout(resolve, COOLDOWN_SECONDS * 1000));
+
       const tx = await program.methods
-        .unstakeTokens(UNSTAKE_AMOUNT)
+        .executeUnstake()
         .accounts({
           stakingPool: stakingPool.publicKey,
           userStake: userStake,
           stakeVault: stakingPoolAta,
           userStakeAccount: userStakeAta,
           user: user.publicKey,
+          cooldownExemption: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([user])
@@ -356,14 +529,26 @@ describe("Staking System", () => {
       const userStakeAccount = await program.account.userStake.fetch(userStake);
       const remainingStake = userStakeAccount.amount;
 
+      await program.methods
+        .requestUnstake(remainingStake)
+        .accounts({
+          stakingPool: stakingPool.publicKey,
+          userStake: userStake,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      await new Promise(resolve => setTimeout(resolve, COOLDOWN_SECONDS * 1000));
+
       const tx = await program.methods
-        .unstakeTokens(remainingStake)
+        .executeUnstake()
         .accounts({
           stakingPool: stakingPool.publicKey,
           userStake: userStake,
           stakeVault: stakingPoolAta,
           userStakeAccount: userStakeAta,
           user: user.publicKey,
+          cooldownExemption: null,
           tokenProgram: TOKEN_PROGRAM_ID,
         })
         .signers([user])
@@ -385,4 +570,882 @@ describe("Staking System", () => {
       throw error;
     }
   });
+
+  describe("Staking APR View", () => {
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const REWARD_RATE = new anchor.BN(1_000);
+    const STAKE_AMOUNT = new anchor.BN(3_153_600_000);
+    const SECONDS_PER_YEAR = 365 * 86400;
+
+    const getApr = () => program.methods.getApr().accounts({ stakingPool: pool.stakingPool }).view();
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      staker = await newStaker(pool, admin, STAKE_AMOUNT, admin);
+    });
+
+    it("Reports zero APR for an empty pool", async () => {
+      expect((await getApr()).toNumber()).to.equal(0);
+    });
+
+    it("Annualizes the reward rate against the weighted stake", async () => {
+      await stake(pool, staker, STAKE_AMOUNT);
+
+      // 1_000 per second over a year is 31_536_000_000: ten times the stake
+      const expected = REWARD_RATE.muln(SECONDS_PER_YEAR).muln(10_000).div(STAKE_AMOUNT);
+      expect(expected.toNumber()).to.equal(100_000);
+      expect((await getApr()).toString()).to.equal(expected.toString());
+
+      console.log("✅ APR:", expected.toNumber() / 100, "%");
+    });
+  });
+
+  describe("Staking Boost Tokens", () => {
+    let pool: StakingPool;
+    let boostVault: PublicKey;
+    let boosted: BoostStaker;
+    let plain: BoostStaker;
+
+    type BoostStaker = Staker & { boostAccount: PublicKey };
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const BOOST_AMOUNT = new anchor.BN(500_000); // Half a boost token per staked token: 1.5x
+    const FUND_AMOUNT = new anchor.BN(100_000_000);
+    const REWARD_RATE = new anchor.BN(10_000);
+
+    const changeBoost = (method: "applyBoost" | "removeBoost", staker: BoostStaker, amount: anchor.BN) =>
+      program.methods[method](amount)
+        .accounts({
+          user: staker.wallet.publicKey,
+          stakingPool: pool.stakingPool,
+          userStake: staker.userStake,
+          userBoostAccount: staker.boostAccount,
+          boostVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker.wallet])
+        .rpc();
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      const boostMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
+      boostVault = pda([Buffer.from("boost_vault"), pool.stakingPool.toBuffer()]);
+
+      await program.methods
+        .enableBoost()
+        .accounts({
+          admin: admin.publicKey,
+          stakingPool: pool.stakingPool,
+          boostMint,
+          boostVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+
+      await fundRewards(pool, admin, FUND_AMOUNT);
+
+      // Two stakers with equal principal, each holding enough boost tokens for 1.5x
+      const newBoostStaker = async (): Promise<BoostStaker> => {
+        const staker = await newStaker(pool, admin, STAKE_AMOUNT);
+        const boostAccount = await createAssociatedTokenAccount(provider.connection, staker.wallet, boostMint, staker.wallet.publicKey);
+        await mintTo(provider.connection, admin, boostMint, boostAccount, admin, BOOST_AMOUNT.toNumber());
+        await stake(pool, staker, STAKE_AMOUNT);
+        return { ...staker, boostAccount };
+      };
+      boosted = await newBoostStaker();
+      plain = await newBoostStaker();
+    });
+
+    it("Pays a boosted staker more than an unboosted one with equal principal", async () => {
+      await changeBoost("applyBoost", boosted, BOOST_AMOUNT);
+      expect((await balance(boostVault)).toString()).to.equal(BOOST_AMOUNT.toString());
+
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.totalWeightedStake.toString()).to.equal(STAKE_AMOUNT.muln(5).divn(2).toString());
+
+      console.log("Waiting 4 seconds to accrue rewards...");
+      await sleep(4000);
+
+      await claimRewards(pool, boosted);
+      await claimRewards(pool, plain);
+      const boostedRewards = await balance(boosted.rewardAccount);
+      const plainRewards = await balance(plain.rewardAccount);
+
+      // 1.5x weight, less the seconds earned at 1x before the boost and after the boosted claim
+      expect(boostedRewards.gt(plainRewards)).to.be.true;
+      expect(boostedRewards.muln(100).div(plainRewards).toNumber()).to.be.at.most(150);
+
+      console.log("✅ Boosted staker earned", boostedRewards.toString(), "vs", plainRewards.toString());
+    });
+
+    it("Returns boost tokens and the base weight on remove_boost", async () => {
+      await changeBoost("removeBoost", boosted, BOOST_AMOUNT);
+
+      expect((await balance(boosted.boostAccount)).toString()).to.equal(BOOST_AMOUNT.toString());
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.totalWeightedStake.toString()).to.equal(STAKE_AMOUNT.muln(2).toString());
+
+      // Only locked tokens can be withdrawn
+      try {
+        await changeBoost("removeBoost", boosted, new anchor.BN(1));
+        expect.fail("Removed more boost than was applied");
+      } catch (error) {
+        expect(error.toString()).to.include("BoostInsufficientBalance");
+      }
+    });
+  });
+
+  describe("Claim Rewards To Recipient", () => {
+    let pool: StakingPool;
+    let staker: Staker;
+    let recipient: Keypair;
+    let recipientRewardAccount: PublicKey;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+
+    const claimTo = (recipientTokenAccount: PublicKey) =>
+      program.methods
+        .claimRewardsTo()
+        .accounts({
+          user: staker.wallet.publicKey,
+          stakingPool: pool.stakingPool,
+          userStake: staker.userStake,
+          recipientTokenAccount,
+          rewardVault: pool.rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker.wallet])
+        .rpc();
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      recipient = Keypair.generate();
+      pool = await createStakingPool(admin, new anchor.BN(1_000));
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+
+      staker = await newStaker(pool, admin, STAKE_AMOUNT);
+      // The third party never signs; the staker pays for their account
+      recipientRewardAccount = await createAssociatedTokenAccount(provider.connection, staker.wallet, pool.rewardMint, recipient.publicKey);
+
+      await stake(pool, staker, STAKE_AMOUNT);
+      await sleep(2000);
+    });
+
+    it("Rejects a recipient for a different mint", async () => {
+      // A token account for the stake mint, not the reward mint
+      const wrongMintAccount = await createAssociatedTokenAccount(provider.connection, staker.wallet, pool.stakeMint, recipient.publicKey);
+      try {
+        await claimTo(wrongMintAccount);
+        expect.fail("Claimed rewards into a stake mint account");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidRewardRecipient");
+      }
+    });
+
+    it("Pays the staker's rewards to a third party's account", async () => {
+      await claimTo(recipientRewardAccount);
+
+      const received = (await balance(recipientRewardAccount)).toNumber();
+      expect(received).to.be.greaterThan(0);
+      expect((await balance(staker.rewardAccount)).toString()).to.equal("0");
+
+      const stakeAccount = await program.account.userStake.fetch(staker.userStake);
+      expect(stakeAccount.pendingRewards.toString()).to.equal("0");
+      console.log("✅ Recipient received", received, "reward tokens; staker's own account untouched");
+    });
+  });
+
+  describe("Staking Cooldown Whitelist", () => {
+    let pool: StakingPool;
+    let integrator: Staker;
+    let regular: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const COOLDOWN = new anchor.BN(3600);
+
+    const exemptionPda = (wallet: PublicKey) =>
+      pda([Buffer.from("cooldown_exemption"), pool.stakingPool.toBuffer(), wallet.toBuffer()]);
+
+    // Request and immediately execute an unstake of the staker's whole principal
+    const unstakeNow = async (staker: Staker, cooldownExemption: PublicKey | null) => {
+      await program.methods
+        .requestUnstake(STAKE_AMOUNT)
+        .accounts({ stakingPool: pool.stakingPool, userStake: staker.userStake, user: staker.wallet.publicKey })
+        .signers([staker.wallet])
+        .rpc();
+
+      await program.methods
+        .executeUnstake()
+        .accounts({
+          stakingPool: pool.stakingPool,
+          userStake: staker.userStake,
+          stakeVault: pool.stakeVault,
+          userStakeAccount: staker.stakeAccount,
+          user: staker.wallet.publicKey,
+          cooldownExemption,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([staker.wallet])
+        .rpc();
+    };
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, new anchor.BN(1_000), { cooldown: COOLDOWN });
+
+      integrator = await newStaker(pool, admin, STAKE_AMOUNT);
+      regular = await newStaker(pool, admin, STAKE_AMOUNT);
+      await stake(pool, integrator, STAKE_AMOUNT);
+      await stake(pool, regular, STAKE_AMOUNT);
+
+      await program.methods
+        .addCooldownExemption(integrator.wallet.publicKey)
+        .accounts({
+          admin: admin.publicKey,
+          stakingPool: pool.stakingPool,
+          cooldownExemption: exemptionPda(integrator.wallet.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([admin])
+        .rpc();
+    });
+
+    it("Lets a whitelisted integrator unstake without waiting", async () => {
+      await unstakeNow(integrator, exemptionPda(integrator.wallet.publicKey));
+
+      const unstaked = await balance(integrator.stakeAccount);
+      expect(unstaked.toString()).to.equal(STAKE_AMOUNT.toString());
+      console.log("✅ Integrator unstaked", unstaked.toString(), "instantly");
+    });
+
+    it("Keeps the cooldown for everyone else", async () => {
+      try {
+        await unstakeNow(regular, null);
+        expect.fail("Unstaked before the cooldown elapsed");
+      } catch (error) {
+        expect(error.toString()).to.include("CooldownNotMet");
+      }
+
+      // Another staker's whitelist entry doesn't carry over
+      try {
+        await unstakeNow(regular, exemptionPda(integrator.wallet.publicKey));
+        expect.fail("Used another staker's exemption");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintSeeds");
+      }
+    });
+  });
+
+  describe("Staking Lock Boost", () => {
+    let pool: StakingPool;
+    let alice: Staker;
+    let bob: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const REWARD_RATE = new anchor.BN(10_000);
+    const NINETY_DAYS = new anchor.BN(90 * 86400);
+
+    before(async () => {
+      const admin = await fundedKeypair(2);
+      pool = await createStakingPool(admin, REWARD_RATE);
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+
+      alice = await newStaker(pool, admin, STAKE_AMOUNT);
+      bob = await newStaker(pool, admin, STAKE_AMOUNT);
+    });
+
+    it("Upgrades a no-lock stake to 90 days and earns boosted rewards", async () => {
+      await stake(pool, alice, STAKE_AMOUNT);
+      await stake(pool, bob, STAKE_AMOUNT);
+
+      const upgrade = (duration: anchor.BN) =>
+        program.methods
+          .upgradeLock(duration)
+          .accounts({
+            user: bob.wallet.publicKey,
+            stakingPool: pool.stakingPool,
+            userStake: bob.userStake,
+          })
+          .signers([bob.wallet])
+          .rpc();
+
+      await upgrade(NINETY_DAYS);
+
+      const bobStake = await program.account.userStake.fetch(bob.userStake);
+      expect(bobStake.boostBps).to.equal(15_000);
+      expect(bobStake.lockDuration.toString()).to.equal(NINETY_DAYS.toString());
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.totalWeightedStake.toString()).to.equal(STAKE_AMOUNT.muln(25).divn(10).toString());
+
+      // A lock can only be extended
+      try {
+        await upgrade(NINETY_DAYS);
+        expect.fail("Re-locking for the same duration succeeded");
+      } catch (error) {
+        expect(error.toString()).to.include("LockNotExtended");
+      }
+
+      // Settle everything earned so far, then measure a clean window
+      await sleep(1000);
+      await claimRewards(pool, alice);
+      await claimRewards(pool, bob);
+      const aliceStart = await balance(alice.rewardAccount);
+      const bobStart = await balance(bob.rewardAccount);
+
+      console.log("Waiting 4 seconds to accumulate rewards...");
+      await sleep(4000);
+
+      await claimRewards(pool, alice);
+      await claimRewards(pool, bob);
+      const aliceEarned = (await balance(alice.rewardAccount)).sub(aliceStart).toNumber();
+      const bobEarned = (await balance(bob.rewardAccount)).sub(bobStart).toNumber();
+
+      // Bob's weight is 1.5x Alice's; allow a second of slack between the claims
+      expect(bobEarned).to.be.greaterThan(aliceEarned * 1.2);
+
+      // The boosted principal cannot start an exit while locked
+      try {
+        await program.methods
+          .requestUnstake(STAKE_AMOUNT)
+          .accounts({
+            user: bob.wallet.publicKey,
+            stakingPool: pool.stakingPool,
+            userStake: bob.userStake,
+          })
+          .signers([bob.wallet])
+          .rpc();
+        expect.fail("Unstake requested during the lock");
+      } catch (error) {
+        expect(error.toString()).to.include("StakeLocked");
+      }
+
+      console.log("✅ Lock upgrade boosted rewards");
+    });
+  });
+
+  describe("Staking Reward Projection", () => {
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const REWARD_RATE = new anchor.BN(1_000);
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const LOOKAHEAD = 3;
+
+    const chainTime = async () => (await provider.connection.getBlockTime(await provider.connection.getSlot())) as number;
+    const projectRewards = (timestamp: number) =>
+      program.methods
+        .projectRewards(new anchor.BN(timestamp))
+        .accounts({ stakingPool: pool.stakingPool, userStake: staker.userStake })
+        .view();
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+      staker = await newStaker(pool, admin, STAKE_AMOUNT, admin);
+      await stake(pool, staker, STAKE_AMOUNT);
+    });
+
+    it("Rejects a timestamp that is not in the future", async () => {
+      try {
+        await projectRewards((await chainTime()) - 1);
+        expect.fail("Projected rewards into the past");
+      } catch (error) {
+        expect(error.toString()).to.include("ProjectionNotInFuture");
+      }
+    });
+
+    it("Projects what a claim at that time pays out", async () => {
+      const target = (await chainTime()) + LOOKAHEAD;
+      const projected = await projectRewards(target);
+      // Every further second adds one second of emissions for the only staker
+      expect((await projectRewards(target + 10)).sub(projected).toString()).to.equal(REWARD_RATE.muln(10).toString());
+
+      console.log(`Waiting ${LOOKAHEAD + 1} seconds to reach the projected time...`);
+      await sleep((LOOKAHEAD + 1) * 1000);
+
+      const before = await balance(staker.rewardAccount);
+      await claimRewards(pool, staker);
+      const claimed = (await balance(staker.rewardAccount)).sub(before);
+
+      // The claim lands at or after the projected time, so it pays the
+      // projection plus whole seconds of emissions past it
+      const overshoot = claimed.sub(projected);
+      expect(overshoot.gten(0)).to.be.true;
+      expect(overshoot.mod(REWARD_RATE).toNumber()).to.equal(0);
+      expect(overshoot.lte(REWARD_RATE.muln(LOOKAHEAD))).to.be.true;
+
+      console.log("✅ Projected", projected.toString(), "claimed", claimed.toString());
+    });
+  });
+
+  describe("Per-User Reward Cap", () => {
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    // The pool emits 1_000 a second, so two seconds of staking reach the cap
+    const MAX_REWARD_PER_USER = 1_500;
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, new anchor.BN(1_000));
+
+      await program.methods
+        .setMaxRewardPerUser(new anchor.BN(MAX_REWARD_PER_USER))
+        .accounts({ admin: admin.publicKey, stakingPool: pool.stakingPool })
+        .signers([admin])
+        .rpc();
+
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+      staker = await newStaker(pool, admin, STAKE_AMOUNT);
+      await stake(pool, staker, STAKE_AMOUNT);
+      await sleep(2000);
+    });
+
+    it("Pays a claim only up to the staker's cap", async () => {
+      await claimRewards(pool, staker);
+
+      expect((await balance(staker.rewardAccount)).toNumber()).to.equal(MAX_REWARD_PER_USER);
+      const stakeAccount = await program.account.userStake.fetch(staker.userStake);
+      expect(stakeAccount.totalClaimed.toNumber()).to.equal(MAX_REWARD_PER_USER);
+    });
+
+    it("Pays nothing more once the cap is reached", async () => {
+      await sleep(2000);
+      const vaultBefore = await balance(pool.rewardVault);
+
+      await claimRewards(pool, staker);
+
+      expect((await balance(staker.rewardAccount)).toNumber()).to.equal(MAX_REWARD_PER_USER);
+      expect((await balance(pool.rewardVault)).toString()).to.equal(vaultBefore.toString());
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.maxRewardPerUser.toNumber()).to.equal(MAX_REWARD_PER_USER);
+      console.log("✅ Claims stopped paying at", MAX_REWARD_PER_USER, "reward tokens");
+    });
+  });
+
+  describe("Reward Index Events", () => {
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+
+    // The single RewardIndexUpdated event `tx` emitted
+    const rewardIndexEvent = async (tx: string) => {
+      const txInfo = await provider.connection.getTransaction(tx, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const events = [...parser.parseLogs(txInfo.meta.logMessages)].filter(event => event.name === "rewardIndexUpdated");
+      expect(events).to.have.lengthOf(1);
+      return events[0].data;
+    };
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, new anchor.BN(1_000));
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+      staker = await newStaker(pool, admin, STAKE_AMOUNT);
+    });
+
+    it("Emits the settled index when staking", async () => {
+      const tx = await stake(pool, staker, STAKE_AMOUNT, { commitment: "confirmed" });
+
+      const payload = await rewardIndexEvent(tx);
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(payload.pool.toString()).to.equal(pool.stakingPool.toString());
+      expect(payload.accumulatedRewardPerShare.toString()).to.equal(poolAccount.accumulatedRewardPerShare.toString());
+      expect(payload.timestamp.toString()).to.equal(poolAccount.lastUpdateTime.toString());
+      // Settled before the stake landed, so nothing was staked yet
+      expect(payload.totalStaked.toString()).to.equal("0");
+    });
+
+    it("Emits the grown index when claiming", async () => {
+      await sleep(2000);
+
+      const tx = await claimRewards(pool, staker, { commitment: "confirmed" });
+
+      const payload = await rewardIndexEvent(tx);
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(payload.pool.toString()).to.equal(pool.stakingPool.toString());
+      expect(payload.accumulatedRewardPerShare.toString()).to.equal(poolAccount.accumulatedRewardPerShare.toString());
+      expect(payload.accumulatedRewardPerShare.gtn(0)).to.be.true;
+      expect(payload.totalStaked.toString()).to.equal(STAKE_AMOUNT.toString());
+      console.log("✅ Claim settled the index to", payload.accumulatedRewardPerShare.toString());
+    });
+  });
+
+  describe("Unallocated Reward Withdrawal", () => {
+    let admin: Keypair;
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const FUND_AMOUNT = new anchor.BN(10_000_000);
+    const REWARD_RATE = new anchor.BN(1_000);
+
+    const withdraw = (amount: anchor.BN) =>
+      program.methods
+        .withdrawUnallocatedRewards(amount)
+        .accounts({
+          admin: admin.publicKey,
+          stakingPool: pool.stakingPool,
+          adminRewardAccount: staker.rewardAccount,
+          rewardVault: pool.rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+
+    before(async () => {
+      admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      await fundRewards(pool, admin, FUND_AMOUNT);
+      // The admin stakes too, so their reward account doubles as the claim target
+      staker = await newStaker(pool, admin, STAKE_AMOUNT, admin);
+      await stake(pool, staker, STAKE_AMOUNT);
+    });
+
+    it("Withdraws only the surplus not yet owed to stakers", async () => {
+      console.log("Waiting 3 seconds to accrue rewards...");
+      await sleep(3000);
+
+      // Rewards have accrued, so the whole funding is no longer free to take back
+      try {
+        await withdraw(FUND_AMOUNT);
+        expect.fail("Withdrew rewards owed to stakers");
+      } catch (error) {
+        expect(error.toString()).to.include("RewardsAlreadyAllocated");
+      }
+
+      // Well under a minute of emissions is owed, so leaving 100k behind is safe
+      const surplus = FUND_AMOUNT.subn(100_000);
+      await withdraw(surplus);
+      expect((await balance(staker.rewardAccount)).toString()).to.equal(surplus.toString());
+
+      const poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.rewardsOwed.gtn(0)).to.be.true;
+      expect(poolAccount.rewardsOwed.lten(100_000)).to.be.true;
+
+      // The staker can still claim everything they accrued
+      await claimRewards(pool, staker);
+      const claimed = (await balance(staker.rewardAccount)).sub(surplus);
+      expect(claimed.gte(poolAccount.rewardsOwed)).to.be.true;
+
+      console.log("✅ Withdrew surplus", surplus.toString(), "and the staker claimed", claimed.toString());
+    });
+  });
+
+  describe("Native SOL Staking Rewards", () => {
+    let admin: Keypair;
+    let user: Keypair;
+    let stakingPool: PublicKey;
+    let solRewardVault: PublicKey;
+    let userStake: PublicKey;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const REWARD_RATE = new anchor.BN(1_000);
+
+    before(async () => {
+      admin = await fundedKeypair();
+      user = await fundedKeypair();
+
+      // Rewards are paid in SOL: the pool's reward mint is the native mint
+      const pool = await createStakingPool(admin, REWARD_RATE, { rewardMint: NATIVE_MINT });
+      stakingPool = pool.stakingPool;
+      solRewardVault = pda([Buffer.from("sol_reward_vault"), stakingPool.toBuffer()]);
+      userStake = userStakeOf(pool, user.publicKey);
+
+      // The SOL reward vault is a plain system account, funded with a transfer
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: admin.publicKey,
+            toPubkey: solRewardVault,
+            lamports: anchor.web3.LAMPORTS_PER_SOL,
+          })
+        ),
+        [admin]
+      );
+
+      const stakeAccount = await createAssociatedTokenAccount(provider.connection, user, pool.stakeMint, user.publicKey);
+      await mintTo(provider.connection, admin, pool.stakeMint, stakeAccount, admin, STAKE_AMOUNT.toNumber());
+      await stake(pool, { wallet: user, userStake, stakeAccount, rewardAccount: null }, STAKE_AMOUNT);
+    });
+
+    it("Claims SOL rewards as lamports", async () => {
+      console.log("Waiting 3 seconds to accumulate rewards...");
+      await sleep(3000);
+
+      const vaultBefore = await provider.connection.getBalance(solRewardVault);
+      const userBefore = await provider.connection.getBalance(user.publicKey);
+
+      // The admin pays the fee so the user's balance only moves by the reward
+      const tx = await program.methods
+        .claimRewards()
+        .accounts({
+          user: user.publicKey,
+          stakingPool,
+          userStake,
+          userRewardAccount: null,
+          rewardVault: null,
+          solRewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .transaction();
+      tx.feePayer = admin.publicKey;
+      await provider.sendAndConfirm(tx, [admin, user]);
+
+      const paid = vaultBefore - (await provider.connection.getBalance(solRewardVault));
+      const userGain = (await provider.connection.getBalance(user.publicKey)) - userBefore;
+
+      expect(paid).to.be.greaterThan(0);
+      expect(userGain).to.equal(paid);
+      console.log("✅ Claimed", paid, "lamports of SOL rewards");
+    });
+
+    it("Rejects SPL reward accounts on a SOL reward pool", async () => {
+      // Let some rewards accrue so the claim reaches the payout branch
+      await sleep(1500);
+      try {
+        await program.methods
+          .claimRewards()
+          .accounts({
+            user: user.publicKey,
+            stakingPool,
+            userStake,
+            userRewardAccount: null,
+            rewardVault: pda([Buffer.from("reward_vault"), stakingPool.toBuffer()]),
+            solRewardVault: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+        expect.fail("Claim without the SOL reward vault succeeded");
+      } catch (error) {
+        expect(error.toString()).to.include("RewardAccountsMismatch");
+      }
+    });
+  });
+
+  describe("Delegated Staking", () => {
+    let pool: StakingPool;
+    let operator: Staker;
+    let beneficiary: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+
+    // Claims and unstake requests against the beneficiary's stake, signed by `signer`
+    const claimAs = (signer: Staker) => claimRewards(pool, { ...signer, userStake: beneficiary.userStake });
+
+    const requestUnstake = (signer: Keypair) =>
+      program.methods
+        .requestUnstake(STAKE_AMOUNT)
+        .accounts({ stakingPool: pool.stakingPool, userStake: beneficiary.userStake, user: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      const admin = await fundedKeypair();
+      pool = await createStakingPool(admin, new anchor.BN(1_000));
+      await fundRewards(pool, admin, new anchor.BN(1_000_000_000));
+
+      operator = await newStaker(pool, admin, STAKE_AMOUNT);
+      beneficiary = await newStaker(pool, admin, new anchor.BN(0));
+    });
+
+    it("Opens a stake owned by the beneficiary with the operator's tokens", async () => {
+      await program.methods
+        .stakeFor(beneficiary.wallet.publicKey, STAKE_AMOUNT)
+        .accounts({
+          operator: operator.wallet.publicKey,
+          stakingPool: pool.stakingPool,
+          userStake: beneficiary.userStake,
+          operatorStakeAccount: operator.stakeAccount,
+          stakeVault: pool.stakeVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([operator.wallet])
+        .rpc();
+
+      const stakeAccount = await program.account.userStake.fetch(beneficiary.userStake);
+      expect(stakeAccount.user.toString()).to.equal(beneficiary.wallet.publicKey.toString());
+      expect(stakeAccount.amount.toString()).to.equal(STAKE_AMOUNT.toString());
+      expect((await balance(operator.stakeAccount)).toString()).to.equal("0");
+      expect((await balance(pool.stakeVault)).toString()).to.equal(STAKE_AMOUNT.toString());
+    });
+
+    it("Does not let the operator claim or unstake", async () => {
+      await sleep(2000);
+
+      try {
+        await claimAs(operator);
+        expect.fail("Operator claimed the beneficiary's rewards");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintSeeds");
+      }
+
+      try {
+        await requestUnstake(operator.wallet);
+        expect.fail("Operator started withdrawing the principal");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintSeeds");
+      }
+    });
+
+    it("Lets the beneficiary claim and unstake to their own wallet", async () => {
+      await claimAs(beneficiary);
+      expect((await balance(beneficiary.rewardAccount)).toNumber()).to.be.greaterThan(0);
+
+      await requestUnstake(beneficiary.wallet);
+      await sleep(2000);
+      await program.methods
+        .executeUnstake()
+        .accounts({
+          stakingPool: pool.stakingPool,
+          userStake: beneficiary.userStake,
+          stakeVault: pool.stakeVault,
+          userStakeAccount: beneficiary.stakeAccount,
+          user: beneficiary.wallet.publicKey,
+          cooldownExemption: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([beneficiary.wallet])
+        .rpc();
+
+      expect((await balance(beneficiary.stakeAccount)).toString()).to.equal(STAKE_AMOUNT.toString());
+      expect((await balance(operator.stakeAccount)).toString()).to.equal("0");
+      console.log("✅ Beneficiary unstaked", STAKE_AMOUNT.toString(), "staked on their behalf");
+    });
+  });
+
+  describe("Reward Dust After The Last Exit", () => {
+    let admin: Keypair;
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const FUND_AMOUNT = new anchor.BN(10_000_000);
+    const REWARD_RATE = new anchor.BN(1_000);
+
+    before(async () => {
+      admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      await fundRewards(pool, admin, FUND_AMOUNT);
+      staker = await newStaker(pool, admin, STAKE_AMOUNT, admin);
+      await stake(pool, staker, STAKE_AMOUNT);
+    });
+
+    it("Leaves no reward dust stranded once the last staker exits", async () => {
+      console.log("Waiting 3 seconds to accrue rewards...");
+      await sleep(3000);
+
+      const exitAccounts = { user: admin.publicKey, stakingPool: pool.stakingPool, userStake: staker.userStake };
+      await program.methods.requestUnstake(STAKE_AMOUNT).accounts(exitAccounts).signers([admin]).rpc();
+      await program.methods
+        .executeUnstake()
+        .accounts({
+          ...exitAccounts,
+          userStakeAccount: staker.stakeAccount,
+          stakeVault: pool.stakeVault,
+          cooldownExemption: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+
+      // With nobody staked, only what was settled to the staker is still owed
+      let poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.totalStaked.toNumber()).to.equal(0);
+      expect(poolAccount.rewardsOwed.toString()).to.equal(poolAccount.rewardsSettled.toString());
+      expect(poolAccount.rewardsSettled.gtn(0)).to.be.true;
+
+      await claimRewards(pool, staker);
+      poolAccount = await program.account.stakingPool.fetch(pool.stakingPool);
+      expect(poolAccount.rewardsOwed.toNumber()).to.equal(0);
+
+      // Everything left in the reward vault, rounding dust included, is reclaimable:
+      // the stranded epsilon is zero
+      const remaining = await balance(pool.rewardVault);
+      await program.methods
+        .withdrawUnallocatedRewards(remaining)
+        .accounts({
+          admin: admin.publicKey,
+          stakingPool: pool.stakingPool,
+          adminRewardAccount: staker.rewardAccount,
+          rewardVault: pool.rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+      expect((await balance(pool.rewardVault)).toNumber()).to.equal(0);
+      expect((await balance(staker.rewardAccount)).toString()).to.equal(FUND_AMOUNT.toString());
+
+      console.log("✅ Reclaimed", remaining.toString(), "reward tokens after the last exit");
+    });
+  });
+
+  describe("Vested Reward Funding", () => {
+    let admin: Keypair;
+    let pool: StakingPool;
+    let staker: Staker;
+
+    const STAKE_AMOUNT = new anchor.BN(1_000_000);
+    const FUND_AMOUNT = new anchor.BN(10_000_000);
+    const VESTING_SECONDS = 10;
+    // Negligible next to the tranche's 1_000_000 per second, and funded separately
+    const REWARD_RATE = new anchor.BN(1);
+    const BASE_FUNDING = new anchor.BN(1_000);
+
+    before(async () => {
+      admin = await fundedKeypair();
+      pool = await createStakingPool(admin, REWARD_RATE);
+      await fundRewards(pool, admin, BASE_FUNDING);
+      staker = await newStaker(pool, admin, STAKE_AMOUNT, admin);
+      await stake(pool, staker, STAKE_AMOUNT);
+    });
+
+    it("Releases a vested tranche linearly over its duration", async () => {
+      await mintTo(provider.connection, admin, pool.rewardMint, staker.rewardAccount, admin, FUND_AMOUNT.toNumber());
+      await program.methods
+        .fundRewardsVested(FUND_AMOUNT, new anchor.BN(VESTING_SECONDS))
+        .accounts({
+          admin: admin.publicKey,
+          stakingPool: pool.stakingPool,
+          adminRewardAccount: staker.rewardAccount,
+          rewardVault: pool.rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([admin])
+        .rpc();
+      expect((await balance(pool.rewardVault)).toString()).to.equal(FUND_AMOUNT.add(BASE_FUNDING).toString());
+
+      // Part way through, only part of the tranche has been released
+      console.log("Waiting 3 seconds into the vesting period...");
+      await sleep(3000);
+      await claimRewards(pool, staker);
+      const early = await balance(staker.rewardAccount);
+      expect(early.gtn(0)).to.be.true;
+      expect(early.lt(FUND_AMOUNT.divn(2))).to.be.true;
+
+      // Once the duration is over, the whole tranche has vested and nothing more
+      // than the base rate accrues
+      console.log(`Waiting out the rest of the ${VESTING_SECONDS}s vesting period...`);
+      await sleep(VESTING_SECONDS * 1000);
+      await claimRewards(pool, staker);
+      const total = await balance(staker.rewardAccount);
+      expect(total.gte(FUND_AMOUNT)).to.be.true;
+      expect(total.lte(FUND_AMOUNT.add(BASE_FUNDING))).to.be.true;
+
+      console.log("✅ Claimed", early.toString(), "early and", total.toString(), "in total");
+    });
+  });
 });
@@ -0,0 +1,492 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Turbin3Rust } from "../target/types/turbin3_rust";
+import { Keypair, PublicKey, SystemProgram, Transaction, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createMint,
+  mintTo,
+  createAssociatedTokenAccount,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import { balance, fundedKeypair, pda } from "./helpers";
+
+describe("Vault", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Turbin3Rust as Program<Turbin3Rust>;
+
+  type Vault = { owner: Keypair; vaultState: PublicKey; vaultAuth: PublicKey; vault: PublicKey };
+
+  // Initializes `owner`'s vault
+  const createVault = async (owner: Keypair): Promise<Vault> => {
+    const vaultState = pda([Buffer.from("state"), owner.publicKey.toBuffer()]);
+    const vaultAuth = pda([Buffer.from("auth"), vaultState.toBuffer()]);
+    const vault = pda([Buffer.from("vault"), vaultState.toBuffer()]);
+
+    await program.methods
+      .initializeVault()
+      .accounts({ owner: owner.publicKey, vaultState, vaultAuth, vault, systemProgram: SystemProgram.programId })
+      .signers([owner])
+      .rpc();
+
+    return { owner, vaultState, vaultAuth, vault };
+  };
+
+  const fundVault = ({ owner, vault }: Vault, lamports: number) =>
+    provider.sendAndConfirm(
+      new Transaction().add(SystemProgram.transfer({ fromPubkey: owner.publicKey, toPubkey: vault, lamports })),
+      [owner]
+    );
+
+  describe("Batch Deposits", () => {
+    let payer: Keypair;
+    let vaults: Vault[];
+
+    const AMOUNTS = [0.1, 0.2, 0.3].map(sol => new anchor.BN(sol * LAMPORTS_PER_SOL));
+
+    const depositBatch = (amounts: anchor.BN[], targets: Vault[]) =>
+      program.methods
+        .depositBatch(amounts)
+        .accounts({ payer: payer.publicKey, systemProgram: SystemProgram.programId })
+        .remainingAccounts(
+          targets.flatMap(({ vaultState, vault }) => [
+            { pubkey: vaultState, isSigner: false, isWritable: true },
+            { pubkey: vault, isSigner: false, isWritable: true },
+          ])
+        )
+        .signers([payer])
+        .rpc();
+
+    before(async () => {
+      payer = await fundedKeypair();
+      vaults = [];
+      for (let i = 0; i < 3; i++) {
+        vaults.push(await createVault(await fundedKeypair(1)));
+      }
+    });
+
+    it("Funds three vaults in one transaction", async () => {
+      const before = await Promise.all(vaults.map(({ vault }) => provider.connection.getBalance(vault)));
+
+      await depositBatch(AMOUNTS, vaults);
+
+      for (const [i, { vaultState, vault }] of vaults.entries()) {
+        expect((await provider.connection.getBalance(vault)) - before[i]).to.equal(AMOUNTS[i].toNumber());
+        const state = await program.account.vaultState.fetch(vaultState);
+        expect(state.lifetimeDeposited.toString()).to.equal(AMOUNTS[i].toString());
+      }
+      console.log("✅ Funded three vaults in one transaction");
+    });
+
+    it("Deposits nothing if any entry fails", async () => {
+      const before = await Promise.all(vaults.map(({ vault }) => provider.connection.getBalance(vault)));
+
+      // The last pair points at the wrong vault
+      const mismatched = { ...vaults[2], vault: vaults[0].vault };
+      try {
+        await depositBatch(AMOUNTS, [vaults[0], vaults[1], mismatched]);
+        expect.fail("Batch with a mismatched vault succeeded");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidVaultAccounts");
+      }
+
+      const after = await Promise.all(vaults.map(({ vault }) => provider.connection.getBalance(vault)));
+      expect(after).to.deep.equal(before);
+    });
+
+    it("Rejects batches over the cap", async () => {
+      const amounts = Array.from({ length: 9 }, () => new anchor.BN(1_000));
+      try {
+        await depositBatch(amounts, []);
+        expect.fail("Oversized batch succeeded");
+      } catch (error) {
+        expect(error.toString()).to.include("DepositBatchTooLarge");
+      }
+    });
+  });
+
+  describe("Deposit Cap", () => {
+    let owner: Keypair;
+    let vault: Vault;
+
+    const CAP = new anchor.BN(LAMPORTS_PER_SOL);
+
+    const deposit = (amount: anchor.BN) =>
+      program.methods
+        .depositSol(amount)
+        .accounts({ owner: owner.publicKey, vaultState: vault.vaultState, vault: vault.vault, systemProgram: SystemProgram.programId })
+        .signers([owner])
+        .rpc();
+
+    const setDepositCap = (cap: anchor.BN) =>
+      program.methods
+        .setDepositCap(cap)
+        .accounts({ owner: owner.publicKey, vaultState: vault.vaultState })
+        .signers([owner])
+        .rpc();
+
+    before(async () => {
+      owner = await fundedKeypair(3);
+      vault = await createVault(owner);
+      await setDepositCap(CAP);
+    });
+
+    it("Accepts deposits up to the cap", async () => {
+      await deposit(CAP.divn(2));
+      await deposit(CAP.divn(2));
+
+      expect(await provider.connection.getBalance(vault.vault)).to.equal(CAP.toNumber());
+      expect((await program.account.vaultState.fetch(vault.vaultState)).depositCap.toString()).to.equal(CAP.toString());
+    });
+
+    it("Rejects a deposit beyond the cap", async () => {
+      try {
+        await deposit(new anchor.BN(1));
+        expect.fail("Deposited past the cap");
+      } catch (error) {
+        expect(error.toString()).to.include("DepositCapExceeded");
+      }
+      expect(await provider.connection.getBalance(vault.vault)).to.equal(CAP.toNumber());
+
+      // Lifting the cap reopens deposits
+      await setDepositCap(new anchor.BN(0));
+      await deposit(new anchor.BN(1));
+      expect(await provider.connection.getBalance(vault.vault)).to.equal(CAP.toNumber() + 1);
+    });
+  });
+
+  describe("Deposits On Behalf Of Another Owner", () => {
+    let owner: Keypair;
+    let payer: Keypair;
+    let vault: Vault;
+
+    const AMOUNT = new anchor.BN(LAMPORTS_PER_SOL / 2);
+
+    const withdraw = (signer: Keypair) =>
+      program.methods
+        .withdrawSol(AMOUNT, null)
+        .accounts({
+          owner: signer.publicKey,
+          vaultState: vault.vaultState,
+          vaultAuth: vault.vaultAuth,
+          vault: vault.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      owner = await fundedKeypair(2);
+      payer = await fundedKeypair(2);
+      vault = await createVault(owner);
+    });
+
+    it("Funds another wallet's vault, which only the beneficiary can withdraw", async () => {
+      const vaultBefore = await provider.connection.getBalance(vault.vault);
+
+      const tx = await program.methods
+        .depositSolFor(owner.publicKey, AMOUNT)
+        .accounts({
+          payer: payer.publicKey,
+          vaultState: vault.vaultState,
+          vault: vault.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payer])
+        .rpc({ commitment: "confirmed" });
+
+      expect((await provider.connection.getBalance(vault.vault)) - vaultBefore).to.equal(AMOUNT.toNumber());
+      const state = await program.account.vaultState.fetch(vault.vaultState);
+      expect(state.lifetimeDeposited.toString()).to.equal(AMOUNT.toString());
+
+      // The event names both the payer and the beneficiary
+      const txInfo = await provider.connection.getTransaction(tx, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const events = [...parser.parseLogs(txInfo.meta.logMessages)].filter(event => event.name === "vaultDepositedFor");
+      expect(events).to.have.lengthOf(1);
+      expect(events[0].data.payer.toString()).to.equal(payer.publicKey.toString());
+      expect(events[0].data.owner.toString()).to.equal(owner.publicKey.toString());
+      expect(events[0].data.amount.toString()).to.equal(AMOUNT.toString());
+
+      // The payer has no claim on the vault
+      try {
+        await withdraw(payer);
+        expect.fail("Payer withdrew from the beneficiary's vault");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+
+      const ownerBefore = await provider.connection.getBalance(owner.publicKey);
+      await withdraw(owner);
+      expect((await provider.connection.getBalance(owner.publicKey)) - ownerBefore).to.be.approximately(AMOUNT.toNumber(), 10_000);
+    });
+  });
+
+  describe("Withdrawal Request Ids", () => {
+    let vault: Vault;
+
+    const AMOUNT = new anchor.BN(LAMPORTS_PER_SOL / 10);
+
+    const withdraw = (requestId: anchor.BN | null) =>
+      program.methods
+        .withdrawSol(AMOUNT, requestId)
+        .accounts({
+          owner: vault.owner.publicKey,
+          vaultState: vault.vaultState,
+          vaultAuth: vault.vaultAuth,
+          vault: vault.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([vault.owner])
+        .rpc();
+
+    before(async () => {
+      vault = await createVault(await fundedKeypair(3));
+      await fundVault(vault, LAMPORTS_PER_SOL);
+    });
+
+    it("Rejects a retried withdrawal with the same request id", async () => {
+      const requestId = new anchor.BN(7);
+      const before = await provider.connection.getBalance(vault.vault);
+
+      await withdraw(requestId);
+
+      try {
+        await withdraw(requestId);
+        expect.fail("Withdrawal was replayed");
+      } catch (error) {
+        expect(error.toString()).to.include("DuplicateRequest");
+      }
+
+      // Only the first withdrawal paid out
+      expect(before - (await provider.connection.getBalance(vault.vault))).to.equal(AMOUNT.toNumber());
+    });
+
+    it("Still allows new ids and withdrawals without one", async () => {
+      const before = await provider.connection.getBalance(vault.vault);
+
+      await withdraw(new anchor.BN(8));
+      await withdraw(null);
+
+      expect(before - (await provider.connection.getBalance(vault.vault))).to.equal(AMOUNT.muln(2).toNumber());
+      const state = await program.account.vaultState.fetch(vault.vaultState);
+      expect(state.requestIdCount).to.equal(2);
+    });
+  });
+
+  describe("Delegated Withdrawals", () => {
+    let delegate: Keypair;
+    let vault: Vault;
+
+    const ALLOWANCE = new anchor.BN(LAMPORTS_PER_SOL / 2);
+
+    const withdrawDelegated = (signer: Keypair, amount: anchor.BN) =>
+      program.methods
+        .withdrawSolDelegated(amount)
+        .accounts({
+          delegate: signer.publicKey,
+          vaultState: vault.vaultState,
+          vault: vault.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      const owner = await fundedKeypair(3);
+      delegate = await fundedKeypair(3);
+      vault = await createVault(owner);
+      await fundVault(vault, LAMPORTS_PER_SOL);
+
+      await program.methods
+        .approveDelegate(delegate.publicKey, ALLOWANCE)
+        .accounts({ owner: owner.publicKey, vaultState: vault.vaultState })
+        .signers([owner])
+        .rpc();
+    });
+
+    it("Lets the delegate withdraw within the allowance", async () => {
+      const before = await provider.connection.getBalance(vault.vault);
+      const amount = ALLOWANCE.muln(3).divn(5);
+
+      await withdrawDelegated(delegate, amount);
+
+      expect(before - (await provider.connection.getBalance(vault.vault))).to.equal(amount.toNumber());
+      const state = await program.account.vaultState.fetch(vault.vaultState);
+      expect(state.delegateAllowance.toString()).to.equal(ALLOWANCE.sub(amount).toString());
+      expect(state.lifetimeWithdrawn.toString()).to.equal(amount.toString());
+    });
+
+    it("Rejects withdrawals beyond the allowance or by anyone else", async () => {
+      const remaining = (await program.account.vaultState.fetch(vault.vaultState)).delegateAllowance;
+
+      try {
+        await withdrawDelegated(delegate, remaining.addn(1));
+        expect.fail("Delegate withdrew past the allowance");
+      } catch (error) {
+        expect(error.toString()).to.include("AllowanceExceeded");
+      }
+
+      const stranger = await fundedKeypair(1);
+      try {
+        await withdrawDelegated(stranger, new anchor.BN(1));
+        expect.fail("A non-delegate withdrew from the vault");
+      } catch (error) {
+        expect(error.toString()).to.include("NotDelegate");
+      }
+
+      // The rest of the allowance is still spendable, and then it's used up
+      await withdrawDelegated(delegate, remaining);
+      const state = await program.account.vaultState.fetch(vault.vaultState);
+      expect(state.delegateAllowance.toString()).to.equal("0");
+    });
+  });
+
+  // Growing an old-layout vault is covered by the program's unit tests; a
+  // fresh vault is already on the current layout.
+  describe("Migration", () => {
+    let owner: Keypair;
+    let stranger: Keypair;
+    let vault: Vault;
+
+    const VAULT_LAYOUT_VERSION = 2;
+
+    const migrate = (signer: Keypair) =>
+      program.methods
+        .migrateVault()
+        .accounts({ owner: signer.publicKey, vaultState: vault.vaultState, systemProgram: SystemProgram.programId })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      owner = await fundedKeypair(1);
+      stranger = await fundedKeypair(1);
+      vault = await createVault(owner);
+    });
+
+    it("Creates vaults on the current layout", async () => {
+      const state = await program.account.vaultState.fetch(vault.vaultState);
+      expect(state.layoutVersion).to.equal(VAULT_LAYOUT_VERSION);
+    });
+
+    it("Refuses to migrate a vault twice", async () => {
+      const sizeBefore = (await provider.connection.getAccountInfo(vault.vaultState)).data.length;
+      try {
+        await migrate(owner);
+        expect.fail("Migrated a current vault");
+      } catch (error) {
+        expect(error.toString()).to.include("VaultAlreadyMigrated");
+      }
+      expect((await provider.connection.getAccountInfo(vault.vaultState)).data.length).to.equal(sizeBefore);
+    });
+
+    it("Only lets the owner migrate", async () => {
+      try {
+        await migrate(stranger);
+        expect.fail("Migrated someone else's vault");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+  });
+
+  describe("Close", () => {
+    let owner: Keypair;
+    let vault: Vault;
+    let tokenMint: PublicKey;
+    let vaultTokenAccount: PublicKey;
+    let ownerTokenAccount: PublicKey;
+
+    const RESIDUAL = 42;
+
+    const closeVault = (tokenAccounts: PublicKey[]) =>
+      program.methods
+        .closeVault()
+        .accounts({
+          owner: owner.publicKey,
+          vaultState: vault.vaultState,
+          vaultAuth: vault.vaultAuth,
+          vault: vault.vault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(tokenAccounts.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+        .signers([owner])
+        .rpc();
+
+    before(async () => {
+      owner = await fundedKeypair(3);
+      vault = await createVault(owner);
+
+      // Tokens sent straight to the vault authority's token account
+      tokenMint = await createMint(provider.connection, owner, owner.publicKey, null, 6);
+      vaultTokenAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, owner, tokenMint, vault.vaultAuth, true)).address;
+      ownerTokenAccount = await createAssociatedTokenAccount(provider.connection, owner, tokenMint, owner.publicKey);
+      await mintTo(provider.connection, owner, tokenMint, vaultTokenAccount, owner, RESIDUAL);
+
+      // Adopting the existing account records the mint for close_vault
+      await program.methods
+        .openTokenVault()
+        .accounts({
+          owner: owner.publicKey,
+          vaultState: vault.vaultState,
+          vaultAuth: vault.vaultAuth,
+          tokenMint,
+          vaultTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+      expect((await program.account.vaultState.fetch(vault.vaultState)).tokenMints.map((mint) => mint.toBase58()))
+        .to.deep.equal([tokenMint.toBase58()]);
+    });
+
+    it("Refuses to close without every token account the vault opened", async () => {
+      try {
+        await closeVault([]);
+        expect.fail("Closed a vault without checking its token account");
+      } catch (error) {
+        expect(error.toString()).to.include("VaultTokenAccountsMismatch");
+      }
+      expect(await provider.connection.getAccountInfo(vault.vaultState)).to.not.be.null;
+    });
+
+    it("Refuses to close while the token vault holds a balance", async () => {
+      try {
+        await closeVault([vaultTokenAccount]);
+        expect.fail("Closed a vault that still held tokens");
+      } catch (error) {
+        expect(error.toString()).to.include("VaultNotEmpty");
+      }
+      expect(await provider.connection.getAccountInfo(vault.vaultState)).to.not.be.null;
+    });
+
+    it("Closes the vault and its token account after a sweep", async () => {
+      await program.methods
+        .sweepTokenVault()
+        .accounts({
+          owner: owner.publicKey,
+          vaultState: vault.vaultState,
+          vaultAuth: vault.vaultAuth,
+          tokenMint,
+          vaultTokenAccount,
+          ownerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+      expect((await balance(ownerTokenAccount)).toNumber()).to.equal(RESIDUAL);
+
+      await closeVault([vaultTokenAccount]);
+      expect(await provider.connection.getAccountInfo(vault.vaultState)).to.be.null;
+      expect(await provider.connection.getAccountInfo(vaultTokenAccount)).to.be.null;
+      console.log("✅ Vault closed once its", RESIDUAL, "tokens were swept out");
+    });
+  });
+});